@@ -0,0 +1,315 @@
+use crate::intrinsics::Intrinsic;
+use crate::lexer::Position;
+use crate::number::Number;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Program {
+    pub imports: Vec<Import>,
+    pub functions: Vec<Function>,
+}
+
+/// `import "<path>";`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Import {
+    /// Path of the imported file, relative to the importing file
+    pub path: String,
+    pub position: Position,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Param>,
+
+    /// Type after `->`, `None` if the function does not return a value
+    pub ret: Option<ValueType>,
+    pub body: Block,
+    pub position: Position,
+
+    /// True for `test function`s, which are only compiled for
+    /// `purple_script test` and never included in the compiled script
+    pub test: bool,
+}
+
+impl Function {
+    pub fn is_main(&self) -> bool {
+        !self.test && self.name == "main"
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Param {
+    pub name: String,
+    pub ty: ValueType,
+    pub malleable: bool,
+    pub position: Position,
+}
+
+pub type Block = Vec<Stmt>;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub position: Position,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StmtKind {
+    /// An expression followed by a semicolon
+    Expr(Expr),
+
+    /// `let <name>[: <type>] = <value>;`
+    Let {
+        name: String,
+        ty: Option<ValueType>,
+        value: Expr,
+    },
+
+    /// `<name> = <value>;`, or `<name> <op>= <value>;` for compound
+    /// assignments. `<name>++` is `<name> += 1`.
+    Assign {
+        name: String,
+        op: Option<BinaryOp>,
+        value: Expr,
+    },
+
+    /// `if (<cond>) { .. } else { .. }`. An `else if` is an `else` block
+    /// holding a single `if` statement.
+    If {
+        cond: Expr,
+        then: Block,
+        otherwise: Option<Block>,
+    },
+
+    /// `while (<cond>) { .. }`
+    While {
+        cond: Expr,
+        body: Block,
+    },
+
+    /// `for (<init>; <cond>; <update>) { .. }`. Every clause is optional.
+    For {
+        init: Option<Box<Stmt>>,
+        cond: Option<Expr>,
+        update: Option<Box<Stmt>>,
+        body: Block,
+    },
+
+    /// `return;` or `return <expr>;`
+    Return(Option<Expr>),
+
+    /// `revert;` or `revert("<reason>");`
+    Revert(Option<String>),
+
+    Continue,
+    Break,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub position: Position,
+
+    /// Type of the expression, `Any` until the type checker ran
+    pub ty: ValueType,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, position: Position) -> Self {
+        Self {
+            kind,
+            position,
+            ty: ValueType::Any,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ExprKind {
+    Identifier(String),
+    Number(Number),
+    Bool(bool),
+    String(String),
+
+    /// `b"..."` or `x"..."`, of type `[u8; N]`
+    Bytes(Vec<u8>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+
+    /// `<expr> as <type>`
+    Cast(Box<Expr>, ValueType),
+
+    /// `<callee>(<args>)`
+    Call(Box<Expr>, Vec<Expr>),
+
+    /// `<expr>.<identifier>`
+    Member(Box<Expr>, String),
+
+    /// `this`
+    This,
+
+    /// Access of a member of a builtin object with its arguments, which
+    /// are empty for fields. Replaces calls and member accesses during type
+    /// checking.
+    Intrinsic(&'static Intrinsic, Vec<Expr>),
+
+    /// `<expr>[<index>]`
+    Index(Box<Expr>, Box<Expr>),
+
+    /// `<expr>[<start>..<end>]`. Both bounds are optional.
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    BitAnd,
+    BitXor,
+    BitOr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    /// Binding power of the operator. Higher binds tighter. All binary
+    /// operators are left associative.
+    ///
+    /// | Precedence | Operators                      |
+    /// |------------|--------------------------------|
+    /// | 10         | `*` `/` `%`                    |
+    /// | 9          | `+` `-`                        |
+    /// | 8          | `<<` `>>`                      |
+    /// | 7          | `&`                            |
+    /// | 6          | `^`                            |
+    /// | 5          | `\|`                           |
+    /// | 4          | `<` `<=` `>` `>=`              |
+    /// | 3          | `==` `!=`                      |
+    /// | 2          | `&&`                           |
+    /// | 1          | `\|\|`                         |
+    ///
+    /// Postfix operators (calls, member access and indexing), unary
+    /// operators and `as` casts bind tighter than any binary operator,
+    /// in that order.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 10,
+            BinaryOp::Add | BinaryOp::Sub => 9,
+            BinaryOp::Shl | BinaryOp::Shr => 8,
+            BinaryOp::BitAnd => 7,
+            BinaryOp::BitXor => 6,
+            BinaryOp::BitOr => 5,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => 4,
+            BinaryOp::Eq | BinaryOp::Ne => 3,
+            BinaryOp::And => 2,
+            BinaryOp::Or => 1,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum ValueType {
+    Any,
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    UBIG,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    IBIG,
+    F32,
+    F64,
+    Decimal,
+    Address,
+    Asset,
+
+    /// An output of the transaction, such as `this`
+    Output,
+
+    /// The input spent to create an output
+    Input,
+
+    /// A 32 byte Ed25519 public key
+    PublicKey,
+
+    /// A 32 byte hash
+    Hash,
+
+    /// `[<type>]`, an array of any length
+    Array(Box<ValueType>),
+
+    /// `[<type>; <len>]`, an array of a fixed length
+    FixedArray(Box<ValueType>, u32),
+}
+
+impl ValueType {
+    /// Returns the builtin type named `name`. Unlike the other types, their
+    /// names are not keywords.
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "Output" => Some(ValueType::Output),
+            "Input" => Some(ValueType::Input),
+            "PublicKey" => Some(ValueType::PublicKey),
+            "Hash" => Some(ValueType::Hash),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ValueType::Array(element) => return write!(f, "[{}]", element),
+            ValueType::FixedArray(element, len) => return write!(f, "[{}; {}]", element, len),
+            ValueType::Any => "any",
+            ValueType::Bool => "bool",
+            ValueType::U8 => "u8",
+            ValueType::U16 => "u16",
+            ValueType::U32 => "u32",
+            ValueType::U64 => "u64",
+            ValueType::U128 => "u128",
+            ValueType::UBIG => "ubig",
+            ValueType::I8 => "i8",
+            ValueType::I16 => "i16",
+            ValueType::I32 => "i32",
+            ValueType::I64 => "i64",
+            ValueType::I128 => "i128",
+            ValueType::IBIG => "ibig",
+            ValueType::F32 => "f32",
+            ValueType::F64 => "f64",
+            ValueType::Decimal => "decimal",
+            ValueType::Address => "address",
+            ValueType::Asset => "asset",
+            ValueType::Output => "Output",
+            ValueType::Input => "Input",
+            ValueType::PublicKey => "PublicKey",
+            ValueType::Hash => "Hash",
+        };
+        f.write_str(name)
+    }
+}
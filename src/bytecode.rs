@@ -0,0 +1,741 @@
+//! Binary layout of compiled scripts.
+//!
+//! See `docs/bytecode.md` for the byte-level specification. All multi-byte
+//! integers are encoded as little-endian.
+
+use crate::ast::ValueType;
+
+/// Magic bytes at the start of every compiled script.
+pub const MAGIC: [u8; 4] = *b"PSBC";
+
+/// Current version of the binary layout.
+pub const VERSION: u8 = 3;
+
+/// Number of array types an encoded type may be nested in, e.g. `[[u8]]` is
+/// nested in 2. Decoding deeper types fails rather than recursing further.
+pub const MAX_TYPE_DEPTH: usize = 32;
+
+/// Instructions of the script virtual machine.
+///
+/// Operands follow the opcode byte and are described next to each variant.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(u8)]
+pub enum OpCode {
+    /// Push a constant. Followed by a type tag and the encoded value.
+    PushConst = 0x01,
+
+    /// Push the value of a frame slot. Followed by the slot index as `u16`.
+    Load = 0x02,
+
+    /// Discard the value on top of the stack.
+    Pop = 0x03,
+
+    /// Return from the current function without a value.
+    Return = 0x04,
+
+    /// Return from the current function with the value on top of the stack.
+    ReturnValue = 0x05,
+
+    /// Duplicate the value on top of the stack.
+    Dup = 0x06,
+
+    // Binary operators. Pop the right hand side, then the left hand side,
+    // and push the result.
+    //
+    Add = 0x07,
+    Sub = 0x08,
+    Mul = 0x09,
+    Div = 0x0a,
+    Rem = 0x0b,
+    Shl = 0x0c,
+    Shr = 0x0d,
+    BitAnd = 0x0e,
+    BitXor = 0x0f,
+    BitOr = 0x10,
+
+    // Comparisons. Pop the right hand side, then the left hand side,
+    // and push `true` if the comparison holds, `false` otherwise.
+    //
+    Lt = 0x11,
+    Le = 0x12,
+    Gt = 0x13,
+    Ge = 0x14,
+    Eq = 0x15,
+
+    /// Negate the value on top of the stack.
+    Neg = 0x16,
+
+    /// Convert the value on top of the stack. Followed by the encoded target type.
+    Cast = 0x17,
+
+    /// Continue at an offset of the current function. Followed by the offset as `u32`.
+    Jump = 0x18,
+
+    /// Pop a `bool` and jump if it is `false`. Followed by the offset as `u32`.
+    JumpIfFalse = 0x19,
+
+    /// Pop a `bool` and jump if it is `true`. Followed by the offset as `u32`.
+    JumpIfTrue = 0x1a,
+
+    /// Inequality comparison, see `Eq`.
+    Ne = 0x1b,
+
+    /// Logical not of the `bool` on top of the stack.
+    Not = 0x1c,
+
+    /// Pop a value and store it in a frame slot. Followed by the slot
+    /// index (`u16`).
+    Store = 0x1d,
+
+    /// Abort the script. Followed by the code of the reason (`u16`), which
+    /// is `0` if none was given. See `Artifact::reasons`.
+    Revert = 0x1e,
+
+    /// Call a function. Followed by the function index (`u16`) and the
+    /// number of arguments (`u8`), which are popped into the first slots
+    /// of the frame of the callee.
+    Call = 0x1f,
+
+    /// Pop an index and an array and push the element at the index. Aborts
+    /// the script if the index is out of bounds.
+    Index = 0x20,
+
+    /// Pop the bounds and an array and push the elements between the bounds.
+    /// Followed by a `u8` whose bit 0 is set if the start is on the stack and
+    /// bit 1 if the end is. The start defaults to zero and the end to the
+    /// length of the array. Aborts the script if the start is greater than
+    /// the end or the end is greater than the length.
+    Slice = 0x21,
+
+    /// Pop an array and push its length as `u32`.
+    Len = 0x22,
+
+    /// Pop a count and an array, then push the first `count` elements of the
+    /// array followed by the remaining elements. Aborts the script if the
+    /// array has less than `count` elements.
+    TakeFront = 0x23,
+
+    /// Like `TakeFront`, but takes the last `count` elements.
+    TakeBack = 0x24,
+
+    // Intrinsics, see `crate::intrinsics`. They pop their arguments, the
+    // last argument first, and push their result.
+    //
+    /// Push the timestamp of the current block as `i64`.
+    BlockTimestamp = 0x40,
+
+    /// Push the number of outputs of the transaction as `u32`.
+    OutputsLen = 0x41,
+
+    /// Push the bytes of the input spending this output as `[u8]`.
+    InputBytes = 0x42,
+
+    /// Pop a signature, a public key, a message and a prefix, and abort the
+    /// script unless the signature is a valid Ed25519 signature of the
+    /// prefix followed by the message.
+    VerifyEd25519 = 0x43,
+
+    /// Pop a script hash, an address and an amount, append an output with
+    /// them to the transaction and push its index as `u32`.
+    PushOut = 0x44,
+
+    /// Pop an index and push the output of the transaction at the index.
+    /// Aborts the script if the index is out of bounds.
+    GetOutputAt = 0x45,
+
+    /// Push the output whose script is running.
+    This = 0x46,
+
+    /// Pop an output, append a copy of it to the transaction and push its
+    /// index as `u32`.
+    PushOutput = 0x47,
+
+    // Fields of outputs and inputs. Pop an output or an input and push the
+    // field.
+    //
+    OutputAmount = 0x48,
+    OutputColourHash = 0x49,
+    OutputColouredAddress = 0x4a,
+    OutputScriptHash = 0x4b,
+    OutputHash = 0x4c,
+    OutputInput = 0x4d,
+    InputPublicKey = 0x4e,
+    InputOutputHash = 0x4f,
+
+    // Intrinsics of test functions, which mock the transaction. They are
+    // only valid in scripts compiled for `purple_script test`.
+    //
+    /// Pop a timestamp and make it the timestamp of the current block.
+    SetBlockTimestamp = 0x60,
+
+    /// Pop bytes and make them the bytes of the input spending this output.
+    SetInputBytes = 0x61,
+
+    /// Pop an output and make it the output whose script is running.
+    SetThis = 0x62,
+
+    /// Pop an output and append it to the outputs of the transaction.
+    AddOutput = 0x63,
+
+    /// Pop a script hash, an address, an asset and an amount, and push a new
+    /// output with them.
+    MockOutput = 0x64,
+
+    /// Like `MockOutput`, but pops the public key of the input of the output
+    /// first.
+    MockOutputWithKey = 0x65,
+
+    /// Pop a secret key and push its public key.
+    DerivePublicKey = 0x66,
+
+    /// Pop a message and a secret key and push the Ed25519 signature of the
+    /// message by the key.
+    Sign = 0x67,
+
+    /// Expect main to revert for the test to pass. Followed by the code of
+    /// the reason main has to revert with (`u16`), like `Revert`.
+    ExpectRevert = 0x68,
+
+    /// Push the number of outputs appended by main as `u32`.
+    PushedLen = 0x69,
+
+    /// Pop an index and push the output appended by main at the index.
+    PushedAt = 0x6a,
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    /// Returns the instruction of an opcode byte, or the byte if it is not
+    /// an opcode.
+    fn try_from(byte: u8) -> Result<Self, u8> {
+        use OpCode::*;
+
+        const OPCODES: [OpCode; 63] = [
+            PushConst,
+            Load,
+            Pop,
+            Return,
+            ReturnValue,
+            Dup,
+            Add,
+            Sub,
+            Mul,
+            Div,
+            Rem,
+            Shl,
+            Shr,
+            BitAnd,
+            BitXor,
+            BitOr,
+            Lt,
+            Le,
+            Gt,
+            Ge,
+            Eq,
+            Neg,
+            Cast,
+            Jump,
+            JumpIfFalse,
+            JumpIfTrue,
+            Ne,
+            Not,
+            Store,
+            Revert,
+            Call,
+            Index,
+            Slice,
+            Len,
+            TakeFront,
+            TakeBack,
+            BlockTimestamp,
+            OutputsLen,
+            InputBytes,
+            VerifyEd25519,
+            PushOut,
+            GetOutputAt,
+            This,
+            PushOutput,
+            OutputAmount,
+            OutputColourHash,
+            OutputColouredAddress,
+            OutputScriptHash,
+            OutputHash,
+            OutputInput,
+            InputPublicKey,
+            InputOutputHash,
+            SetBlockTimestamp,
+            SetInputBytes,
+            SetThis,
+            AddOutput,
+            MockOutput,
+            MockOutputWithKey,
+            DerivePublicKey,
+            Sign,
+            ExpectRevert,
+            PushedLen,
+            PushedAt,
+        ];
+
+        OPCODES.into_iter().find(|op| *op as u8 == byte).ok_or(byte)
+    }
+}
+
+/// Returns the tag of a value type, which is the first byte of its encoding.
+pub fn type_tag(ty: &ValueType) -> u8 {
+    match ty {
+        ValueType::Any => 0x00,
+        ValueType::U8 => 0x01,
+        ValueType::U16 => 0x02,
+        ValueType::U32 => 0x03,
+        ValueType::U64 => 0x04,
+        ValueType::U128 => 0x05,
+        ValueType::UBIG => 0x06,
+        ValueType::I8 => 0x07,
+        ValueType::I16 => 0x08,
+        ValueType::I32 => 0x09,
+        ValueType::I64 => 0x0a,
+        ValueType::I128 => 0x0b,
+        ValueType::IBIG => 0x0c,
+        ValueType::F32 => 0x0d,
+        ValueType::F64 => 0x0e,
+        ValueType::Decimal => 0x0f,
+        ValueType::Address => 0x10,
+        ValueType::Asset => 0x11,
+        ValueType::Array(_) => 0x12,
+        ValueType::FixedArray(_, _) => 0x13,
+        ValueType::Bool => 0x14,
+        ValueType::Output => 0x15,
+        ValueType::Input => 0x16,
+        ValueType::PublicKey => 0x17,
+        ValueType::Hash => 0x18,
+    }
+}
+
+/// Encodes a value type. Array types are followed by the encoding of their
+/// element type, and fixed arrays additionally by their length as `u32`.
+pub fn encode_type(ty: &ValueType, out: &mut Vec<u8>) {
+    out.push(type_tag(ty));
+    match ty {
+        ValueType::Array(element) => encode_type(element, out),
+        ValueType::FixedArray(element, len) => {
+            encode_type(element, out);
+            out.extend_from_slice(&len.to_le_bytes());
+        }
+        _ => {}
+    }
+}
+
+/// Returns the value type of a tag, unless the tag is an array type, whose
+/// encoding continues after the tag.
+fn tag_type(tag: u8) -> Option<ValueType> {
+    let ty = match tag {
+        0x00 => ValueType::Any,
+        0x01 => ValueType::U8,
+        0x02 => ValueType::U16,
+        0x03 => ValueType::U32,
+        0x04 => ValueType::U64,
+        0x05 => ValueType::U128,
+        0x06 => ValueType::UBIG,
+        0x07 => ValueType::I8,
+        0x08 => ValueType::I16,
+        0x09 => ValueType::I32,
+        0x0a => ValueType::I64,
+        0x0b => ValueType::I128,
+        0x0c => ValueType::IBIG,
+        0x0d => ValueType::F32,
+        0x0e => ValueType::F64,
+        0x0f => ValueType::Decimal,
+        0x10 => ValueType::Address,
+        0x11 => ValueType::Asset,
+        0x14 => ValueType::Bool,
+        0x15 => ValueType::Output,
+        0x16 => ValueType::Input,
+        0x17 => ValueType::PublicKey,
+        0x18 => ValueType::Hash,
+        _ => return None,
+    };
+    Some(ty)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Artifact {
+    /// Malleable arguments bitmap of the main function
+    pub bitmap: Vec<u8>,
+
+    /// Body of the main function
+    pub main: Vec<u8>,
+
+    /// Bodies of all other functions, indexed by `FuncIdx - 1`
+    pub funcs: Vec<Vec<u8>>,
+
+    /// Revert reasons, indexed by their code minus one. Empty if the
+    /// reasons were stripped from the script.
+    pub reasons: Vec<String>,
+}
+
+impl Artifact {
+    /// Encodes the artifact. Fails if a section does not fit the width of
+    /// its length or count.
+    pub fn encode(&self) -> Result<Vec<u8>, EncodeErr> {
+        let mut out = vec![];
+        out.extend_from_slice(&MAGIC);
+        out.push(VERSION);
+
+        let bitmap_len = u16::try_from(self.bitmap.len()).map_err(|_| EncodeErr::BitmapTooLong)?;
+        out.extend_from_slice(&bitmap_len.to_le_bytes());
+        out.extend_from_slice(&self.bitmap);
+
+        let main_len = u32::try_from(self.main.len()).map_err(|_| EncodeErr::FunctionTooLong(0))?;
+        out.extend_from_slice(&main_len.to_le_bytes());
+        out.extend_from_slice(&self.main);
+
+        let funcs_count =
+            u16::try_from(self.funcs.len()).map_err(|_| EncodeErr::TooManyFunctions)?;
+        out.extend_from_slice(&funcs_count.to_le_bytes());
+        for (i, func) in self.funcs.iter().enumerate() {
+            let func_len =
+                u32::try_from(func.len()).map_err(|_| EncodeErr::FunctionTooLong(i + 1))?;
+            out.extend_from_slice(&func_len.to_le_bytes());
+            out.extend_from_slice(func);
+        }
+
+        let reasons_count =
+            u16::try_from(self.reasons.len()).map_err(|_| EncodeErr::TooManyReasons)?;
+        out.extend_from_slice(&reasons_count.to_le_bytes());
+        for (i, reason) in self.reasons.iter().enumerate() {
+            let reason_len =
+                u16::try_from(reason.len()).map_err(|_| EncodeErr::ReasonTooLong(i + 1))?;
+            out.extend_from_slice(&reason_len.to_le_bytes());
+            out.extend_from_slice(reason.as_bytes());
+        }
+
+        Ok(out)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeErr> {
+        let mut reader = Reader::new(bytes);
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(DecodeErr::InvalidMagic);
+        }
+
+        let version = reader.take_u8()?;
+        if version != VERSION {
+            return Err(DecodeErr::UnsupportedVersion(version));
+        }
+
+        let bitmap_len = reader.take_u16()? as usize;
+        let bitmap = reader.take(bitmap_len)?.to_vec();
+
+        let main_len = reader.take_u32()? as usize;
+        let main = reader.take(main_len)?.to_vec();
+
+        let funcs_count = reader.take_u16()? as usize;
+        let mut funcs = Vec::with_capacity(funcs_count);
+        for _ in 0..funcs_count {
+            let func_len = reader.take_u32()? as usize;
+            funcs.push(reader.take(func_len)?.to_vec());
+        }
+
+        let reasons_count = reader.take_u16()? as usize;
+        let mut reasons = Vec::with_capacity(reasons_count);
+        for _ in 0..reasons_count {
+            let offset = reader.offset;
+            let reason_len = reader.take_u16()? as usize;
+            let reason = String::from_utf8(reader.take(reason_len)?.to_vec())
+                .map_err(|_| DecodeErr::InvalidReason(offset))?;
+            reasons.push(reason);
+        }
+
+        if !reader.is_empty() {
+            return Err(DecodeErr::TrailingBytes(reader.offset));
+        }
+
+        Ok(Self {
+            bitmap,
+            main,
+            funcs,
+            reasons,
+        })
+    }
+
+    /// Returns the revert reason with the given code, or `None` for code `0`
+    /// and for codes whose reason is not in the script.
+    pub fn reason(&self, code: u16) -> Option<&str> {
+        let idx = (code as usize).checked_sub(1)?;
+        self.reasons.get(idx).map(String::as_str)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum EncodeErr {
+    /// The bitmap is longer than `u16::MAX` bytes
+    BitmapTooLong,
+    /// Index of a function longer than `u32::MAX` bytes, `0` for main
+    FunctionTooLong(usize),
+    /// More than `u16::MAX` functions besides main
+    TooManyFunctions,
+    /// More than `u16::MAX` revert reasons
+    TooManyReasons,
+    /// Code of a revert reason longer than `u16::MAX` bytes
+    ReasonTooLong(usize),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DecodeErr {
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    UnexpectedEnd(usize),
+    TrailingBytes(usize),
+    InvalidTypeTag(u8, usize),
+
+    /// A revert reason which is not UTF-8
+    InvalidReason(usize),
+
+    /// An array type nested in more than `MAX_TYPE_DEPTH` array types
+    TypeTooDeep(usize),
+}
+
+/// Reads encoded values from the start of `bytes`, keeping track of the
+/// offset of the next byte.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pub(crate) offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeErr> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(DecodeErr::UnexpectedEnd(self.offset))?;
+
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    /// Returns the number of bytes left.
+    pub(crate) fn remaining(&self) -> usize {
+        self.bytes.len() - self.offset
+    }
+
+    pub(crate) fn take_u8(&mut self) -> Result<u8, DecodeErr> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn take_u16(&mut self) -> Result<u16, DecodeErr> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn take_u32(&mut self) -> Result<u32, DecodeErr> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Takes a value type encoded by `encode_type`.
+    pub(crate) fn take_type(&mut self) -> Result<ValueType, DecodeErr> {
+        self.take_nested_type(0)
+    }
+
+    /// Takes the element type of `depth` enclosing array types.
+    fn take_nested_type(&mut self, depth: usize) -> Result<ValueType, DecodeErr> {
+        let offset = self.offset;
+        let tag = self.take_u8()?;
+        if matches!(tag, 0x12 | 0x13) && depth == MAX_TYPE_DEPTH {
+            return Err(DecodeErr::TypeTooDeep(offset));
+        }
+
+        let ty = match tag {
+            0x12 => ValueType::Array(Box::new(self.take_nested_type(depth + 1)?)),
+            0x13 => {
+                let element = self.take_nested_type(depth + 1)?;
+                ValueType::FixedArray(Box::new(element), self.take_u32()?)
+            }
+            _ => tag_type(tag).ok_or(DecodeErr::InvalidTypeTag(tag, offset))?,
+        };
+        Ok(ty)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset == self.bytes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode_type, Artifact, DecodeErr, EncodeErr, OpCode, Reader, MAGIC, MAX_TYPE_DEPTH, VERSION,
+    };
+    use crate::ast::ValueType;
+
+    #[test]
+    fn test_encode_empty() {
+        let encoded = Artifact::default().encode().unwrap();
+        let mut expected = MAGIC.to_vec();
+        expected.push(VERSION);
+        expected.extend_from_slice(&[0x00, 0x00]); // bitmap len
+        expected.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // main len
+        expected.extend_from_slice(&[0x00, 0x00]); // funcs count
+        expected.extend_from_slice(&[0x00, 0x00]); // reasons count
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn test_encode_layout() {
+        let artifact = Artifact {
+            bitmap: vec![0b0000_0101],
+            main: vec![0xaa, 0xbb],
+            funcs: vec![vec![0xcc], vec![]],
+            reasons: vec!["no".to_owned()],
+        };
+        let mut expected = MAGIC.to_vec();
+        expected.push(VERSION);
+        expected.extend_from_slice(&[0x01, 0x00, 0b0000_0101]);
+        expected.extend_from_slice(&[0x02, 0x00, 0x00, 0x00, 0xaa, 0xbb]);
+        expected.extend_from_slice(&[0x02, 0x00]);
+        expected.extend_from_slice(&[0x01, 0x00, 0x00, 0x00, 0xcc]);
+        expected.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+        expected.extend_from_slice(&[0x01, 0x00, 0x02, 0x00, b'n', b'o']);
+        assert_eq!(artifact.encode(), Ok(expected));
+    }
+
+    #[test]
+    fn test_decode_roundtrip() {
+        let artifact = Artifact {
+            bitmap: vec![0x01, 0x80],
+            main: vec![0x01, 0x02, 0x03],
+            funcs: vec![vec![0x04], vec![0x05, 0x06]],
+            reasons: vec!["deadline passed".to_owned(), "".to_owned()],
+        };
+        assert_eq!(
+            Artifact::decode(&artifact.encode().unwrap()),
+            Ok(artifact.clone())
+        );
+        assert_eq!(artifact.reason(0), None);
+        assert_eq!(artifact.reason(1), Some("deadline passed"));
+        assert_eq!(artifact.reason(3), None);
+    }
+
+    #[test]
+    fn test_encode_too_long() {
+        let artifact = Artifact {
+            reasons: vec!["no".to_owned(), "x".repeat(70000)],
+            ..Artifact::default()
+        };
+        assert_eq!(artifact.encode(), Err(EncodeErr::ReasonTooLong(2)));
+
+        let artifact = Artifact {
+            funcs: vec![vec![]; 65536],
+            ..Artifact::default()
+        };
+        assert_eq!(artifact.encode(), Err(EncodeErr::TooManyFunctions));
+    }
+
+    #[test]
+    fn test_decode_invalid_magic() {
+        let mut encoded = Artifact::default().encode().unwrap();
+        encoded[0] = b'X';
+        assert_eq!(Artifact::decode(&encoded), Err(DecodeErr::InvalidMagic));
+    }
+
+    #[test]
+    fn test_decode_unsupported_version() {
+        let mut encoded = Artifact::default().encode().unwrap();
+        encoded[4] = VERSION + 1;
+        assert_eq!(
+            Artifact::decode(&encoded),
+            Err(DecodeErr::UnsupportedVersion(VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        let encoded = Artifact {
+            main: vec![0x01, 0x02],
+            ..Artifact::default()
+        }
+        .encode()
+        .unwrap();
+        assert_eq!(
+            Artifact::decode(&encoded[..encoded.len() - 5]),
+            Err(DecodeErr::UnexpectedEnd(11))
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid_reason() {
+        let mut encoded = Artifact {
+            reasons: vec!["no".to_owned()],
+            ..Artifact::default()
+        }
+        .encode()
+        .unwrap();
+        let len = encoded.len();
+        encoded[len - 1] = 0xff;
+        assert_eq!(
+            Artifact::decode(&encoded),
+            Err(DecodeErr::InvalidReason(15))
+        );
+    }
+
+    #[test]
+    fn test_decode_trailing_bytes() {
+        let mut encoded = Artifact::default().encode().unwrap();
+        encoded.push(0x00);
+        assert_eq!(
+            Artifact::decode(&encoded),
+            Err(DecodeErr::TrailingBytes(15))
+        );
+    }
+
+    #[test]
+    fn test_opcode_from_byte() {
+        assert_eq!(OpCode::try_from(0x01), Ok(OpCode::PushConst));
+        assert_eq!(OpCode::try_from(0x24), Ok(OpCode::TakeBack));
+        assert_eq!(OpCode::try_from(0x4f), Ok(OpCode::InputOutputHash));
+        assert_eq!(OpCode::try_from(0x6a), Ok(OpCode::PushedAt));
+        assert_eq!(OpCode::try_from(0x25), Err(0x25));
+        assert_eq!(OpCode::try_from(0x00), Err(0x00));
+    }
+
+    #[test]
+    fn test_type_roundtrip() {
+        let ty = ValueType::Array(Box::new(ValueType::FixedArray(
+            Box::new(ValueType::Hash),
+            3,
+        )));
+        let mut encoded = vec![];
+        encode_type(&ty, &mut encoded);
+        let mut reader = Reader::new(&encoded);
+        assert_eq!(reader.take_type(), Ok(ty));
+        assert!(reader.is_empty());
+
+        assert_eq!(
+            Reader::new(&[0x12, 0x19]).take_type(),
+            Err(DecodeErr::InvalidTypeTag(0x19, 1))
+        );
+        assert_eq!(
+            Reader::new(&[0x13, 0x01, 0x02]).take_type(),
+            Err(DecodeErr::UnexpectedEnd(2))
+        );
+
+        let mut nested = vec![0x12; MAX_TYPE_DEPTH];
+        nested.push(0x01);
+        assert!(Reader::new(&nested).take_type().is_ok());
+        nested.insert(0, 0x12);
+        assert_eq!(
+            Reader::new(&nested).take_type(),
+            Err(DecodeErr::TypeTooDeep(MAX_TYPE_DEPTH))
+        );
+        assert_eq!(
+            Reader::new(&[0x12; 100_000]).take_type(),
+            Err(DecodeErr::TypeTooDeep(MAX_TYPE_DEPTH))
+        );
+    }
+}
@@ -1,13 +1,31 @@
-use crate::lexer::{Keyword, Position, Symbol, Token, TokenKind};
+use crate::ast::{BinaryOp, Expr, ExprKind, Function, Param, Stmt, StmtKind, UnaryOp, ValueType};
+use crate::bytecode::{encode_type, type_tag, Artifact, OpCode};
+use crate::intrinsics::Intrinsic;
+use crate::lexer::{LexError, Position};
+use crate::number::Number;
+use crate::typeck;
+use num_bigint::{BigInt, Sign};
+use std::collections::HashMap;
 
 type IdentifierAndArg = (String, ValueType);
 type FuncIdx = usize; // 0 for main
 type Frame = (FuncIdx, Vec<IdentifierAndArg>);
 
-pub struct Compiler {
-    /// Compiler state
-    state: CompilerState,
+/// Number of decimal places of `decimal` values.
+pub(crate) const DECIMAL_SCALE: u32 = 18;
+
+/// Bits of the operand of `Slice` telling which bounds are on the stack.
+const SLICE_START: u8 = 0b01;
+const SLICE_END: u8 = 0b10;
+
+/// The arity operand of `Call` is a `u8`.
+const MAX_PARAMS: usize = u8::MAX as usize;
 
+/// Function indices and frame slots are `u16` operands.
+const MAX_FUNCTIONS: usize = u16::MAX as usize;
+const MAX_VARIABLES: usize = u16::MAX as usize;
+
+pub struct Compiler {
     /// Buffer for the main function
     out_main: Vec<u8>,
 
@@ -18,399 +36,1931 @@ pub struct Compiler {
     /// Buffer for other functions
     out_funcs: Vec<Vec<u8>>,
 
-    /// Buffer for the bitmap
+    /// Malleable arguments bitmap of the main function
     out_bitmap: Vec<u8>,
 
-    /// Number of bools written in the bitmap
-    out_malleable_args_count: usize,
+    /// Position of the main function, if we found it
+    main_position: Option<Position>,
+
+    /// Parameter types of the main function, which tests call
+    main_params: Vec<ValueType>,
+
+    /// Declared test functions, by name and position
+    tests: Vec<(String, Position)>,
+
+    /// Buffer for test functions, which are only compiled by `compile_tests`
+    out_tests: Vec<Vec<u8>>,
+
+    /// Index of the test function being compiled, if any
+    test_idx: Option<usize>,
+
+    /// Loops enclosing the statement being compiled, innermost last
+    loop_stack: Vec<Loop>,
+
+    /// Declared functions other than main
+    functions: HashMap<String, Signature>,
+
+    /// What the function being compiled may return
+    ret: Returns,
+
+    /// Distinct revert reasons, indexed by their code minus one
+    reasons: Vec<String>,
+
+    /// Revert reasons only used by test functions, which are kept out of
+    /// the compiled script
+    test_reasons: Vec<String>,
+
+    /// Placeholders of the codes of reverts in test functions, as the index
+    /// of the test, the offset of the placeholder and the index of the reason
+    /// in `test_reasons`. They are filled in by `compile_tests`.
+    test_reverts: Vec<(usize, usize, usize)>,
+
+    /// Leave the revert reasons out of the compiled script
+    strip_reasons: bool,
+}
 
-    /// If we found the main function or not
-    found_main: bool,
+pub(crate) struct Signature {
+    pub(crate) idx: FuncIdx,
+    pub(crate) params: Vec<ValueType>,
+    pub(crate) ret: Option<ValueType>,
+    pub(crate) position: Position,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Returns {
+    /// Helpers without a return type
+    Nothing,
+
+    /// Helpers with a return type
+    Value,
+
+    /// Main may return with or without a value
+    Any,
+}
+
+/// A test function of a script compiled by `Compiler::compile_tests`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TestFunction {
+    pub name: String,
+    pub position: Position,
+
+    /// Index of the test function in the function table
+    pub func: usize,
+}
+
+/// Jumps out of a loop which are patched once the loop is compiled.
+#[derive(Default)]
+struct Loop {
+    /// Placeholders of `continue` jumps
+    continues: Vec<usize>,
+
+    /// Placeholders of `break` jumps
+    breaks: Vec<usize>,
 }
 
 impl Compiler {
     pub fn new() -> Self {
         Self {
-            state: CompilerState::Any,
             out_main: vec![],
             out_funcs: vec![],
             out_bitmap: vec![],
-            frame_stack: vec![(0, vec![])],
-            out_malleable_args_count: 0,
-            found_main: false,
+            frame_stack: vec![],
+            main_position: None,
+            main_params: vec![],
+            tests: vec![],
+            out_tests: vec![],
+            test_idx: None,
+            loop_stack: vec![],
+            functions: HashMap::new(),
+            ret: Returns::Any,
+            reasons: vec![],
+            test_reasons: vec![],
+            test_reverts: vec![],
+            strip_reasons: false,
         }
     }
 
-    pub fn push_token(&mut self, token: Token) -> Result<(), CompilerErr> {
-        match (&self.state, token.kind) {
-            (&CompilerState::Any, TokenKind::Keyword(Keyword::Function)) => {
-                self.state = CompilerState::ExpectingFuncIdentifier;
+    /// Leaves the revert reasons out of the script returned by `compile`,
+    /// so that reverts only carry their code. `revert_reasons` still maps
+    /// the codes to their reasons.
+    pub fn set_strip_reasons(&mut self, strip: bool) {
+        self.strip_reasons = strip;
+    }
+
+    /// Returns the revert reasons of the compiled functions. The reason at
+    /// index `i` has the code `i + 1`.
+    pub fn revert_reasons(&self) -> &[String] {
+        &self.reasons
+    }
+
+    /// Declares a function, so that it can be called from functions
+    /// compiled before it. Functions are indexed in declaration order.
+    pub fn declare_function(&mut self, function: &Function) -> Result<(), CompilerErr> {
+        if let Some(param) = function.params.get(MAX_PARAMS) {
+            return Err(CompilerErr::TooManyParameters(param.position.clone()));
+        }
+
+        if function.test {
+            if !function.params.is_empty() || function.ret.is_some() {
+                return Err(CompilerErr::InvalidTestSignature(function.position.clone()));
             }
 
-            // We expected a function identifier
-            (&CompilerState::Any, _) => {
-                return Err(CompilerErr::ExpectedFunctionDefinition(
-                    token.position.clone(),
+            if let Some((_, first)) = self.tests.iter().find(|(name, _)| *name == function.name) {
+                return Err(CompilerErr::DuplicateFunctionDeclaration(
+                    function.position.clone(),
+                    first.clone(),
                 ));
             }
 
-            // Main function identifier
-            (&CompilerState::ExpectingFuncIdentifier, TokenKind::Identifier(identifier))
-                if identifier.as_str() == "main" =>
-            {
-                if self.found_main {
-                    return Err(CompilerErr::DuplicateMainDeclaration(
-                        token.position.clone(),
-                    ));
-                }
+            self.check_functions_len(&function.position)?;
 
-                self.found_main = true;
-                self.state = CompilerState::ExpectingMainFuncLeftParanthesis;
-            }
+            self.tests
+                .push((function.name.clone(), function.position.clone()));
+            self.out_tests.push(vec![]);
+            return Ok(());
+        }
 
-            // Other function identifier
-            (&CompilerState::ExpectingFuncIdentifier, TokenKind::Identifier(identifier))
-                if identifier.as_str() != "main" =>
-            {
-                self.state = CompilerState::ExpectingFuncLeftParanthesis;
+        if function.is_main() {
+            if let Some(first) = &self.main_position {
+                return Err(CompilerErr::DuplicateMainDeclaration(
+                    function.position.clone(),
+                    first.clone(),
+                ));
             }
 
-            (&CompilerState::ExpectingFuncIdentifier, _) => {
-                return Err(CompilerErr::ExpectedIdentifier(token.position.clone()));
-            }
+            self.main_position = Some(function.position.clone());
+            self.main_params = function
+                .params
+                .iter()
+                .map(|param| param.ty.clone())
+                .collect();
+            return Ok(());
+        }
 
-            (
-                &CompilerState::ExpectingMainFuncLeftParanthesis,
-                TokenKind::Symbol(Symbol::ParenthesisLeft),
-            ) => {
-                self.state = CompilerState::ExpectingMainFuncMalleableOrIdentifier;
-            }
+        if let Some(first) = self.functions.get(&function.name) {
+            return Err(CompilerErr::DuplicateFunctionDeclaration(
+                function.position.clone(),
+                first.position.clone(),
+            ));
+        }
+
+        self.check_functions_len(&function.position)?;
+        let signature = Signature {
+            idx: self.out_funcs.len() + 1,
+            params: function
+                .params
+                .iter()
+                .map(|param| param.ty.clone())
+                .collect(),
+            ret: function.ret.clone(),
+            position: function.position.clone(),
+        };
+        self.functions.insert(function.name.clone(), signature);
+        self.out_funcs.push(vec![]);
+
+        Ok(())
+    }
+
+    /// Type checks and compiles a function, declaring it first if
+    /// `declare_function` was not called for it. Every function called by
+    /// it has to be declared beforehand.
+    pub fn push_function(&mut self, function: &Function) -> Result<(), CompilerErr> {
+        if function.test {
+            return self.push_test(function);
+        }
 
-            (
-                &CompilerState::ExpectingFuncLeftParanthesis,
-                TokenKind::Symbol(Symbol::ParenthesisLeft),
-            ) => {
-                self.state = CompilerState::ExpectingFuncArgIdentifier;
+        let is_main = function.is_main();
+        let declared = if is_main {
+            self.main_position.as_ref()
+        } else {
+            self.functions.get(&function.name).map(|sig| &sig.position)
+        };
+
+        if declared != Some(&function.position) {
+            self.declare_function(function)?;
+        }
+
+        // The checker annotates the function with types and casts
+        let mut function = function.clone();
+        typeck::check_function(&mut function, &self.functions)?;
+
+        let func_idx = if is_main {
+            0
+        } else {
+            self.functions[&function.name].idx
+        };
+        let mut frame = (func_idx, vec![]);
+
+        for param in function.params.iter() {
+            if param.malleable && !is_main {
+                return Err(CompilerErr::MalleableArgOutsideMain(param.position.clone()));
             }
 
-            (&CompilerState::ExpectingFuncLeftParanthesis, _) => {
-                return Err(CompilerErr::ExpectedLeftParanthesis(token.position.clone()));
+            frame.1.push((param.name.clone(), param.ty.clone()));
+        }
+
+        if is_main {
+            self.out_bitmap = malleable_bitmap(&function.params);
+        }
+
+        self.frame_stack.push(frame);
+        self.ret = if is_main {
+            Returns::Any
+        } else {
+            match function.ret {
+                Some(_) => Returns::Value,
+                None => Returns::Nothing,
             }
+        };
+        let mut out = vec![];
+        let result = self.compile_block(&function.body, &mut out);
+        self.frame_stack.pop();
+        result?;
+
+        // Implicit return at the end of every function
+        out.push(OpCode::Return as u8);
+        check_function_len(&out, &function.position)?;
+
+        if is_main {
+            self.out_main = out;
+        } else {
+            self.out_funcs[func_idx - 1] = out;
+        }
+
+        Ok(())
+    }
+
+    /// Type checks and compiles a test function. Tests can call main, whose
+    /// return value is discarded.
+    fn push_test(&mut self, function: &Function) -> Result<(), CompilerErr> {
+        let declared = self
+            .tests
+            .iter()
+            .find(|(name, _)| *name == function.name)
+            .map(|(_, position)| position);
+
+        if declared != Some(&function.position) {
+            self.declare_function(function)?;
+        }
 
-            // We hit a malleable arg keyword
-            (
-                &CompilerState::ExpectingMainFuncMalleableOrIdentifier,
-                TokenKind::Keyword(Keyword::Malleable),
-            ) => {
-                // Increment bitmap count
-                self.out_malleable_args_count += 1;
-                let desired_bitmap_len = (self.out_malleable_args_count - 1) / 8 + 1;
+        let test_idx = self
+            .tests
+            .iter()
+            .position(|(name, _)| *name == function.name)
+            .unwrap();
+
+        if let Some(position) = &self.main_position {
+            let main = Signature {
+                idx: 0,
+                params: self.main_params.clone(),
+                ret: None,
+                position: position.clone(),
+            };
+            self.functions.insert("main".to_owned(), main);
+        }
 
-                // Add new bitmap to the buffer
-                if self.out_bitmap.len() < desired_bitmap_len {
-                    self.out_bitmap.push(0x00);
+        let result = self.compile_test(function, test_idx);
+        self.functions.remove("main");
+        result
+    }
+
+    fn compile_test(&mut self, function: &Function, test_idx: usize) -> Result<(), CompilerErr> {
+        let mut function = function.clone();
+        typeck::check_function(&mut function, &self.functions)?;
+
+        // Tests come after the other functions in the function table
+        self.frame_stack
+            .push((self.out_funcs.len() + 1 + test_idx, vec![]));
+        self.ret = Returns::Nothing;
+        self.test_idx = Some(test_idx);
+        let mut out = vec![];
+        let result = self.compile_block(&function.body, &mut out);
+        self.test_idx = None;
+        self.frame_stack.pop();
+        if result.is_err() {
+            self.test_reverts.retain(|(test, _, _)| *test != test_idx);
+        }
+        result?;
+
+        out.push(OpCode::Return as u8);
+        check_function_len(&out, &function.position)?;
+        self.out_tests[test_idx] = out;
+
+        Ok(())
+    }
+
+    /// Returns the compiled script. Test functions are never included.
+    pub fn compile(&self) -> Vec<u8> {
+        Artifact {
+            bitmap: self.out_bitmap.clone(),
+            main: self.out_main.clone(),
+            funcs: self.out_funcs.clone(),
+            reasons: if self.strip_reasons {
+                vec![]
+            } else {
+                self.reasons.clone()
+            },
+        }
+        .encode()
+        .expect("functions and reasons are checked when they are compiled")
+    }
+
+    /// Returns the compiled script with the test functions appended to its
+    /// function table, along with the test functions. Such a script only
+    /// runs through `vm::run_test`. Revert reasons are never stripped, and
+    /// the reasons only used by tests come after the reasons of the script.
+    pub fn compile_tests(&self) -> (Vec<u8>, Vec<TestFunction>) {
+        let mut reasons = self.reasons.clone();
+        let mut tests = self.out_tests.clone();
+        for (test_idx, at, reason) in self.test_reverts.iter() {
+            let reason = &self.test_reasons[*reason];
+            let idx = match reasons.iter().position(|known| known == reason) {
+                Some(idx) => idx,
+                None => {
+                    reasons.push(reason.clone());
+                    reasons.len() - 1
                 }
+            };
+            let code = idx as u16 + 1;
+            tests[*test_idx][*at..*at + 2].copy_from_slice(&code.to_le_bytes());
+        }
 
-                let bitmap_len = self.out_bitmap.len();
-                let bitmap_idx = self.out_malleable_args_count - 1;
-                let bitmap = self.out_bitmap.get_mut(bitmap_len - 1).unwrap();
-                *bitmap |= 1 << bitmap_idx;
+        let mut funcs = self.out_funcs.clone();
+        funcs.extend(tests);
+        let script = Artifact {
+            bitmap: self.out_bitmap.clone(),
+            main: self.out_main.clone(),
+            funcs,
+            reasons,
+        }
+        .encode()
+        .expect("functions and reasons are checked when they are compiled");
+
+        let tests = self
+            .tests
+            .iter()
+            .enumerate()
+            .map(|(i, (name, position))| TestFunction {
+                name: name.clone(),
+                position: position.clone(),
+                func: self.out_funcs.len() + 1 + i,
+            })
+            .collect();
+
+        (script, tests)
+    }
+
+    /// Compiles a block. Variables declared in the block go out of scope at
+    /// its end and their slots are reused.
+    fn compile_block(&mut self, block: &[Stmt], out: &mut Vec<u8>) -> Result<(), CompilerErr> {
+        let scope = self.frame_len();
+        let result = block
+            .iter()
+            .try_for_each(|stmt| self.compile_stmt(stmt, out));
+        self.truncate_frame(scope);
+        result
+    }
 
-                self.state = CompilerState::ExpectingMainFuncMalleableIdentifier;
+    fn compile_stmt(&mut self, stmt: &Stmt, out: &mut Vec<u8>) -> Result<(), CompilerErr> {
+        match &stmt.kind {
+            StmtKind::Expr(expr) => {
+                // Calls to functions without a return type leave nothing to discard
+                if let ExprKind::Call(callee, args) = &expr.kind {
+                    if !self.compile_call(callee, args, out)? {
+                        return Ok(());
+                    }
+                } else if let ExprKind::Intrinsic(intrinsic, args) = &expr.kind {
+                    if !self.compile_intrinsic(intrinsic, args, out)? {
+                        return Ok(());
+                    }
+                } else {
+                    self.compile_expr(expr, out)?;
+                }
+                out.push(OpCode::Pop as u8);
             }
 
-            // We didn't hit a malleable arg keywork but we hit an identifier
-            (
-                &CompilerState::ExpectingMainFuncMalleableOrIdentifier,
-                TokenKind::Identifier(identifier),
-            ) => {
-                self.frame_stack
-                    .last_mut()
-                    .unwrap()
-                    .1
-                    .push((identifier, ValueType::Any));
-                self.state = CompilerState::ExpectingMainFuncColonCommaOrRightParanthesis;
+            StmtKind::Let { name, ty, value } => {
+                // The variable is not in scope in its own initializer
+                self.compile_expr(value, out)?;
+                let slot =
+                    self.declare(name, ty.clone().unwrap_or(ValueType::Any), &stmt.position)?;
+                emit_slot(out, OpCode::Store, slot);
             }
 
-            // We hit the right paranthesis after a comma. This is valid.
-            (
-                &CompilerState::ExpectingMainFuncMalleableOrIdentifier,
-                TokenKind::Symbol(Symbol::ParenthesisRight),
-            ) => {
-                self.state = CompilerState::ExpectingMainFuncBrace;
+            StmtKind::Assign { name, op, value } => {
+                let slot = self
+                    .resolve_slot(name)
+                    .ok_or_else(|| CompilerErr::UnknownIdentifier(stmt.position.clone()))?;
+
+                if let Some(op) = op {
+                    emit_slot(out, OpCode::Load, slot);
+                    self.compile_expr(value, out)?;
+                    out.push(binary_opcode(*op) as u8);
+                } else {
+                    self.compile_expr(value, out)?;
+                }
+
+                emit_slot(out, OpCode::Store, slot);
             }
 
-            (&CompilerState::ExpectingMainFuncMalleableOrIdentifier, _) => {
-                return Err(CompilerErr::ExpectedIdentifier(token.position.clone()));
+            StmtKind::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.compile_expr(cond, out)?;
+                let to_else = emit_jump(out, OpCode::JumpIfFalse);
+                self.compile_block(then, out)?;
+
+                if let Some(otherwise) = otherwise {
+                    let to_end = emit_jump(out, OpCode::Jump);
+                    patch_jump(out, to_else);
+                    self.compile_block(otherwise, out)?;
+                    patch_jump(out, to_end);
+                } else {
+                    patch_jump(out, to_else);
+                }
             }
 
-            (
-                &CompilerState::ExpectingMainFuncColonCommaOrRightParanthesis,
-                TokenKind::Symbol(Symbol::Colon),
-            ) => {
-                self.state = CompilerState::ExpectingMainFuncArgType;
+            StmtKind::While { cond, body } => {
+                let start = out.len();
+                self.compile_expr(cond, out)?;
+                let to_end = emit_jump(out, OpCode::JumpIfFalse);
+
+                let jumps = self.compile_loop_body(body, out)?;
+                jumps
+                    .continues
+                    .iter()
+                    .for_each(|at| patch_jump_to(out, *at, start));
+                emit_jump_to(out, OpCode::Jump, start);
+
+                patch_jump(out, to_end);
+                jumps.breaks.iter().for_each(|at| patch_jump(out, *at));
             }
 
-            (
-                &CompilerState::ExpectingMainFuncColonCommaOrRightParanthesis
-                | &CompilerState::ExpectingMainFuncCommaOrRightParanthesis,
-                TokenKind::Symbol(Symbol::Comma),
-            ) => {
-                self.state = CompilerState::ExpectingMainFuncMalleableOrIdentifier;
+            StmtKind::For {
+                init,
+                cond,
+                update,
+                body,
+            } => {
+                // Variables declared by the initializer are scoped to the loop
+                let scope = self.frame_len();
+                let result = self.compile_for(init, cond, update, body, out);
+                self.truncate_frame(scope);
+                result?;
             }
 
-            (
-                &CompilerState::ExpectingMainFuncColonCommaOrRightParanthesis
-                | &CompilerState::ExpectingMainFuncCommaOrRightParanthesis,
-                TokenKind::Symbol(Symbol::ParenthesisRight),
-            ) => {
-                self.state = CompilerState::ExpectingFuncBrace;
+            StmtKind::Return(None) => {
+                if self.ret == Returns::Value {
+                    return Err(CompilerErr::MissingReturnValue(stmt.position.clone()));
+                }
+                out.push(OpCode::Return as u8);
             }
 
-            (&CompilerState::ExpectingMainFuncColonCommaOrRightParanthesis, _) => {
-                return Err(CompilerErr::ExpectedColonCommaOrRightParanthesis(
-                    token.position.clone(),
-                ));
+            StmtKind::Return(Some(expr)) => {
+                if self.ret == Returns::Nothing {
+                    return Err(CompilerErr::UnexpectedReturnValue(expr.position.clone()));
+                }
+                self.compile_expr(expr, out)?;
+                out.push(OpCode::ReturnValue as u8);
             }
 
-            (&CompilerState::ExpectingMainFuncCommaOrRightParanthesis, _) => {
-                return Err(CompilerErr::ExpectedCommaOrRightParanthesis(
-                    token.position.clone(),
-                ));
+            StmtKind::Revert(reason) => {
+                out.push(OpCode::Revert as u8);
+                self.compile_reason(reason.as_deref(), &stmt.position, out)?;
             }
 
-            (&CompilerState::ExpectingMainFuncBrace, TokenKind::Symbol(Symbol::BraceLeft)) => {
-                self.state = CompilerState::ExpectingMainFuncBody;
+            StmtKind::Continue => {
+                let at = emit_jump(out, OpCode::Jump);
+                self.loop_stack
+                    .last_mut()
+                    .ok_or_else(|| CompilerErr::ContinueOutsideLoop(stmt.position.clone()))?
+                    .continues
+                    .push(at);
             }
 
-            (&CompilerState::ExpectingMainFuncBrace, _) => {
-                return Err(CompilerErr::ExpectedLeftBrace(token.position.clone()));
+            StmtKind::Break => {
+                let at = emit_jump(out, OpCode::Jump);
+                self.loop_stack
+                    .last_mut()
+                    .ok_or_else(|| CompilerErr::BreakOutsideLoop(stmt.position.clone()))?
+                    .breaks
+                    .push(at);
             }
+        }
 
-            // Main func argument types
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::Address)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::Address;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+        Ok(())
+    }
+
+    fn compile_for(
+        &mut self,
+        init: &Option<Box<Stmt>>,
+        cond: &Option<Expr>,
+        update: &Option<Box<Stmt>>,
+        body: &[Stmt],
+        out: &mut Vec<u8>,
+    ) -> Result<(), CompilerErr> {
+        if let Some(init) = init {
+            self.compile_stmt(init, out)?;
+        }
+
+        let start = out.len();
+        let to_end = match cond {
+            Some(cond) => {
+                self.compile_expr(cond, out)?;
+                Some(emit_jump(out, OpCode::JumpIfFalse))
             }
+            None => None,
+        };
+
+        let jumps = self.compile_loop_body(body, out)?;
+        jumps.continues.iter().for_each(|at| patch_jump(out, *at));
+        if let Some(update) = update {
+            self.compile_stmt(update, out)?;
+        }
+        emit_jump_to(out, OpCode::Jump, start);
+
+        if let Some(to_end) = to_end {
+            patch_jump(out, to_end);
+        }
+        jumps.breaks.iter().for_each(|at| patch_jump(out, *at));
+
+        Ok(())
+    }
+
+    /// Compiles the body of a loop and returns the `continue` and `break`
+    /// jumps in it, which the caller has to patch.
+    fn compile_loop_body(&mut self, body: &[Stmt], out: &mut Vec<u8>) -> Result<Loop, CompilerErr> {
+        self.loop_stack.push(Loop::default());
+        let result = self.compile_block(body, out);
+        let jumps = self.loop_stack.pop().unwrap();
+        result.map(|_| jumps)
+    }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::Asset)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::Asset;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+    fn compile_expr(&mut self, expr: &Expr, out: &mut Vec<u8>) -> Result<(), CompilerErr> {
+        match &expr.kind {
+            ExprKind::Identifier(identifier) => {
+                let slot = self
+                    .resolve_slot(identifier)
+                    .ok_or_else(|| CompilerErr::UnknownIdentifier(expr.position.clone()))?;
+                emit_slot(out, OpCode::Load, slot);
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::U8)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::U8;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            ExprKind::Number(number) => emit_number(number, false, expr, out)?,
+
+            ExprKind::Bool(value) => {
+                out.push(OpCode::PushConst as u8);
+                out.push(type_tag(&ValueType::Bool));
+                out.push(*value as u8);
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::U16)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::U16;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            ExprKind::Bytes(bytes) => {
+                out.push(OpCode::PushConst as u8);
+                encode_type(
+                    &ValueType::FixedArray(Box::new(ValueType::U8), bytes.len() as u32),
+                    out,
+                );
+                out.extend_from_slice(bytes);
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::U32)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::U32;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            // Negative literals are encoded directly, as their absolute value
+            // may not fit their type
+            ExprKind::Unary(UnaryOp::Neg, operand)
+                if matches!(operand.kind, ExprKind::Number(_)) =>
+            {
+                if let ExprKind::Number(number) = &operand.kind {
+                    emit_number(number, true, operand, out)?;
+                }
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::U64)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::U64;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            ExprKind::Unary(op, operand) => {
+                self.compile_expr(operand, out)?;
+                match op {
+                    UnaryOp::Neg => out.push(OpCode::Neg as u8),
+                    UnaryOp::Not => out.push(OpCode::Not as u8),
+                }
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::U128)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::U128;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            // Logical operators short-circuit and leave the value of the last
+            // evaluated operand on the stack.
+            ExprKind::Binary(op @ (BinaryOp::And | BinaryOp::Or), lhs, rhs) => {
+                self.compile_expr(lhs, out)?;
+                out.push(OpCode::Dup as u8);
+                let jump = if *op == BinaryOp::And {
+                    emit_jump(out, OpCode::JumpIfFalse)
+                } else {
+                    emit_jump(out, OpCode::JumpIfTrue)
+                };
+                out.push(OpCode::Pop as u8);
+                self.compile_expr(rhs, out)?;
+                patch_jump(out, jump);
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::UBIG)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::UBIG;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            ExprKind::Binary(op, lhs, rhs) => {
+                self.compile_expr(lhs, out)?;
+                self.compile_expr(rhs, out)?;
+                out.push(binary_opcode(*op) as u8);
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::I8)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::I8;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            ExprKind::Cast(operand, ty) => {
+                self.compile_expr(operand, out)?;
+                out.push(OpCode::Cast as u8);
+                encode_type(ty, out);
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::I16)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::I16;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            ExprKind::Index(object, index) => {
+                self.compile_expr(object, out)?;
+                self.compile_expr(index, out)?;
+                out.push(OpCode::Index as u8);
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::I32)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::I32;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            ExprKind::Slice(object, start, end) => {
+                self.compile_expr(object, out)?;
+                let mut bounds = 0;
+                if let Some(start) = start {
+                    self.compile_expr(start, out)?;
+                    bounds |= SLICE_START;
+                }
+                if let Some(end) = end {
+                    self.compile_expr(end, out)?;
+                    bounds |= SLICE_END;
+                }
+                out.push(OpCode::Slice as u8);
+                out.push(bounds);
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::I64)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::I64;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            ExprKind::Call(callee, args) => {
+                if !self.compile_call(callee, args, out)? {
+                    return Err(CompilerErr::NoReturnValue(expr.position.clone()));
+                }
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::I128)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::I128;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            ExprKind::Intrinsic(intrinsic, args) => {
+                if !self.compile_intrinsic(intrinsic, args, out)? {
+                    return Err(CompilerErr::NoReturnValue(expr.position.clone()));
+                }
             }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::IBIG)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::IBIG;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+            ExprKind::This => out.push(OpCode::This as u8),
+
+            ExprKind::String(_) | ExprKind::Member(_, _) => {
+                return Err(CompilerErr::UnsupportedExpression(expr.position.clone()));
             }
+        }
+
+        Ok(())
+    }
+
+    /// Compiles the access of a member of a builtin object and returns
+    /// whether it pushes a value.
+    fn compile_intrinsic(
+        &mut self,
+        intrinsic: &Intrinsic,
+        args: &[Expr],
+        out: &mut Vec<u8>,
+    ) -> Result<bool, CompilerErr> {
+        if intrinsic.opcode == OpCode::ExpectRevert {
+            let reason = match &args[0].kind {
+                ExprKind::String(reason) => reason,
+                _ => return Err(CompilerErr::ExpectedRevertReason(args[0].position.clone())),
+            };
+            out.push(OpCode::ExpectRevert as u8);
+            self.compile_reason(Some(reason), &args[0].position, out)?;
+            return Ok(false);
+        }
+
+        for arg in args.iter() {
+            self.compile_expr(arg, out)?;
+        }
+        out.push(intrinsic.opcode as u8);
+        Ok(intrinsic.ret.is_some())
+    }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::F32)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::F32;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+    /// Compiles a call and returns whether the callee returns a value.
+    ///
+    /// Arguments are pushed in order and become the first slots of the
+    /// frame of the callee.
+    fn compile_call(
+        &mut self,
+        callee: &Expr,
+        args: &[Expr],
+        out: &mut Vec<u8>,
+    ) -> Result<bool, CompilerErr> {
+        let name = match &callee.kind {
+            ExprKind::Identifier(name) => name,
+            ExprKind::Member(object, method) => {
+                self.compile_method(object, method, args, out)?;
+                return Ok(true);
             }
+            _ => return Err(CompilerErr::UnsupportedExpression(callee.position.clone())),
+        };
+
+        let (idx, arity, returns) = match self.functions.get(name) {
+            Some(sig) => (sig.idx, sig.params.len(), sig.ret.is_some()),
+            None => return Err(CompilerErr::UnknownFunction(callee.position.clone())),
+        };
+
+        if args.len() != arity {
+            return Err(CompilerErr::ArgumentCountMismatch(
+                arity,
+                args.len(),
+                callee.position.clone(),
+            ));
+        }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::F64)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::F64;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+        for arg in args.iter() {
+            self.compile_expr(arg, out)?;
+        }
+
+        out.push(OpCode::Call as u8);
+        out.extend_from_slice(&(idx as u16).to_le_bytes());
+        out.push(arity as u8);
+
+        Ok(returns)
+    }
+
+    /// Compiles a call of a builtin array method.
+    fn compile_method(
+        &mut self,
+        object: &Expr,
+        method: &str,
+        args: &[Expr],
+        out: &mut Vec<u8>,
+    ) -> Result<(), CompilerErr> {
+        let op = match method {
+            "len" => {
+                self.compile_expr(object, out)?;
+                out.push(OpCode::Len as u8);
+                return Ok(());
+            }
+            "takeFront" => OpCode::TakeFront,
+            "takeBack" => OpCode::TakeBack,
+            _ => return Err(CompilerErr::UnsupportedExpression(object.position.clone())),
+        };
+
+        // The remaining elements are stored back into the variable, which
+        // leaves the taken ones on the stack
+        let slot = match &object.kind {
+            ExprKind::Identifier(name) => self
+                .resolve_slot(name)
+                .ok_or_else(|| CompilerErr::UnknownIdentifier(object.position.clone()))?,
+            _ => {
+                return Err(CompilerErr::InvalidAssignmentTarget(
+                    object.position.clone(),
+                ))
             }
+        };
+        emit_slot(out, OpCode::Load, slot);
+        for arg in args.iter() {
+            self.compile_expr(arg, out)?;
+        }
+        out.push(op as u8);
+        emit_slot(out, OpCode::Store, slot);
+
+        Ok(())
+    }
 
-            (&CompilerState::ExpectingMainFuncArgType, TokenKind::Keyword(Keyword::Decimal)) => {
-                let (_, ref mut arg_type) =
-                    self.frame_stack.last_mut().unwrap().1.last_mut().unwrap();
-                *arg_type = ValueType::Decimal;
-                self.state = CompilerState::ExpectingMainFuncCommaOrRightParanthesis;
+    /// Writes the code of a revert reason as the operand of `Revert` or
+    /// `ExpectRevert`. Codes of reasons in test functions are patched by
+    /// `compile_tests`.
+    fn compile_reason(
+        &mut self,
+        reason: Option<&str>,
+        position: &Position,
+        out: &mut Vec<u8>,
+    ) -> Result<(), CompilerErr> {
+        let code = match (reason, self.test_idx) {
+            (None | Some(""), _) => 0,
+            (Some(reason), Some(test_idx)) => {
+                let reason = self.test_reason(reason, position)?;
+                self.test_reverts.push((test_idx, out.len(), reason));
+                0
             }
+            (Some(reason), None) => self.reason_code(reason, position)?,
+        };
+        out.extend_from_slice(&code.to_le_bytes());
+        Ok(())
+    }
+
+    /// Returns the code of a revert reason. Reverts with the same reason
+    /// share its code.
+    fn reason_code(&mut self, reason: &str, position: &Position) -> Result<u16, CompilerErr> {
+        let idx = match self.reasons.iter().position(|known| known == reason) {
+            Some(idx) => idx,
+            None => {
+                self.check_new_reason(reason, position)?;
+                self.reasons.push(reason.to_owned());
+                self.reasons.len() - 1
+            }
+        };
+        Ok(idx as u16 + 1)
+    }
 
-            _ => unimplemented!(),
+    /// Returns the index of a revert reason of a test function in
+    /// `test_reasons`.
+    fn test_reason(&mut self, reason: &str, position: &Position) -> Result<usize, CompilerErr> {
+        match self.test_reasons.iter().position(|known| known == reason) {
+            Some(idx) => Ok(idx),
+            None => {
+                self.check_new_reason(reason, position)?;
+                self.test_reasons.push(reason.to_owned());
+                Ok(self.test_reasons.len() - 1)
+            }
         }
+    }
 
+    /// Codes and the lengths of reasons are `u16`, so that the reasons of a
+    /// script and of its tests together cannot exceed `u16::MAX`, and neither
+    /// can the bytes of a reason.
+    fn check_new_reason(&self, reason: &str, position: &Position) -> Result<(), CompilerErr> {
+        if reason.len() > u16::MAX as usize {
+            return Err(CompilerErr::RevertReasonTooLong(position.clone()));
+        }
+
+        if self.reasons.len() + self.test_reasons.len() < u16::MAX as usize {
+            Ok(())
+        } else {
+            Err(CompilerErr::TooManyRevertReasons(position.clone()))
+        }
+    }
+
+    /// Indices of functions are `u16`, and tests come after the other
+    /// functions in the function table.
+    fn check_functions_len(&self, position: &Position) -> Result<(), CompilerErr> {
+        if self.out_funcs.len() + self.tests.len() < MAX_FUNCTIONS {
+            Ok(())
+        } else {
+            Err(CompilerErr::TooManyFunctions(position.clone()))
+        }
+    }
+
+    /// Declares a variable in the current frame and returns its slot.
+    fn declare(
+        &mut self,
+        name: &str,
+        ty: ValueType,
+        position: &Position,
+    ) -> Result<u16, CompilerErr> {
+        let (_, slots) = self.frame_stack.last_mut().unwrap();
+        if slots.len() >= MAX_VARIABLES {
+            return Err(CompilerErr::TooManyVariables(position.clone()));
+        }
+        slots.push((name.to_owned(), ty));
+        Ok((slots.len() - 1) as u16)
+    }
+
+    /// Number of variables in scope in the current frame.
+    fn frame_len(&self) -> usize {
+        self.frame_stack.last().map_or(0, |(_, slots)| slots.len())
+    }
+
+    /// Ends the scope of all variables declared after the first `len` ones.
+    fn truncate_frame(&mut self, len: usize) {
+        if let Some((_, slots)) = self.frame_stack.last_mut() {
+            slots.truncate(len);
+        }
+    }
+
+    /// Returns the frame slot of the given identifier in the current frame.
+    fn resolve_slot(&self, identifier: &str) -> Option<u16> {
+        let (_, slots) = self.frame_stack.last()?;
+        slots
+            .iter()
+            .rposition(|(name, _)| name == identifier)
+            .map(|slot| slot as u16)
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn binary_opcode(op: BinaryOp) -> OpCode {
+    match op {
+        BinaryOp::Mul => OpCode::Mul,
+        BinaryOp::Div => OpCode::Div,
+        BinaryOp::Rem => OpCode::Rem,
+        BinaryOp::Add => OpCode::Add,
+        BinaryOp::Sub => OpCode::Sub,
+        BinaryOp::Shl => OpCode::Shl,
+        BinaryOp::Shr => OpCode::Shr,
+        BinaryOp::BitAnd => OpCode::BitAnd,
+        BinaryOp::BitXor => OpCode::BitXor,
+        BinaryOp::BitOr => OpCode::BitOr,
+        BinaryOp::Lt => OpCode::Lt,
+        BinaryOp::Le => OpCode::Le,
+        BinaryOp::Gt => OpCode::Gt,
+        BinaryOp::Ge => OpCode::Ge,
+        BinaryOp::Eq => OpCode::Eq,
+        BinaryOp::Ne => OpCode::Ne,
+        BinaryOp::And | BinaryOp::Or => unreachable!("logical operators are compiled to jumps"),
+    }
+}
+
+/// Returns the malleable arguments bitmap of the main function, which has
+/// one bit per argument. The bit of argument `i` is bit `i % 8` of byte
+/// `i / 8` and is set if the argument is malleable.
+fn malleable_bitmap(params: &[Param]) -> Vec<u8> {
+    let mut bitmap = vec![0x00; params.len().div_ceil(8)];
+    for (i, param) in params.iter().enumerate() {
+        if param.malleable {
+            bitmap[i / 8] |= 1 << (i % 8);
+        }
+    }
+    bitmap
+}
+
+/// Bodies of functions are at most `u32::MAX` bytes, which also keeps the
+/// targets of their jumps within `u32`.
+fn check_function_len(out: &[u8], position: &Position) -> Result<(), CompilerErr> {
+    if out.len() <= u32::MAX as usize {
         Ok(())
+    } else {
+        Err(CompilerErr::FunctionTooLong(position.clone()))
     }
+}
 
-    pub fn compile(&self) -> Vec<u8> {
-        unimplemented!();
+/// Emits a jump with a placeholder target and returns the offset of the
+/// placeholder, to be filled in by `patch_jump`.
+fn emit_jump(out: &mut Vec<u8>, op: OpCode) -> usize {
+    out.push(op as u8);
+    out.extend_from_slice(&[0x00; 4]);
+    out.len() - 4
+}
+
+/// Emits a jump to a known target, e.g. back to the start of a loop.
+fn emit_jump_to(out: &mut Vec<u8>, op: OpCode, target: usize) {
+    out.push(op as u8);
+    out.extend_from_slice(&(target as u32).to_le_bytes());
+}
+
+/// Points the jump placeholder at `at` to the end of the buffer.
+fn patch_jump(out: &mut [u8], at: usize) {
+    let target = out.len();
+    patch_jump_to(out, at, target);
+}
+
+fn patch_jump_to(out: &mut [u8], at: usize, target: usize) {
+    out[at..at + 4].copy_from_slice(&(target as u32).to_le_bytes());
+}
+
+/// Emits a number literal as a constant of the type of `expr`.
+fn emit_number(
+    number: &Number,
+    negative: bool,
+    expr: &Expr,
+    out: &mut Vec<u8>,
+) -> Result<(), CompilerErr> {
+    let (ty, value) = encode_number(number, negative, &expr.ty)
+        .ok_or_else(|| CompilerErr::InvalidNumberLiteral(expr.position.clone()))?;
+    out.push(OpCode::PushConst as u8);
+    out.push(type_tag(&ty));
+    out.extend_from_slice(&value);
+    Ok(())
+}
+
+/// Emits an instruction operating on a frame slot.
+fn emit_slot(out: &mut Vec<u8>, op: OpCode, slot: u16) {
+    out.push(op as u8);
+    out.extend_from_slice(&slot.to_le_bytes());
+}
+
+/// Encodes a number literal as a constant of type `ty` and returns the type
+/// of the constant along with its bytes. `negative` is true if the literal
+/// is the operand of a negation.
+///
+/// Literals of type `any` are encoded as `i64`, or as `decimal` if they are
+/// fractional. Returns `None` if the literal does not fit the type.
+pub(crate) fn encode_number(
+    number: &Number,
+    negative: bool,
+    ty: &ValueType,
+) -> Option<(ValueType, Vec<u8>)> {
+    fn fixed<T: for<'a> TryFrom<&'a BigInt>>(number: &Number, negative: bool) -> Option<T> {
+        T::try_from(&number.to_integer(negative)?).ok()
     }
+
+    let bytes = match ty {
+        ValueType::U8 => fixed::<u8>(number, negative)?.to_le_bytes().to_vec(),
+        ValueType::U16 => fixed::<u16>(number, negative)?.to_le_bytes().to_vec(),
+        ValueType::U32 => fixed::<u32>(number, negative)?.to_le_bytes().to_vec(),
+        ValueType::U64 => fixed::<u64>(number, negative)?.to_le_bytes().to_vec(),
+        ValueType::U128 => fixed::<u128>(number, negative)?.to_le_bytes().to_vec(),
+        ValueType::I8 => fixed::<i8>(number, negative)?.to_le_bytes().to_vec(),
+        ValueType::I16 => fixed::<i16>(number, negative)?.to_le_bytes().to_vec(),
+        ValueType::I32 => fixed::<i32>(number, negative)?.to_le_bytes().to_vec(),
+        ValueType::I64 => fixed::<i64>(number, negative)?.to_le_bytes().to_vec(),
+        ValueType::I128 => fixed::<i128>(number, negative)?.to_le_bytes().to_vec(),
+        ValueType::F32 => {
+            let value = Some(number.to_f32(negative)).filter(|value| value.is_finite())?;
+            value.to_le_bytes().to_vec()
+        }
+        ValueType::F64 => {
+            let value = Some(number.to_f64(negative)).filter(|value| value.is_finite())?;
+            value.to_le_bytes().to_vec()
+        }
+        ValueType::Decimal => {
+            let scaled = number.scaled(negative, DECIMAL_SCALE)?;
+            i128::try_from(&scaled).ok()?.to_le_bytes().to_vec()
+        }
+        ValueType::UBIG => {
+            let value = number.to_integer(negative)?;
+            if value.sign() == Sign::Minus {
+                return None;
+            }
+            encode_big(value.magnitude().to_bytes_le())?
+        }
+        ValueType::IBIG => encode_big(number.to_integer(negative)?.to_signed_bytes_le())?,
+        ValueType::Any if number.fractional => {
+            return encode_number(number, negative, &ValueType::Decimal);
+        }
+        ValueType::Any => return encode_number(number, negative, &ValueType::I64),
+        ValueType::Bool
+        | ValueType::Address
+        | ValueType::Asset
+        | ValueType::Output
+        | ValueType::Input
+        | ValueType::PublicKey
+        | ValueType::Hash
+        | ValueType::Array(_)
+        | ValueType::FixedArray(_, _) => return None,
+    };
+
+    Some((ty.clone(), bytes))
+}
+
+/// Big integers are encoded as their length in bytes (`u16`) followed by the
+/// little-endian bytes of their value.
+fn encode_big(value: Vec<u8>) -> Option<Vec<u8>> {
+    let len = u16::try_from(value.len()).ok()?;
+    let mut bytes = len.to_le_bytes().to_vec();
+    bytes.extend_from_slice(&value);
+    Some(bytes)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum CompilerErr {
+    Lex(LexError),
     ExpectedFunctionDefinition(Position),
     ExpectedIdentifier(Position),
     ExpectedLeftParanthesis(Position),
+    ExpectedRightParanthesis(Position),
+    ExpectedRightBracket(Position),
     ExpectedColonCommaOrRightParanthesis(Position),
     ExpectedCommaOrRightParanthesis(Position),
     ExpectedLeftBrace(Position),
-    DuplicateMainDeclaration(Position),
+    ExpectedSemicolon(Position),
+    ExpectedType(Position),
+    ExpectedImportPath(Position),
+    ExpectedRevertReason(Position),
+    ExpectedEqual(Position),
+    InvalidAssignmentTarget(Position),
+    ExpectedArrayLength(Position),
+    TypeNestedTooDeeply(Position),
+    /// An expression or block nested in too many expressions and blocks
+    NestedTooDeeply(Position),
+    UnexpectedToken(Position),
+    UnexpectedEndOfInput(Position),
+    /// Position of the duplicate and of the first declaration
+    DuplicateMainDeclaration(Position, Position),
+    MalleableArgOutsideMain(Position),
+    UnknownIdentifier(Position),
+    InvalidNumberLiteral(Position),
+    UnsupportedExpression(Position),
+    BreakOutsideLoop(Position),
+    ContinueOutsideLoop(Position),
+    /// Position of the duplicate and of the first declaration
+    DuplicateFunctionDeclaration(Position, Position),
+    UnknownFunction(Position),
+    /// Number of parameters and of arguments
+    ArgumentCountMismatch(usize, usize, Position),
+    /// A call to a function without a return type used as a value
+    NoReturnValue(Position),
+    /// `return;` in a function with a return type
+    MissingReturnValue(Position),
+    /// `return <expr>;` in a function without a return type
+    UnexpectedReturnValue(Position),
+    /// Resolved path of the import which could not be read
+    UnresolvedImport(String, Position),
+    /// Imported files are libraries and cannot declare `main`
+    ImportedMain(Position),
+    /// Expected and found type
+    TypeMismatch(ValueType, ValueType, Position),
+    /// Type of the operand which the operator does not accept
+    InvalidOperandType(ValueType, Position),
+    /// Type of the operand and the type it is cast to
+    InvalidCast(ValueType, ValueType, Position),
+    /// Type of the literal
+    LiteralOutOfRange(ValueType, Position),
+    /// Builtin object or type, and the name of the member it does not have
+    UnknownMember(String, String, Position),
+    /// A method of a builtin object used as a field, e.g. `XPU.outputsLen`
+    MethodNotCalled(String, Position),
+    /// A field of a builtin object called like a method
+    NotAMethod(String, Position),
+    /// A test function with parameters or a return type
+    InvalidTestSignature(Position),
+    /// A revert reason beyond the 65535 distinct reasons of a script
+    TooManyRevertReasons(Position),
+    /// The parameter beyond the 255 parameters of a function
+    TooManyParameters(Position),
+    /// A function beyond the 65535 functions of a script
+    TooManyFunctions(Position),
+    /// A variable beyond the 65535 variables in scope in a function
+    TooManyVariables(Position),
+    /// A revert reason longer than 65535 bytes
+    RevertReasonTooLong(Position),
+    /// A function whose bytecode is longer than `u32::MAX` bytes
+    FunctionTooLong(Position),
 }
 
-enum CompilerState {
-    // General states
-    //
-    /// Any func definition
-    Any,
+impl CompilerErr {
+    /// Primary position of the error. Its file and span point at the exact
+    /// source range which caused the error.
+    pub fn position(&self) -> &Position {
+        match self {
+            CompilerErr::Lex(err) => err.position(),
+            CompilerErr::ExpectedFunctionDefinition(position)
+            | CompilerErr::ExpectedIdentifier(position)
+            | CompilerErr::ExpectedLeftParanthesis(position)
+            | CompilerErr::ExpectedRightParanthesis(position)
+            | CompilerErr::ExpectedRightBracket(position)
+            | CompilerErr::ExpectedColonCommaOrRightParanthesis(position)
+            | CompilerErr::ExpectedCommaOrRightParanthesis(position)
+            | CompilerErr::ExpectedLeftBrace(position)
+            | CompilerErr::ExpectedSemicolon(position)
+            | CompilerErr::ExpectedType(position)
+            | CompilerErr::ExpectedImportPath(position)
+            | CompilerErr::ExpectedRevertReason(position)
+            | CompilerErr::ExpectedEqual(position)
+            | CompilerErr::InvalidAssignmentTarget(position)
+            | CompilerErr::ExpectedArrayLength(position)
+            | CompilerErr::TypeNestedTooDeeply(position)
+            | CompilerErr::NestedTooDeeply(position)
+            | CompilerErr::UnexpectedToken(position)
+            | CompilerErr::UnexpectedEndOfInput(position)
+            | CompilerErr::DuplicateMainDeclaration(position, _)
+            | CompilerErr::MalleableArgOutsideMain(position)
+            | CompilerErr::UnknownIdentifier(position)
+            | CompilerErr::InvalidNumberLiteral(position)
+            | CompilerErr::UnsupportedExpression(position)
+            | CompilerErr::BreakOutsideLoop(position)
+            | CompilerErr::ContinueOutsideLoop(position)
+            | CompilerErr::DuplicateFunctionDeclaration(position, _)
+            | CompilerErr::UnknownFunction(position)
+            | CompilerErr::ArgumentCountMismatch(_, _, position)
+            | CompilerErr::NoReturnValue(position)
+            | CompilerErr::MissingReturnValue(position)
+            | CompilerErr::UnexpectedReturnValue(position)
+            | CompilerErr::UnresolvedImport(_, position)
+            | CompilerErr::ImportedMain(position)
+            | CompilerErr::TypeMismatch(_, _, position)
+            | CompilerErr::InvalidOperandType(_, position)
+            | CompilerErr::InvalidCast(_, _, position)
+            | CompilerErr::LiteralOutOfRange(_, position)
+            | CompilerErr::UnknownMember(_, _, position)
+            | CompilerErr::MethodNotCalled(_, position)
+            | CompilerErr::NotAMethod(_, position)
+            | CompilerErr::InvalidTestSignature(position)
+            | CompilerErr::TooManyRevertReasons(position)
+            | CompilerErr::TooManyParameters(position)
+            | CompilerErr::TooManyFunctions(position)
+            | CompilerErr::TooManyVariables(position)
+            | CompilerErr::RevertReasonTooLong(position)
+            | CompilerErr::FunctionTooLong(position) => position,
+        }
+    }
+}
 
-    /// Any func definition except the main function
-    AnyExceptMain,
+impl From<LexError> for CompilerErr {
+    fn from(err: LexError) -> Self {
+        CompilerErr::Lex(err)
+    }
+}
 
-    // Func definition states
-    //
-    /// We hit a `function` definition, now we want the identifier.
-    ExpectingFuncIdentifier,
+#[cfg(test)]
+mod tests {
+    use super::{Compiler, CompilerErr};
+    use crate::bytecode::{Artifact, OpCode};
+    use crate::lexer::{tokenise, Position};
+    use crate::parser::parse;
+
+    fn compile_str(input: &str) -> Result<Artifact, CompilerErr> {
+        let program = parse(tokenise(input).collect::<Result<_, _>>()?)?;
+        let mut compiler = Compiler::new();
+        for function in program.functions.iter() {
+            compiler.declare_function(function)?;
+        }
+        for function in program.functions.iter() {
+            compiler.push_function(function)?;
+        }
+        Ok(Artifact::decode(&compiler.compile()).unwrap())
+    }
 
-    /// We hit the main function identifier, now we want the left paranthesis for the arguments.
-    ExpectingMainFuncLeftParanthesis,
+    #[test]
+    fn test_compile_empty_main() {
+        let artifact = compile_str("function main() {}").unwrap();
+        assert_eq!(artifact.main, vec![OpCode::Return as u8]);
+        assert!(artifact.funcs.is_empty());
+    }
 
-    /// We hit the main function paranthesis for arguments, now we want the actual
-    /// arguments which can be malleable or not.
-    ExpectingMainFuncMalleableOrIdentifier,
+    #[test]
+    fn test_compile_return_arg() {
+        let artifact = compile_str("function main(a, b) { return b; }").unwrap();
+        assert_eq!(
+            artifact.main,
+            vec![
+                OpCode::Load as u8,
+                0x01,
+                0x00,
+                OpCode::ReturnValue as u8,
+                OpCode::Return as u8
+            ]
+        );
+    }
 
-    /// We hit a malleable arg definition, now we want the identifier.
-    ExpectingMainFuncMalleableIdentifier,
+    #[test]
+    fn test_compile_number_literals() {
+        let artifact = compile_str("function main() { 2; 1.5; }").unwrap();
+        let mut expected = vec![OpCode::PushConst as u8, 0x0a];
+        expected.extend_from_slice(&2i64.to_le_bytes());
+        expected.push(OpCode::Pop as u8);
+        expected.extend_from_slice(&[OpCode::PushConst as u8, 0x0f]);
+        expected.extend_from_slice(&1_500_000_000_000_000_000i128.to_le_bytes());
+        expected.push(OpCode::Pop as u8);
+        expected.push(OpCode::Return as u8);
+        assert_eq!(artifact.main, expected);
+    }
 
-    /// We have hit the main function identifier, now we either expect a colon,
-    /// in order to specify the argument type, a comma, or the right paranthesis
-    /// to end the main function definitions.
-    ExpectingMainFuncColonCommaOrRightParanthesis,
+    #[test]
+    fn test_compile_big_integer_literals() {
+        let artifact = compile_str(
+            "function main() { let a: ubig = 0x1_0000; let b: ibig = -128; let c: decimal = 2.5e-3; }",
+        )
+        .unwrap();
+        let mut expected = vec![OpCode::PushConst as u8, 0x06, 0x03, 0x00, 0x00, 0x00, 0x01];
+        expected.extend_from_slice(&[OpCode::Store as u8, 0x00, 0x00]);
+        expected.extend_from_slice(&[OpCode::PushConst as u8, 0x0c, 0x01, 0x00, 0x80]);
+        expected.extend_from_slice(&[OpCode::Store as u8, 0x01, 0x00]);
+        expected.extend_from_slice(&[OpCode::PushConst as u8, 0x0f]);
+        expected.extend_from_slice(&2_500_000_000_000_000i128.to_le_bytes());
+        expected.extend_from_slice(&[OpCode::Store as u8, 0x02, 0x00]);
+        expected.push(OpCode::Return as u8);
+        assert_eq!(artifact.main, expected);
+    }
 
-    /// We have just parsed an argument type, so we allow either a comma or
-    /// a right paranthesis.
-    ExpectingMainFuncCommaOrRightParanthesis,
+    #[test]
+    fn test_compile_test_functions() {
+        let artifact =
+            compile_str("function main(a: u8) {}\ntest function t() { main(1); }").unwrap();
+        assert!(artifact.funcs.is_empty());
+
+        // Reasons only used by tests are not part of the compiled script
+        let tokens = tokenise(
+            "test function t() { revert(\"a\"); revert(\"b\"); }\nfunction main() { revert(\"b\"); }",
+        )
+        .collect::<Result<_, _>>()
+        .unwrap();
+        let program = parse(tokens).unwrap();
+        let mut compiler = Compiler::new();
+        for function in program.functions.iter() {
+            compiler.push_function(function).unwrap();
+        }
+        let artifact = Artifact::decode(&compiler.compile()).unwrap();
+        assert_eq!(artifact.reasons, vec!["b".to_owned()]);
+
+        let (script, tests) = compiler.compile_tests();
+        let artifact = Artifact::decode(&script).unwrap();
+        assert_eq!(artifact.reasons, vec!["b".to_owned(), "a".to_owned()]);
+        assert_eq!(tests[0].func, 1);
+        assert_eq!(
+            artifact.funcs[0],
+            vec![
+                OpCode::Revert as u8,
+                0x02,
+                0x00,
+                OpCode::Revert as u8,
+                0x01,
+                0x00,
+                OpCode::Return as u8
+            ]
+        );
+
+        assert_eq!(
+            compile_str("test function t(a: u8) {}"),
+            Err(CompilerErr::InvalidTestSignature(Position::new(1, 1)))
+        );
+        assert_eq!(
+            compile_str("test function t() -> u8 { return 1; }"),
+            Err(CompilerErr::InvalidTestSignature(Position::new(1, 1)))
+        );
+        assert_eq!(
+            compile_str("function t() {}\ntest function t() {}\ntest function t() {}"),
+            Err(CompilerErr::DuplicateFunctionDeclaration(
+                Position::new(1, 3),
+                Position::new(1, 2)
+            ))
+        );
+
+        // Only tests can call main and use `Test`
+        assert_eq!(
+            compile_str("function main() {}\nfunction f() { main(); }"),
+            Err(CompilerErr::UnknownFunction(Position::new(16, 2)))
+        );
+        assert_eq!(
+            compile_str("function main() { Test.pushedLen(); }"),
+            Err(CompilerErr::UnsupportedExpression(Position::new(19, 1)))
+        );
+    }
 
-    /// We have hit a colon for a main function argument, we are now expecting the type
-    ExpectingMainFuncArgType,
+    #[test]
+    fn test_compile_helper_function() {
+        let artifact =
+            compile_str("function helper(a: u8) -> u8 { return a; } function main() {}").unwrap();
+        assert_eq!(artifact.funcs.len(), 1);
+    }
 
-    /// We finished the arguments definition, now we want the start brace
-    /// of the main function body.
-    ExpectingMainFuncBrace,
+    #[test]
+    fn test_compile_malleable_bitmap() {
+        let artifact = compile_str("function main(a, malleable b, malleable c) {}").unwrap();
+        assert_eq!(artifact.bitmap, vec![0b0000_0110]);
 
-    /// We hit the start brace of the function body, now we want the actual body.
-    ExpectingMainFuncBody,
+        let artifact = compile_str("function main() {}").unwrap();
+        assert!(artifact.bitmap.is_empty());
 
-    /// We hit the function identifier, now we want the left paranthesis for the arguments.
-    ExpectingFuncLeftParanthesis,
+        let artifact = compile_str("function main(a, b) {}").unwrap();
+        assert_eq!(artifact.bitmap, vec![0x00]);
+    }
 
-    /// We hit the function paranthesis for arguments, now we want the actual
-    /// arguments.
-    ExpectingFuncArgIdentifier,
+    #[test]
+    fn test_compile_malleable_bitmap_beyond_one_byte() {
+        // Arguments 0, 7, 8, 15, 16 and 19 of 20 are malleable
+        let params: Vec<_> = (0..20)
+            .map(|i| match i {
+                0 | 7 | 8 | 15 | 16 | 19 => format!("malleable a{}: u8", i),
+                _ => format!("a{}", i),
+            })
+            .collect();
+        let source = format!("function main({}) {{}}", params.join(", "));
+        let artifact = compile_str(&source).unwrap();
+        assert_eq!(artifact.bitmap, vec![0b1000_0001, 0b1000_0001, 0b0000_1001]);
+
+        // Only the last of 17 arguments is malleable
+        let params: Vec<_> = (0..17).map(|i| format!("a{}", i)).collect();
+        let source = format!("function main({}, malleable last) {{}}", params.join(", "));
+        let artifact = compile_str(&source).unwrap();
+        assert_eq!(artifact.bitmap, vec![0x00, 0x00, 0b0000_0010]);
+    }
 
-    /// We have hit the function identifier, now we either expect a colon,
-    /// in order to specify the argument type, a comma, or the right paranthesis
-    /// to end the function definitions.
-    ExpectingFuncColonCommaOrRightParanthesis,
+    #[test]
+    fn test_compile_duplicate_main() {
+        assert_eq!(
+            compile_str("function main() {}\nfunction main() {}"),
+            Err(CompilerErr::DuplicateMainDeclaration(
+                Position::new(1, 2),
+                Position::new(1, 1)
+            ))
+        );
+    }
 
-    /// We finished the arguments definition, now we want the start brace
-    /// of the function body.
-    ExpectingFuncBrace,
+    #[test]
+    fn test_compile_malleable_outside_main() {
+        assert_eq!(
+            compile_str("function helper(malleable a) {}"),
+            Err(CompilerErr::MalleableArgOutsideMain(Position::new(17, 1)))
+        );
+    }
 
-    /// We hit the start brace of the function body, now we want the actual body.
-    ExpectingFuncBody,
-    // Func bodies
-    //
-}
+    #[test]
+    fn test_compile_unknown_identifier() {
+        assert_eq!(
+            compile_str("function main(a) { return b; }"),
+            Err(CompilerErr::UnknownIdentifier(Position::new(27, 1)))
+        );
+    }
 
-enum ValueType {
-    Any,
-    U8,
-    U16,
-    U32,
-    U64,
-    U128,
-    UBIG,
-    I8,
-    I16,
-    I32,
-    I64,
-    I128,
-    IBIG,
-    F32,
-    F64,
-    Decimal,
-    Address,
-    Asset,
+    #[test]
+    fn test_compile_binary_and_cast() {
+        let artifact = compile_str("function main(a, b) { return (a + b) as u8; }").unwrap();
+        assert_eq!(
+            artifact.main,
+            vec![
+                OpCode::Load as u8,
+                0x00,
+                0x00,
+                OpCode::Load as u8,
+                0x01,
+                0x00,
+                OpCode::Add as u8,
+                OpCode::Cast as u8,
+                0x01,
+                OpCode::ReturnValue as u8,
+                OpCode::Return as u8
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_logical_and_short_circuits() {
+        let artifact = compile_str("function main(a: bool, b: bool) { return a && b; }").unwrap();
+        assert_eq!(
+            artifact.main,
+            vec![
+                OpCode::Load as u8,
+                0x00,
+                0x00,
+                OpCode::Dup as u8,
+                OpCode::JumpIfFalse as u8,
+                0x0d,
+                0x00,
+                0x00,
+                0x00,
+                OpCode::Pop as u8,
+                OpCode::Load as u8,
+                0x01,
+                0x00,
+                OpCode::ReturnValue as u8,
+                OpCode::Return as u8
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_let_reuses_slots_of_ended_scopes() {
+        let artifact = compile_str(
+            "function main(a: bool) { let b = a; if (b) { let c = b; } let d = a; return d; }",
+        )
+        .unwrap();
+        assert_eq!(
+            artifact.main,
+            vec![
+                OpCode::Load as u8,
+                0x00,
+                0x00,
+                OpCode::Store as u8,
+                0x01,
+                0x00,
+                OpCode::Load as u8,
+                0x01,
+                0x00,
+                OpCode::JumpIfFalse as u8,
+                0x14,
+                0x00,
+                0x00,
+                0x00,
+                OpCode::Load as u8,
+                0x01,
+                0x00,
+                OpCode::Store as u8,
+                0x02,
+                0x00,
+                OpCode::Load as u8,
+                0x00,
+                0x00,
+                OpCode::Store as u8,
+                0x02,
+                0x00,
+                OpCode::Load as u8,
+                0x02,
+                0x00,
+                OpCode::ReturnValue as u8,
+                OpCode::Return as u8
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_if_else() {
+        let artifact =
+            compile_str("function main(a: bool) { if (a) { return 1; } else { return; } }")
+                .unwrap();
+        let mut expected = vec![OpCode::Load as u8, 0x00, 0x00];
+        expected.extend_from_slice(&[OpCode::JumpIfFalse as u8, 0x18, 0x00, 0x00, 0x00]);
+        expected.extend_from_slice(&[OpCode::PushConst as u8, 0x0a]);
+        expected.extend_from_slice(&1i64.to_le_bytes());
+        expected.push(OpCode::ReturnValue as u8);
+        expected.extend_from_slice(&[OpCode::Jump as u8, 0x19, 0x00, 0x00, 0x00]);
+        expected.push(OpCode::Return as u8);
+        expected.push(OpCode::Return as u8);
+        assert_eq!(artifact.main, expected);
+    }
+
+    #[test]
+    fn test_compile_while_with_break_and_continue() {
+        let artifact =
+            compile_str("function main(a: bool) { while (a) { if (a) { break; } continue; } }")
+                .unwrap();
+        assert_eq!(
+            artifact.main,
+            vec![
+                OpCode::Load as u8,
+                0x00,
+                0x00,
+                OpCode::JumpIfFalse as u8,
+                0x1f,
+                0x00,
+                0x00,
+                0x00,
+                OpCode::Load as u8,
+                0x00,
+                0x00,
+                OpCode::JumpIfFalse as u8,
+                0x15,
+                0x00,
+                0x00,
+                0x00,
+                // break
+                OpCode::Jump as u8,
+                0x1f,
+                0x00,
+                0x00,
+                0x00,
+                // continue
+                OpCode::Jump as u8,
+                0x00,
+                0x00,
+                0x00,
+                0x00,
+                OpCode::Jump as u8,
+                0x00,
+                0x00,
+                0x00,
+                0x00,
+                OpCode::Return as u8
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_for() {
+        let artifact =
+            compile_str("function main() { for (let i = 0; i < 3; i++) { continue; } }").unwrap();
+        let push = |value: i64| {
+            let mut bytes = vec![OpCode::PushConst as u8, 0x0a];
+            bytes.extend_from_slice(&value.to_le_bytes());
+            bytes
+        };
+
+        // Initializer
+        let mut expected = push(0);
+        expected.extend_from_slice(&[OpCode::Store as u8, 0x00, 0x00]);
+
+        // Condition at 13
+        expected.extend_from_slice(&[OpCode::Load as u8, 0x00, 0x00]);
+        expected.extend(push(3));
+        expected.push(OpCode::Lt as u8);
+        expected.extend_from_slice(&[OpCode::JumpIfFalse as u8, 0x3b, 0x00, 0x00, 0x00]);
+
+        // `continue` jumps to the update at 37
+        expected.extend_from_slice(&[OpCode::Jump as u8, 0x25, 0x00, 0x00, 0x00]);
+        expected.extend_from_slice(&[OpCode::Load as u8, 0x00, 0x00]);
+        expected.extend(push(1));
+        expected.push(OpCode::Add as u8);
+        expected.extend_from_slice(&[OpCode::Store as u8, 0x00, 0x00]);
+        expected.extend_from_slice(&[OpCode::Jump as u8, 0x0d, 0x00, 0x00, 0x00]);
+        expected.push(OpCode::Return as u8);
+
+        assert_eq!(artifact.main, expected);
+    }
+
+    #[test]
+    fn test_compile_loop_variables_are_scoped_to_the_loop() {
+        assert_eq!(
+            compile_str("function main() { for (let i = 0; i < 3; i++) {} return i; }"),
+            Err(CompilerErr::UnknownIdentifier(Position::new(57, 1)))
+        );
+        assert_eq!(
+            compile_str("function main() { b = 1; }"),
+            Err(CompilerErr::UnknownIdentifier(Position::new(19, 1)))
+        );
+    }
+
+    #[test]
+    fn test_compile_revert() {
+        let artifact = compile_str(
+            "function main() { revert; revert(\"no\"); revert(\"\"); }\nfunction f() { revert(\"yes\"); revert(\"no\"); }",
+        )
+        .unwrap();
+        assert_eq!(
+            artifact.main,
+            vec![
+                OpCode::Revert as u8,
+                0x00,
+                0x00,
+                OpCode::Revert as u8,
+                0x01,
+                0x00,
+                OpCode::Revert as u8,
+                0x00,
+                0x00,
+                OpCode::Return as u8
+            ]
+        );
+        assert_eq!(
+            artifact.funcs[0],
+            vec![
+                OpCode::Revert as u8,
+                0x02,
+                0x00,
+                OpCode::Revert as u8,
+                0x01,
+                0x00,
+                OpCode::Return as u8
+            ]
+        );
+        assert_eq!(artifact.reasons, vec!["no".to_owned(), "yes".to_owned()]);
+    }
+
+    #[test]
+    fn test_compile_revert_reason_too_long() {
+        let source = format!("function main() {{ revert(\"{}\"); }}", "x".repeat(70000));
+        assert_eq!(
+            compile_str(&source),
+            Err(CompilerErr::RevertReasonTooLong(Position::new(19, 1)))
+        );
+    }
+
+    #[test]
+    fn test_compile_strip_reasons() {
+        let tokens = tokenise("function main() { revert(\"no\"); }")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let program = parse(tokens).unwrap();
+        let mut compiler = Compiler::new();
+        compiler.set_strip_reasons(true);
+        compiler.push_function(&program.functions[0]).unwrap();
+
+        let artifact = Artifact::decode(&compiler.compile()).unwrap();
+        assert_eq!(
+            artifact.main,
+            vec![OpCode::Revert as u8, 0x01, 0x00, OpCode::Return as u8]
+        );
+        assert!(artifact.reasons.is_empty());
+        assert_eq!(compiler.revert_reasons(), ["no".to_owned()]);
+    }
+
+    #[test]
+    fn test_compile_loop_control_outside_loop() {
+        assert_eq!(
+            compile_str("function main() { break; }"),
+            Err(CompilerErr::BreakOutsideLoop(Position::new(19, 1)))
+        );
+        assert_eq!(
+            compile_str("function main() { if (true) { continue; } }"),
+            Err(CompilerErr::ContinueOutsideLoop(Position::new(31, 1)))
+        );
+    }
+
+    #[test]
+    fn test_compile_calls() {
+        let artifact = compile_str(
+            "function main(a) { log(a); return double(a); }
+            function double(a: u64) -> u64 { return add(a, a); }
+            function add(a: u64, b: u64) -> u64 { let c = a + b; return c; }
+            function log(a) {}",
+        )
+        .unwrap();
+        assert_eq!(
+            artifact.main,
+            vec![
+                OpCode::Load as u8,
+                0x00,
+                0x00,
+                OpCode::Call as u8,
+                0x03,
+                0x00,
+                0x01,
+                // Arguments of type `any` are checked against the parameter type
+                OpCode::Load as u8,
+                0x00,
+                0x00,
+                OpCode::Cast as u8,
+                0x04,
+                OpCode::Call as u8,
+                0x01,
+                0x00,
+                0x01,
+                OpCode::ReturnValue as u8,
+                OpCode::Return as u8
+            ]
+        );
+        assert_eq!(
+            artifact.funcs[0],
+            vec![
+                OpCode::Load as u8,
+                0x00,
+                0x00,
+                OpCode::Load as u8,
+                0x00,
+                0x00,
+                OpCode::Call as u8,
+                0x02,
+                0x00,
+                0x02,
+                OpCode::ReturnValue as u8,
+                OpCode::Return as u8
+            ]
+        );
+
+        // Locals follow the parameters in the frame of the callee
+        assert_eq!(
+            artifact.funcs[1],
+            vec![
+                OpCode::Load as u8,
+                0x00,
+                0x00,
+                OpCode::Load as u8,
+                0x01,
+                0x00,
+                OpCode::Add as u8,
+                OpCode::Store as u8,
+                0x02,
+                0x00,
+                OpCode::Load as u8,
+                0x02,
+                0x00,
+                OpCode::ReturnValue as u8,
+                OpCode::Return as u8
+            ]
+        );
+        assert_eq!(artifact.funcs[2], vec![OpCode::Return as u8]);
+    }
+
+    #[test]
+    fn test_compile_call_errors() {
+        assert_eq!(
+            compile_str("function main() { f(); }"),
+            Err(CompilerErr::UnknownFunction(Position::new(19, 1)))
+        );
+        assert_eq!(
+            compile_str("function f(a) {} function main() { f(); }"),
+            Err(CompilerErr::ArgumentCountMismatch(
+                1,
+                0,
+                Position::new(36, 1)
+            ))
+        );
+        assert_eq!(
+            compile_str("function f() {} function main() { return f(); }"),
+            Err(CompilerErr::NoReturnValue(Position::new(42, 1)))
+        );
+        assert_eq!(
+            compile_str("function f() {}\nfunction f() {}"),
+            Err(CompilerErr::DuplicateFunctionDeclaration(
+                Position::new(1, 2),
+                Position::new(1, 1)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_compile_limits() {
+        // Each parameter is on its own line, so that parameter `i` is on line `i + 2`
+        let params: Vec<_> = (0..300).map(|i| format!("p{}: u8", i)).collect();
+        let source = format!("function f(\n{}) {{}}", params.join(",\n"));
+        assert_eq!(
+            compile_str(&source),
+            Err(CompilerErr::TooManyParameters(Position::new(1, 257)))
+        );
+
+        let lets: String = (0..65536).map(|i| format!("let v{} = 0;\n", i)).collect();
+        let source = format!("function main() {{\n{}}}", lets);
+        assert_eq!(
+            compile_str(&source),
+            Err(CompilerErr::TooManyVariables(Position::new(1, 65537)))
+        );
+
+        let functions: String = (0..65536)
+            .map(|i| format!("function g{}() {{}}\n", i))
+            .collect();
+        assert_eq!(
+            compile_str(&functions),
+            Err(CompilerErr::TooManyFunctions(Position::new(1, 65536)))
+        );
+    }
+
+    #[test]
+    fn test_compile_return_value_must_match_return_type() {
+        assert_eq!(
+            compile_str("function f() { return 1; }"),
+            Err(CompilerErr::UnexpectedReturnValue(Position::new(23, 1)))
+        );
+        assert_eq!(
+            compile_str("function f() -> u8 { return; }"),
+            Err(CompilerErr::MissingReturnValue(Position::new(22, 1)))
+        );
+    }
+
+    #[test]
+    fn test_compile_arrays() {
+        let artifact = compile_str(
+            "function main(malleable sigs: [u8]) { let s = sigs.takeFront(64); return s[1..]; }",
+        )
+        .unwrap();
+        let mut expected = vec![OpCode::Load as u8, 0x00, 0x00];
+        expected.extend_from_slice(&[OpCode::PushConst as u8, 0x03, 0x40, 0x00, 0x00, 0x00]);
+        expected.push(OpCode::TakeFront as u8);
+        expected.extend_from_slice(&[OpCode::Store as u8, 0x00, 0x00]);
+        expected.extend_from_slice(&[OpCode::Store as u8, 0x01, 0x00]);
+        expected.extend_from_slice(&[OpCode::Load as u8, 0x01, 0x00]);
+        expected.extend_from_slice(&[OpCode::PushConst as u8, 0x03, 0x01, 0x00, 0x00, 0x00]);
+        expected.extend_from_slice(&[OpCode::Slice as u8, 0b01]);
+        expected.extend_from_slice(&[OpCode::ReturnValue as u8, OpCode::Return as u8]);
+        assert_eq!(artifact.main, expected);
+
+        // Fixed length arrays are cast to arrays of any length
+        let artifact = compile_str(
+            "function main(a: [u8; 2], b: [u8]) { return (a == b) as u8 + a.len() as u8 + b[0]; }",
+        )
+        .unwrap();
+        let mut expected = vec![OpCode::Load as u8, 0x00, 0x00];
+        expected.extend_from_slice(&[OpCode::Cast as u8, 0x12, 0x01]);
+        expected.extend_from_slice(&[OpCode::Load as u8, 0x01, 0x00, OpCode::Eq as u8]);
+        expected.extend_from_slice(&[OpCode::Cast as u8, 0x01]);
+        expected.extend_from_slice(&[OpCode::Load as u8, 0x00, 0x00, OpCode::Len as u8]);
+        expected.extend_from_slice(&[OpCode::Cast as u8, 0x01, OpCode::Add as u8]);
+        expected.extend_from_slice(&[OpCode::Load as u8, 0x01, 0x00]);
+        expected.extend_from_slice(&[OpCode::PushConst as u8, 0x03, 0x00, 0x00, 0x00, 0x00]);
+        expected.extend_from_slice(&[OpCode::Index as u8, OpCode::Add as u8]);
+        expected.extend_from_slice(&[OpCode::ReturnValue as u8, OpCode::Return as u8]);
+        assert_eq!(artifact.main, expected);
+    }
+
+    #[test]
+    fn test_compile_byte_strings() {
+        let artifact =
+            compile_str(r#"function main() { let a: [u8] = x"dead" + b"\n"; }"#).unwrap();
+        let mut expected = vec![OpCode::PushConst as u8, 0x13, 0x01];
+        expected.extend_from_slice(&[0x02, 0x00, 0x00, 0x00, 0xde, 0xad]);
+        expected.extend_from_slice(&[OpCode::PushConst as u8, 0x13, 0x01]);
+        expected.extend_from_slice(&[0x01, 0x00, 0x00, 0x00, b'\n']);
+        expected.extend_from_slice(&[OpCode::Add as u8, OpCode::Cast as u8, 0x12, 0x01]);
+        expected.extend_from_slice(&[OpCode::Store as u8, 0x00, 0x00]);
+        expected.push(OpCode::Return as u8);
+        assert_eq!(artifact.main, expected);
+    }
+
+    #[test]
+    fn test_compile_intrinsics() {
+        let artifact = compile_str(
+            "function main(a: address) { XPU.verifyEd25519(this.inputBytes, x\"\", x\"\", x\"\"); return XPU.pushOut(5, a, a); }",
+        );
+        assert!(matches!(artifact, Err(CompilerErr::TypeMismatch(_, _, _))));
+
+        let artifact = compile_str(
+            "function main(a: address, k: PublicKey) { XPU.verifyEd25519(this.inputBytes, x\"\", k, x\"\"); return XPU.pushOut(5, a, a); }",
+        )
+        .unwrap();
+        let mut expected = vec![OpCode::InputBytes as u8];
+        expected.extend_from_slice(&[OpCode::PushConst as u8, 0x13, 0x01, 0x00, 0x00, 0x00, 0x00]);
+        expected.extend_from_slice(&[OpCode::Cast as u8, 0x12, 0x01]);
+        expected.extend_from_slice(&[OpCode::Load as u8, 0x01, 0x00]);
+        expected.extend_from_slice(&[OpCode::PushConst as u8, 0x13, 0x01, 0x00, 0x00, 0x00, 0x00]);
+        expected.extend_from_slice(&[OpCode::Cast as u8, 0x12, 0x01]);
+        expected.push(OpCode::VerifyEd25519 as u8);
+        expected.extend_from_slice(&[OpCode::PushConst as u8, 0x0b]);
+        expected.extend_from_slice(&5i128.to_le_bytes());
+        expected.extend_from_slice(&[OpCode::Load as u8, 0x00, 0x00]);
+        expected.extend_from_slice(&[OpCode::Load as u8, 0x00, 0x00]);
+        expected.extend_from_slice(&[OpCode::PushOut as u8, OpCode::ReturnValue as u8]);
+        expected.push(OpCode::Return as u8);
+        assert_eq!(artifact.main, expected);
+    }
+
+    #[test]
+    fn test_compile_output_fields() {
+        let artifact =
+            compile_str("function main() { if (XPU.getOutputAt(0) == this) { return XPU.pushOut(this); } return this.input.outputHash; }")
+                .unwrap();
+        let mut expected = vec![OpCode::PushConst as u8, 0x03, 0x00, 0x00, 0x00, 0x00];
+        expected.extend_from_slice(&[
+            OpCode::GetOutputAt as u8,
+            OpCode::This as u8,
+            OpCode::Eq as u8,
+        ]);
+        expected.extend_from_slice(&[OpCode::JumpIfFalse as u8, 0x11, 0x00, 0x00, 0x00]);
+        expected.extend_from_slice(&[
+            OpCode::This as u8,
+            OpCode::PushOutput as u8,
+            OpCode::ReturnValue as u8,
+        ]);
+        expected.extend_from_slice(&[
+            OpCode::This as u8,
+            OpCode::OutputInput as u8,
+            OpCode::InputOutputHash as u8,
+        ]);
+        expected.extend_from_slice(&[OpCode::ReturnValue as u8, OpCode::Return as u8]);
+        assert_eq!(artifact.main, expected);
+    }
+
+    #[test]
+    fn test_compile_unsupported_expression() {
+        assert_eq!(
+            compile_str("function main() { return 'a'; }"),
+            Err(CompilerErr::UnsupportedExpression(Position::new(26, 1)))
+        );
+    }
 }
@@ -0,0 +1,633 @@
+//! Rendering of compiler errors as source annotated diagnostics.
+
+use crate::compiler::CompilerErr;
+use crate::lexer::{LexError, Position};
+use crate::source_map::SourceMap;
+use std::fmt::Write;
+
+/// Number of spaces tabs are rendered as in source excerpts.
+const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    /// Stable error code, e.g. `E0200`
+    pub code: &'static str,
+
+    /// Summary of the error
+    pub message: String,
+
+    /// Where the error occured
+    pub primary: Label,
+
+    /// Other locations which help explaining the error
+    pub secondary: Vec<Label>,
+
+    /// Free form notes rendered after the source snippets
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Label {
+    pub position: Position,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, position: Position) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            primary: Label {
+                position,
+                message: String::new(),
+            },
+            secondary: vec![],
+            notes: vec![],
+        }
+    }
+
+    /// Sets the message shown next to the primary location.
+    pub fn with_label(mut self, message: impl Into<String>) -> Self {
+        self.primary.message = message.into();
+        self
+    }
+
+    pub fn with_secondary(mut self, position: Position, message: impl Into<String>) -> Self {
+        self.secondary.push(Label {
+            position,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Renders the diagnostic against the sources its labels refer to.
+    /// Labels are underlined along their whole span. Labels in another file
+    /// than the primary one get a header naming their file.
+    ///
+    /// ```text
+    /// error[E0200]: duplicate declaration of `main`
+    ///  --> main.ps:4:1
+    ///   |
+    /// 4 | function main() {}
+    ///   | ^^^^^^^^ `main` declared again here
+    ///   |
+    /// 1 | function main() {}
+    ///   | -------- first `main` declared here
+    /// ```
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let gutter = std::iter::once(&self.primary)
+            .chain(self.secondary.iter())
+            .map(|label| label.position.line.to_string().len())
+            .max()
+            .unwrap();
+
+        let mut out = String::new();
+        writeln!(out, "error[{}]: {}", self.code, self.message).unwrap();
+        writeln!(
+            out,
+            "{:gutter$}--> {}",
+            "",
+            location(sources, &self.primary.position),
+            gutter = gutter
+        )
+        .unwrap();
+        writeln!(out, "{:gutter$} |", "", gutter = gutter).unwrap();
+        render_label(&mut out, sources, &self.primary, '^', gutter);
+
+        for label in self.secondary.iter() {
+            if label.position.file != self.primary.position.file {
+                writeln!(
+                    out,
+                    "{:gutter$}::: {}",
+                    "",
+                    location(sources, &label.position),
+                    gutter = gutter
+                )
+                .unwrap();
+            }
+            writeln!(out, "{:gutter$} |", "", gutter = gutter).unwrap();
+            render_label(&mut out, sources, label, '-', gutter);
+        }
+
+        for note in self.notes.iter() {
+            writeln!(out, "{:gutter$} = note: {}", "", note, gutter = gutter).unwrap();
+        }
+
+        out
+    }
+}
+
+fn location(sources: &SourceMap, position: &Position) -> String {
+    let name = sources
+        .get(position.file)
+        .map_or("<unknown>", |file| file.name.as_str());
+    format!("{}:{}:{}", name, position.line, position.column)
+}
+
+fn render_label(out: &mut String, sources: &SourceMap, label: &Label, marker: char, gutter: usize) {
+    let source = sources
+        .get(label.position.file)
+        .map_or("", |file| file.source.as_str());
+    let line = source
+        .lines()
+        .nth(label.position.line.saturating_sub(1))
+        .unwrap_or_default();
+    writeln!(
+        out,
+        "{:>gutter$} | {}",
+        label.position.line,
+        line.replace('\t', &" ".repeat(TAB_WIDTH)),
+        gutter = gutter
+    )
+    .unwrap();
+
+    // Underline the span up to the end of its first line
+    let width = source
+        .get(label.position.span())
+        .map_or(0, |span| {
+            display_width(span.chars().take_while(|c| *c != '\r' && *c != '\n'))
+        })
+        .max(1);
+    let indent = display_width(line.chars().take(label.position.column.saturating_sub(1)));
+    let annotation = format!(
+        "{:indent$}{} {}",
+        "",
+        marker.to_string().repeat(width),
+        label.message,
+        indent = indent
+    );
+    writeln!(
+        out,
+        "{:gutter$} | {}",
+        "",
+        annotation.trim_end(),
+        gutter = gutter
+    )
+    .unwrap();
+}
+
+/// Width of rendered source text, in which tabs are `TAB_WIDTH` spaces.
+fn display_width(chars: impl Iterator<Item = char>) -> usize {
+    chars.map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum()
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(err: &LexError) -> Self {
+        let position = err.position().clone();
+        match err {
+            LexError::UnterminatedString(_) => {
+                Diagnostic::error("E0001", "unterminated string literal", position)
+                    .with_label("string starts here")
+            }
+            LexError::UnterminatedBlockComment(_) => {
+                Diagnostic::error("E0002", "unterminated block comment", position)
+                    .with_label("comment starts here")
+                    .with_note("block comments must be closed with `*/`")
+            }
+            LexError::InvalidCharacter(character, _) => Diagnostic::error(
+                "E0003",
+                format!("invalid character `{}`", character.escape_default()),
+                position,
+            )
+            .with_label("this character cannot start a token"),
+            LexError::MalformedNumber(_) => {
+                Diagnostic::error("E0004", "malformed number literal", position)
+                    .with_label("invalid number")
+            }
+            LexError::InvalidNumberSuffix(suffix, _) => Diagnostic::error(
+                "E0005",
+                format!("invalid suffix `{}` for number literal", suffix),
+                position,
+            )
+            .with_label("invalid suffix")
+            .with_note(
+                "suffixes are integer types, or `f32` and `f64` for numbers with a fractional part",
+            ),
+            LexError::NonAsciiIdentifier(character, _) => Diagnostic::error(
+                "E0008",
+                format!(
+                    "non-ASCII character `{}` in identifier",
+                    character.escape_default()
+                ),
+                position,
+            )
+            .with_label("invalid identifier")
+            .with_note("identifiers consist of ASCII letters, digits, `_` and `$`"),
+            LexError::InvalidEscape(_) => {
+                Diagnostic::error("E0006", "invalid escape sequence", position)
+                    .with_label("unknown escape")
+                    .with_note(
+                        "escapes are `\\n`, `\\r`, `\\t`, `\\0`, `\\\\`, `\\\"`, `\\'`, `\\xHH` and `\\u{H..}`, \
+                         and `\\xHH` is ASCII outside of byte strings",
+                    )
+            }
+            LexError::InvalidHexLiteral(_) => {
+                Diagnostic::error("E0007", "invalid hex literal", position)
+                    .with_label("invalid hex literal")
+                    .with_note("hex literals consist of pairs of hex digits, e.g. `x\"00ff\"`")
+            }
+        }
+    }
+}
+
+impl From<&CompilerErr> for Diagnostic {
+    fn from(err: &CompilerErr) -> Self {
+        match err {
+            CompilerErr::Lex(err) => err.into(),
+            CompilerErr::ExpectedFunctionDefinition(position) => {
+                Diagnostic::error("E0100", "expected a function definition", position.clone())
+                    .with_label("expected `function`")
+                    .with_note("only imports and function definitions are allowed at the top level")
+            }
+            CompilerErr::ExpectedIdentifier(position) => {
+                Diagnostic::error("E0101", "expected an identifier", position.clone())
+                    .with_label("expected an identifier")
+            }
+            CompilerErr::ExpectedLeftParanthesis(position) => {
+                Diagnostic::error("E0102", "expected `(`", position.clone())
+                    .with_label("expected `(`")
+            }
+            CompilerErr::ExpectedRightParanthesis(position) => {
+                Diagnostic::error("E0103", "expected `)`", position.clone())
+                    .with_label("expected `)`")
+            }
+            CompilerErr::ExpectedRightBracket(position) => {
+                Diagnostic::error("E0104", "expected `]`", position.clone())
+                    .with_label("expected `]`")
+            }
+            CompilerErr::ExpectedColonCommaOrRightParanthesis(position) => {
+                Diagnostic::error("E0105", "expected `:`, `,` or `)`", position.clone())
+                    .with_label("expected a type annotation or the next argument")
+            }
+            CompilerErr::ExpectedCommaOrRightParanthesis(position) => {
+                Diagnostic::error("E0106", "expected `,` or `)`", position.clone())
+                    .with_label("expected the next argument or the end of the list")
+            }
+            CompilerErr::ExpectedLeftBrace(position) => {
+                Diagnostic::error("E0107", "expected `{`", position.clone())
+                    .with_label("expected the function body")
+            }
+            CompilerErr::ExpectedSemicolon(position) => {
+                Diagnostic::error("E0108", "expected `;`", position.clone())
+                    .with_label("expected `;` before this")
+            }
+            CompilerErr::ExpectedType(position) => {
+                Diagnostic::error("E0109", "expected a type", position.clone())
+                    .with_label("not a type")
+            }
+            CompilerErr::ExpectedImportPath(position) => {
+                Diagnostic::error("E0112", "expected an import path", position.clone())
+                    .with_label("expected a string")
+            }
+            CompilerErr::ExpectedRevertReason(position) => {
+                Diagnostic::error("E0113", "expected a revert reason", position.clone())
+                    .with_label("expected a string")
+            }
+            CompilerErr::ExpectedEqual(position) => {
+                Diagnostic::error("E0114", "expected `=`", position.clone())
+                    .with_label("variables must be initialized")
+            }
+            CompilerErr::InvalidAssignmentTarget(position) => {
+                Diagnostic::error("E0115", "invalid assignment target", position.clone())
+                    .with_label("cannot assign to this expression")
+                    .with_note("only variables can be assigned to")
+            }
+            CompilerErr::ExpectedArrayLength(position) => {
+                Diagnostic::error("E0116", "expected an array length", position.clone())
+                    .with_label("expected an integer")
+            }
+            CompilerErr::TypeNestedTooDeeply(position) => {
+                Diagnostic::error("E0117", "type nested too deeply", position.clone())
+                    .with_label("array type number 33")
+                    .with_note("array types can be nested at most 32 deep")
+            }
+            CompilerErr::NestedTooDeeply(position) => {
+                Diagnostic::error("E0118", "expression nested too deeply", position.clone())
+                    .with_label("nested in 256 expressions and blocks")
+                    .with_note(
+                        "expressions and blocks can be nested at most 256 deep, \
+                         operators chained without parentheses included",
+                    )
+            }
+            CompilerErr::UnexpectedToken(position) => {
+                Diagnostic::error("E0110", "unexpected token", position.clone())
+                    .with_label("expected an expression")
+            }
+            CompilerErr::UnexpectedEndOfInput(position) => {
+                Diagnostic::error("E0111", "unexpected end of input", position.clone())
+                    .with_label("input ends after this")
+            }
+            CompilerErr::DuplicateMainDeclaration(position, first) => {
+                Diagnostic::error("E0200", "duplicate declaration of `main`", position.clone())
+                    .with_label("`main` declared again here")
+                    .with_secondary(first.clone(), "first `main` declared here")
+            }
+            CompilerErr::MalleableArgOutsideMain(position) => Diagnostic::error(
+                "E0201",
+                "malleable argument outside of `main`",
+                position.clone(),
+            )
+            .with_label("argument declared as malleable")
+            .with_note("only arguments of `main` can be malleable"),
+            CompilerErr::UnknownIdentifier(position) => {
+                Diagnostic::error("E0202", "unknown identifier", position.clone())
+                    .with_label("not found in this scope")
+            }
+            CompilerErr::InvalidNumberLiteral(position) => {
+                Diagnostic::error("E0203", "invalid number literal", position.clone())
+                    .with_label("number does not fit its type")
+            }
+            CompilerErr::UnsupportedExpression(position) => {
+                Diagnostic::error("E0204", "unsupported expression", position.clone())
+                    .with_label("this expression cannot be compiled yet")
+            }
+            CompilerErr::BreakOutsideLoop(position) => {
+                Diagnostic::error("E0205", "`break` outside of a loop", position.clone())
+                    .with_label("cannot `break` here")
+            }
+            CompilerErr::ContinueOutsideLoop(position) => {
+                Diagnostic::error("E0206", "`continue` outside of a loop", position.clone())
+                    .with_label("cannot `continue` here")
+            }
+            CompilerErr::DuplicateFunctionDeclaration(position, first) => Diagnostic::error(
+                "E0207",
+                "duplicate declaration of a function",
+                position.clone(),
+            )
+            .with_label("function declared again here")
+            .with_secondary(first.clone(), "first declared here"),
+            CompilerErr::UnknownFunction(position) => {
+                Diagnostic::error("E0208", "unknown function", position.clone())
+                    .with_label("no function with this name")
+            }
+            CompilerErr::ArgumentCountMismatch(expected, found, position) => Diagnostic::error(
+                "E0209",
+                format!(
+                    "function takes {} argument{} but {} were supplied",
+                    expected,
+                    if *expected == 1 { "" } else { "s" },
+                    found
+                ),
+                position.clone(),
+            )
+            .with_label("wrong number of arguments"),
+            CompilerErr::NoReturnValue(position) => Diagnostic::error(
+                "E0210",
+                "function does not return a value",
+                position.clone(),
+            )
+            .with_label("this call has no value")
+            .with_note("declare a return type with `-> <type>`"),
+            CompilerErr::MissingReturnValue(position) => {
+                Diagnostic::error("E0211", "missing return value", position.clone())
+                    .with_label("the function declares a return type")
+            }
+            CompilerErr::UnexpectedReturnValue(position) => {
+                Diagnostic::error("E0212", "unexpected return value", position.clone())
+                    .with_label("the function has no return type")
+                    .with_note("declare a return type with `-> <type>`")
+            }
+            CompilerErr::InvalidTestSignature(position) => Diagnostic::error(
+                "E0213",
+                "invalid signature of a test function",
+                position.clone(),
+            )
+            .with_label("declared here")
+            .with_note("test functions take no parameters and return nothing"),
+            CompilerErr::TooManyRevertReasons(position) => Diagnostic::error(
+                "E0214",
+                "too many distinct revert reasons",
+                position.clone(),
+            )
+            .with_label("reason number 65536")
+            .with_note("a script can have at most 65535 distinct revert reasons"),
+            CompilerErr::TooManyParameters(position) => Diagnostic::error(
+                "E0215",
+                "too many parameters",
+                position.clone(),
+            )
+            .with_label("parameter number 256")
+            .with_note("a function can have at most 255 parameters"),
+            CompilerErr::TooManyFunctions(position) => Diagnostic::error(
+                "E0216",
+                "too many functions",
+                position.clone(),
+            )
+            .with_label("function number 65536")
+            .with_note("a script can have at most 65535 functions besides `main`, tests included"),
+            CompilerErr::TooManyVariables(position) => Diagnostic::error(
+                "E0217",
+                "too many variables",
+                position.clone(),
+            )
+            .with_label("variable number 65536")
+            .with_note("a function can have at most 65535 variables in scope, parameters included"),
+            CompilerErr::RevertReasonTooLong(position) => Diagnostic::error(
+                "E0218",
+                "revert reason too long",
+                position.clone(),
+            )
+            .with_label("reverts here")
+            .with_note("a revert reason can have at most 65535 bytes"),
+            CompilerErr::FunctionTooLong(position) => Diagnostic::error(
+                "E0219",
+                "function too long",
+                position.clone(),
+            )
+            .with_label("declared here")
+            .with_note("the bytecode of a function can have at most 4294967295 bytes"),
+            CompilerErr::UnresolvedImport(path, position) => Diagnostic::error(
+                "E0300",
+                format!("cannot read imported file `{}`", path),
+                position.clone(),
+            )
+            .with_label("imported here")
+            .with_note("import paths are relative to the importing file"),
+            CompilerErr::ImportedMain(position) => {
+                Diagnostic::error("E0301", "imported file declares `main`", position.clone())
+                    .with_label("imported here")
+                    .with_note(
+                        "files declaring `main` are compiled on their own and cannot be imported",
+                    )
+            }
+            CompilerErr::TypeMismatch(expected, found, position) => Diagnostic::error(
+                "E0400",
+                format!(
+                    "mismatched types: expected `{}`, found `{}`",
+                    expected, found
+                ),
+                position.clone(),
+            )
+            .with_label(format!("expected `{}`", expected))
+            .with_note(
+                "only conversions which cannot lose information are implicit, use `as` to convert",
+            ),
+            CompilerErr::InvalidOperandType(ty, position) => Diagnostic::error(
+                "E0401",
+                format!("invalid operand of type `{}`", ty),
+                position.clone(),
+            )
+            .with_label("the operator does not accept this type"),
+            CompilerErr::InvalidCast(from, to, position) => Diagnostic::error(
+                "E0402",
+                format!("cannot cast `{}` as `{}`", from, to),
+                position.clone(),
+            )
+            .with_label("invalid cast")
+            .with_note("casts are allowed between numeric types, between arrays, and between addresses or assets and their bytes"),
+            CompilerErr::LiteralOutOfRange(ty, position) => Diagnostic::error(
+                "E0403",
+                format!("literal out of range for `{}`", ty),
+                position.clone(),
+            )
+            .with_label("does not fit the type"),
+            CompilerErr::UnknownMember(object, member, position) => Diagnostic::error(
+                "E0404",
+                format!("no member `{}` on `{}`", member, object),
+                position.clone(),
+            )
+            .with_label("unknown member"),
+            CompilerErr::MethodNotCalled(method, position) => Diagnostic::error(
+                "E0405",
+                format!("method `{}` is not called", method),
+                position.clone(),
+            )
+            .with_label("method used as a value")
+            .with_note("add parentheses to call the method"),
+            CompilerErr::NotAMethod(field, position) => Diagnostic::error(
+                "E0406",
+                format!("`{}` is not a method", field),
+                position.clone(),
+            )
+            .with_label("cannot be called")
+            .with_note("remove the parentheses to access the field"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Diagnostic;
+    use crate::compiler::CompilerErr;
+    use crate::lexer::{tokenise, LexError, Position};
+    use crate::source_map::SourceMap;
+
+    fn sources(source: &str) -> SourceMap {
+        let mut sources = SourceMap::new();
+        sources.add("main.ps", source);
+        sources
+    }
+
+    #[test]
+    fn test_render_primary_label() {
+        let source = "function main() {\n    return b;\n}";
+        let err = CompilerErr::UnknownIdentifier(Position::new(12, 2));
+        assert_eq!(
+            Diagnostic::from(&err).render(&sources(source)),
+            "error[E0202]: unknown identifier
+ --> main.ps:2:12
+  |
+2 |     return b;
+  |            ^ not found in this scope
+"
+        );
+    }
+
+    #[test]
+    fn test_render_span() {
+        let source = "function main() {\n    return 0b12;\n}";
+        let err = CompilerErr::from(tokenise(source).find_map(Result::err).unwrap());
+        assert_eq!(
+            Diagnostic::from(&err).render(&sources(source)),
+            "error[E0004]: malformed number literal
+ --> main.ps:2:12
+  |
+2 |     return 0b12;
+  |            ^^^^ invalid number
+"
+        );
+    }
+
+    #[test]
+    fn test_render_tabs_and_crlf() {
+        let source = "function main() {\r\n\treturn\t0b12;\r\n}";
+        let err = CompilerErr::from(tokenise(source).find_map(Result::err).unwrap());
+        assert_eq!(err.position(), &Position::new(9, 2));
+        assert_eq!(
+            Diagnostic::from(&err).render(&sources(source)),
+            "error[E0004]: malformed number literal
+ --> main.ps:2:9
+  |
+2 |     return    0b12;
+  |               ^^^^ invalid number
+"
+        );
+
+        let source = "function main() {\r\n  /* a\r\n  b";
+        let err = CompilerErr::from(tokenise(source).find_map(Result::err).unwrap());
+        assert_eq!(
+            Diagnostic::from(&err).render(&sources(source)),
+            "error[E0002]: unterminated block comment
+ --> main.ps:2:3
+  |
+2 |   /* a
+  |   ^^^^ comment starts here
+  = note: block comments must be closed with `*/`
+"
+        );
+    }
+
+    #[test]
+    fn test_render_secondary_label_in_other_file() {
+        let mut sources = sources("\nfunction main() {}");
+        sources.add("lib.ps", "function main() {}");
+        let duplicate = Position {
+            file: 1,
+            start: 0,
+            end: 8,
+            ..Position::new(1, 1)
+        };
+        let first = Position {
+            start: 1,
+            end: 9,
+            ..Position::new(1, 2)
+        };
+        let rendered = Diagnostic::from(&CompilerErr::DuplicateMainDeclaration(duplicate, first))
+            .with_note("each script has a single entry point")
+            .render(&sources);
+        assert_eq!(
+            rendered,
+            "error[E0200]: duplicate declaration of `main`
+ --> lib.ps:1:1
+  |
+1 | function main() {}
+  | ^^^^^^^^ `main` declared again here
+ ::: main.ps:2:1
+  |
+2 | function main() {}
+  | -------- first `main` declared here
+  = note: each script has a single entry point
+"
+        );
+    }
+
+    #[test]
+    fn test_render_gutter_width() {
+        let source = "\n".repeat(9) + "  @";
+        let err = CompilerErr::Lex(LexError::InvalidCharacter('@', Position::new(3, 10)));
+        assert_eq!(
+            Diagnostic::from(&err).render(&sources(&source)),
+            "error[E0003]: invalid character `@`
+  --> main.ps:10:3
+   |
+10 |   @
+   |   ^ this character cannot start a token
+"
+        );
+    }
+}
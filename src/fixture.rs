@@ -0,0 +1,595 @@
+//! Transaction fixtures for running scripts locally.
+//!
+//! A fixture describes the transaction a script runs in and the arguments of
+//! its main function, in JSON or TOML:
+//!
+//! ```toml
+//! block_timestamp = 50
+//! input_bytes = "696e707574"
+//! this = 0
+//!
+//! [keys]
+//! taker = "0707070707070707070707070707070707070707070707070707070707070707"
+//!
+//! [[outputs]]
+//! amount = 500
+//! colour_hash = "09"
+//!
+//! [[outputs]]
+//! amount = 100
+//! input = { public_key = "taker" }
+//!
+//! [args]
+//! rate = "2.5"
+//! signature = { key = "taker", message = "696e707574" }
+//! ```
+//!
+//! Bytes are written in hex. `keys` names Ed25519 secret keys, which public
+//! keys and signatures can refer to by name. See `docs/fixtures.md` for the
+//! full format.
+
+use crate::ast::{Param, ValueType};
+use crate::compiler::DECIMAL_SCALE;
+use crate::number::Number;
+use crate::vm::{Context, Input, Output, Value};
+use ed25519_dalek::{Signer, SigningKey};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fixture {
+    #[serde(default)]
+    pub block_timestamp: i64,
+
+    /// Secret keys by name
+    #[serde(default)]
+    pub keys: BTreeMap<String, String>,
+
+    /// Bytes of the input spending `this`
+    pub input_bytes: Option<FixtureValue>,
+
+    /// The output whose script runs, or the index of an output
+    pub this: Option<ThisFixture>,
+
+    #[serde(default)]
+    pub outputs: Vec<OutputFixture>,
+
+    /// Arguments of main by name
+    #[serde(default)]
+    pub args: BTreeMap<String, FixtureValue>,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ThisFixture {
+    Index(usize),
+    Output(Box<OutputFixture>),
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputFixture {
+    pub amount: Option<FixtureValue>,
+    pub colour_hash: Option<FixtureValue>,
+    pub coloured_address: Option<FixtureValue>,
+    pub script_hash: Option<FixtureValue>,
+    pub hash: Option<FixtureValue>,
+
+    #[serde(default)]
+    pub input: InputFixture,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InputFixture {
+    pub public_key: Option<FixtureValue>,
+    pub output_hash: Option<FixtureValue>,
+}
+
+/// A value as written in a fixture, which is converted to the type it is
+/// used as.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum FixtureValue {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+
+    /// Numbers which do not fit `i64`, decimals, or hex bytes
+    String(String),
+
+    Signature(SignatureFixture),
+
+    /// Elements of arrays. Bytes may also be given as a list of hex strings
+    /// and signatures, which are concatenated.
+    Array(Vec<FixtureValue>),
+}
+
+/// Signature of `message`, in hex, by the secret key `key`.
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignatureFixture {
+    pub key: String,
+    pub message: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum FixtureErr {
+    /// The fixture is not valid JSON or TOML
+    Syntax(String),
+
+    /// Main has an argument which the fixture does not give
+    MissingArg(String),
+
+    /// The fixture gives an argument which main does not have
+    UnknownArg(String),
+
+    /// A value cannot be converted to its type. Holds where the value is
+    /// and its type.
+    InvalidValue(String, ValueType),
+
+    /// `this` is the index of an output which does not exist
+    InvalidThis(usize),
+
+    /// An invalid secret key, or the name of an unknown key
+    InvalidKey(String),
+}
+
+impl fmt::Display for FixtureErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixtureErr::Syntax(err) => write!(f, "{}", err),
+            FixtureErr::MissingArg(name) => write!(f, "missing argument `{}`", name),
+            FixtureErr::UnknownArg(name) => write!(f, "main has no argument `{}`", name),
+            FixtureErr::InvalidValue(path, ty) => {
+                write!(f, "invalid value for `{}`, expected `{}`", path, ty)
+            }
+            FixtureErr::InvalidThis(index) => {
+                write!(f, "`this` refers to output {}, which does not exist", index)
+            }
+            FixtureErr::InvalidKey(key) => write!(f, "invalid or unknown key `{}`", key),
+        }
+    }
+}
+
+impl Fixture {
+    pub fn from_json(text: &str) -> Result<Self, FixtureErr> {
+        serde_json::from_str(text).map_err(|err| FixtureErr::Syntax(err.to_string()))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, FixtureErr> {
+        toml::from_str(text).map_err(|err| FixtureErr::Syntax(err.to_string()))
+    }
+
+    /// Returns the transaction described by the fixture.
+    ///
+    /// Outputs without a hash are told apart by their position: the hash of
+    /// the output at index `i` is `i + 1` as a little-endian integer.
+    pub fn context(&self) -> Result<Context, FixtureErr> {
+        let outputs = self
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(i, output)| {
+                let mut hash = [0; 32];
+                hash[..8].copy_from_slice(&(i as u64 + 1).to_le_bytes());
+                self.output(output, &format!("outputs[{}]", i), hash)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let this = match &self.this {
+            None => Output::default(),
+            Some(ThisFixture::Index(index)) => outputs
+                .get(*index)
+                .cloned()
+                .ok_or(FixtureErr::InvalidThis(*index))?,
+            Some(ThisFixture::Output(output)) => self.output(output, "this", [0; 32])?,
+        };
+
+        let input_bytes = match &self.input_bytes {
+            Some(value) => self.bytes(value, "input_bytes", &bytes_type())?,
+            None => vec![],
+        };
+
+        Ok(Context {
+            block_timestamp: self.block_timestamp,
+            outputs,
+            this,
+            input_bytes,
+        })
+    }
+
+    /// Returns the arguments of a main function with the given parameters.
+    pub fn args(&self, params: &[Param]) -> Result<Vec<Value>, FixtureErr> {
+        if let Some(name) = self
+            .args
+            .keys()
+            .find(|name| !params.iter().any(|param| param.name == **name))
+        {
+            return Err(FixtureErr::UnknownArg(name.clone()));
+        }
+
+        params
+            .iter()
+            .map(|param| match self.args.get(&param.name) {
+                Some(value) => self.value(value, &format!("args.{}", param.name), &param.ty),
+                None => Err(FixtureErr::MissingArg(param.name.clone())),
+            })
+            .collect()
+    }
+
+    fn output(
+        &self,
+        output: &OutputFixture,
+        path: &str,
+        hash: [u8; 32],
+    ) -> Result<Output, FixtureErr> {
+        let amount = match &output.amount {
+            Some(amount) => self.value(amount, &format!("{}.amount", path), &ValueType::I128)?,
+            None => Value::Int(ValueType::I128, BigInt::ZERO),
+        };
+        let amount = match amount {
+            Value::Int(_, amount) => amount.to_i128().unwrap(),
+            _ => unreachable!("amounts are `i128`"),
+        };
+
+        let bytes = |value: &Option<FixtureValue>, field: &str| match value {
+            Some(value) => self.bytes(value, &format!("{}.{}", path, field), &bytes_type()),
+            None => Ok(vec![]),
+        };
+
+        Ok(Output {
+            amount,
+            colour_hash: bytes(&output.colour_hash, "colour_hash")?,
+            coloured_address: bytes(&output.coloured_address, "coloured_address")?,
+            script_hash: bytes(&output.script_hash, "script_hash")?,
+            hash: match &output.hash {
+                Some(value) => self.bytes32(value, &format!("{}.hash", path), &ValueType::Hash)?,
+                None => hash,
+            },
+            input: Input {
+                public_key: match &output.input.public_key {
+                    Some(value) => self.public_key(value, &format!("{}.input.public_key", path))?,
+                    None => [0; 32],
+                },
+                output_hash: match &output.input.output_hash {
+                    Some(value) => self.bytes32(
+                        value,
+                        &format!("{}.input.output_hash", path),
+                        &ValueType::Hash,
+                    )?,
+                    None => [0; 32],
+                },
+            },
+        })
+    }
+
+    /// Converts a value to `ty`. `path` tells where the value is, for errors.
+    fn value(&self, value: &FixtureValue, path: &str, ty: &ValueType) -> Result<Value, FixtureErr> {
+        let invalid = || FixtureErr::InvalidValue(path.to_owned(), ty.clone());
+
+        let converted = match (value, ty) {
+            (_, ValueType::Any) => return self.any(value, path),
+            (FixtureValue::Bool(value), ValueType::Bool) => Value::Bool(*value),
+            (_, ValueType::F32) => {
+                let (number, negative) = signed_number(value).ok_or_else(invalid)?;
+                Value::F32(number.to_f32(negative))
+            }
+            (_, ValueType::F64) => {
+                let (number, negative) = signed_number(value).ok_or_else(invalid)?;
+                Value::F64(number.to_f64(negative))
+            }
+            (_, ValueType::Decimal) => {
+                let (number, negative) = signed_number(value).ok_or_else(invalid)?;
+                let scaled = number.scaled(negative, DECIMAL_SCALE);
+                Value::Decimal(
+                    scaled
+                        .and_then(|value| value.to_i128())
+                        .ok_or_else(invalid)?,
+                )
+            }
+            (_, ValueType::Address) => Value::Address(self.bytes(value, path, ty)?),
+            (_, ValueType::Asset) => Value::Asset(self.bytes(value, path, ty)?),
+            (_, ValueType::PublicKey) => Value::PublicKey(self.public_key(value, path)?),
+            (_, ValueType::Hash) => Value::Hash(self.bytes32(value, path, ty)?),
+            (_, ValueType::Array(element) | ValueType::FixedArray(element, _))
+                if **element == ValueType::U8 =>
+            {
+                Value::bytes(&self.bytes(value, path, ty)?)
+            }
+            (FixtureValue::Array(elements), ValueType::Array(element)) => Value::Array(
+                elements
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| self.value(value, &format!("{}[{}]", path, i), element))
+                    .collect::<Result<_, _>>()?,
+            ),
+            (FixtureValue::Array(elements), ValueType::FixedArray(element, len))
+                if elements.len() == *len as usize =>
+            {
+                let array = ValueType::Array(element.clone());
+                return self.value(value, path, &array);
+            }
+            _ => {
+                let (number, negative) = signed_number(value).ok_or_else(invalid)?;
+                let value = number.to_integer(negative).ok_or_else(invalid)?;
+                Value::int(ty.clone(), value).ok_or_else(invalid)?
+            }
+        };
+
+        // Bytes of a fixed length are checked after conversion
+        if let ValueType::FixedArray(_, len) = ty {
+            if !matches!(&converted, Value::Array(elements) if elements.len() == *len as usize) {
+                return Err(invalid());
+            }
+        }
+
+        Ok(converted)
+    }
+
+    /// Converts a value given for an argument of type `any`. Integers are
+    /// `i64` and numbers with a fraction `decimal`, like literals, and
+    /// strings are bytes.
+    fn any(&self, value: &FixtureValue, path: &str) -> Result<Value, FixtureErr> {
+        let ty = match value {
+            FixtureValue::Bool(_) => ValueType::Bool,
+            FixtureValue::Int(_) | FixtureValue::UInt(_) => ValueType::I64,
+            FixtureValue::Float(_) => ValueType::Decimal,
+            FixtureValue::String(_) | FixtureValue::Signature(_) => bytes_type(),
+            FixtureValue::Array(_) => ValueType::Array(Box::new(ValueType::Any)),
+        };
+        self.value(value, path, &ty)
+    }
+
+    /// Converts hex strings, signatures and lists of them to bytes. Lists of
+    /// numbers are converted to a byte each.
+    fn bytes(
+        &self,
+        value: &FixtureValue,
+        path: &str,
+        ty: &ValueType,
+    ) -> Result<Vec<u8>, FixtureErr> {
+        let invalid = || FixtureErr::InvalidValue(path.to_owned(), ty.clone());
+
+        match value {
+            FixtureValue::String(text) => {
+                hex::decode(text.strip_prefix("0x").unwrap_or(text)).map_err(|_| invalid())
+            }
+            FixtureValue::Signature(SignatureFixture { key, message }) => {
+                let message = hex::decode(message.strip_prefix("0x").unwrap_or(message))
+                    .map_err(|_| invalid())?;
+                Ok(self.key(key)?.sign(&message).to_bytes().to_vec())
+            }
+            FixtureValue::Array(elements) => {
+                let mut bytes = vec![];
+                for (i, element) in elements.iter().enumerate() {
+                    let path = format!("{}[{}]", path, i);
+                    match element {
+                        FixtureValue::Int(_) | FixtureValue::UInt(_) => {
+                            let byte = self.value(element, &path, &ValueType::U8)?;
+                            bytes.push(match byte {
+                                Value::Int(_, byte) => byte.to_u8().unwrap(),
+                                _ => unreachable!("bytes are `u8`"),
+                            });
+                        }
+                        _ => bytes.extend(self.bytes(element, &path, ty)?),
+                    }
+                }
+                Ok(bytes)
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    fn bytes32(
+        &self,
+        value: &FixtureValue,
+        path: &str,
+        ty: &ValueType,
+    ) -> Result<[u8; 32], FixtureErr> {
+        self.bytes(value, path, ty)?
+            .try_into()
+            .map_err(|_| FixtureErr::InvalidValue(path.to_owned(), ty.clone()))
+    }
+
+    /// Public keys are given in hex or as the name of a secret key.
+    fn public_key(&self, value: &FixtureValue, path: &str) -> Result<[u8; 32], FixtureErr> {
+        match value {
+            FixtureValue::String(name) if self.keys.contains_key(name) => {
+                Ok(self.key(name)?.verifying_key().to_bytes())
+            }
+            _ => self.bytes32(value, path, &ValueType::PublicKey),
+        }
+    }
+
+    /// Returns the secret key named `key`, or given in hex.
+    fn key(&self, key: &str) -> Result<SigningKey, FixtureErr> {
+        let text = self.keys.get(key).map_or(key, String::as_str);
+        let bytes = hex::decode(text.strip_prefix("0x").unwrap_or(text))
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .ok_or_else(|| FixtureErr::InvalidKey(key.to_owned()))?;
+        Ok(SigningKey::from_bytes(&bytes))
+    }
+}
+
+fn bytes_type() -> ValueType {
+    ValueType::Array(Box::new(ValueType::U8))
+}
+
+/// Parses a number, which may be given as a string to hold values which do
+/// not fit JSON or TOML numbers. Returns the number and whether it is
+/// negative.
+fn signed_number(value: &FixtureValue) -> Option<(Number, bool)> {
+    let text = match value {
+        FixtureValue::Int(value) => value.to_string(),
+        FixtureValue::UInt(value) => value.to_string(),
+        FixtureValue::Float(value) if value.is_finite() => format!("{:?}", value),
+        FixtureValue::String(text) => text.clone(),
+        _ => return None,
+    };
+
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text.to_owned()),
+        None => (false, text),
+    };
+    let number = Number::parse(&text)
+        .ok()
+        .filter(|number| number.suffix.is_none())?;
+    Some((number, negative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fixture, FixtureErr};
+    use crate::ast::{Param, ValueType};
+    use crate::compiler::Compiler;
+    use crate::lexer::tokenise;
+    use crate::parser::parse;
+    use crate::vm::{self, Outcome, Value};
+    use ed25519_dalek::{Signer, SigningKey};
+    use num_bigint::BigInt;
+
+    fn params(input: &str) -> Vec<Param> {
+        let program = parse(tokenise(input).collect::<Result<_, _>>().unwrap()).unwrap();
+        program.functions[0].params.clone()
+    }
+
+    #[test]
+    fn test_context() {
+        let fixture = Fixture::from_toml(
+            "
+            block_timestamp = 7
+            input_bytes = [\"0x0102\", 3]
+            this = 1
+
+            [keys]
+            owner = \"0707070707070707070707070707070707070707070707070707070707070707\"
+
+            [[outputs]]
+            amount = -5
+            hash = \"ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff\"
+
+            [[outputs]]
+            amount = \"170141183460469231731687303715884105727\"
+            colour_hash = \"09\"
+            input = { public_key = \"owner\", output_hash = \"0x0000000000000000000000000000000000000000000000000000000000000000\" }
+            ",
+        )
+        .unwrap();
+        let ctx = fixture.context().unwrap();
+        assert_eq!(ctx.block_timestamp, 7);
+        assert_eq!(ctx.input_bytes, vec![1, 2, 3]);
+        assert_eq!(ctx.outputs[0].amount, -5);
+        assert_eq!(ctx.outputs[0].hash, [0xff; 32]);
+        assert_eq!(ctx.outputs[1].amount, i128::MAX);
+        assert_eq!(ctx.outputs[1].colour_hash, vec![9]);
+        assert_eq!(&ctx.outputs[1].hash[..2], &[2, 0]);
+        assert_eq!(
+            ctx.outputs[1].input.public_key,
+            SigningKey::from_bytes(&[7; 32]).verifying_key().to_bytes()
+        );
+        assert_eq!(ctx.this, ctx.outputs[1]);
+
+        let fixture = Fixture::from_json("{\"this\": 2, \"outputs\": [{}]}").unwrap();
+        assert_eq!(fixture.context(), Err(FixtureErr::InvalidThis(2)));
+    }
+
+    #[test]
+    fn test_args() {
+        let params = params(
+            "function main(a: decimal, b: i8, c: [u8; 2], d: [u16], e, f: PublicKey, malleable g: [u8]) {}",
+        );
+        let fixture = Fixture::from_json(
+            "{
+                \"keys\": { \"k\": \"0707070707070707070707070707070707070707070707070707070707070707\" },
+                \"args\": {
+                    \"a\": \"-1.25\",
+                    \"b\": -128,
+                    \"c\": \"0xbeef\",
+                    \"d\": [1, \"0x10\"],
+                    \"e\": 1.5,
+                    \"f\": \"k\",
+                    \"g\": [{ \"key\": \"k\", \"message\": \"00\" }, \"ff\"]
+                }
+            }",
+        )
+        .unwrap();
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let signature = [key.sign(&[0]).to_bytes().to_vec(), vec![0xff]].concat();
+        assert_eq!(
+            fixture.args(&params),
+            Ok(vec![
+                Value::Decimal(-1_250_000_000_000_000_000),
+                Value::Int(ValueType::I8, BigInt::from(-128)),
+                Value::bytes(&[0xbe, 0xef]),
+                Value::Array(vec![
+                    Value::Int(ValueType::U16, BigInt::from(1)),
+                    Value::Int(ValueType::U16, BigInt::from(16)),
+                ]),
+                Value::Decimal(1_500_000_000_000_000_000),
+                Value::PublicKey(key.verifying_key().to_bytes()),
+                Value::bytes(&signature),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_args_errors() {
+        let params = params("function main(a: u8, b: [u8; 2]) {}");
+        let args = |json: &str| Fixture::from_json(json).unwrap().args(&params);
+        assert_eq!(
+            args("{\"args\": {\"a\": 1}}"),
+            Err(FixtureErr::MissingArg("b".to_owned()))
+        );
+        assert_eq!(
+            args("{\"args\": {\"a\": 1, \"b\": \"0000\", \"c\": 1}}"),
+            Err(FixtureErr::UnknownArg("c".to_owned()))
+        );
+        assert_eq!(
+            args("{\"args\": {\"a\": 256, \"b\": \"0000\"}}"),
+            Err(FixtureErr::InvalidValue("args.a".to_owned(), ValueType::U8))
+        );
+        assert_eq!(
+            args("{\"args\": {\"a\": 1, \"b\": \"00\"}}"),
+            Err(FixtureErr::InvalidValue(
+                "args.b".to_owned(),
+                ValueType::FixedArray(Box::new(ValueType::U8), 2)
+            ))
+        );
+        assert_eq!(
+            args("{\"args\": {\"a\": 1, \"b\": {\"key\": \"k\", \"message\": \"00\"}}}"),
+            Err(FixtureErr::InvalidKey("k".to_owned()))
+        );
+        assert!(matches!(
+            Fixture::from_json("{\"block_timestamp\": 1, \"unknown\": 2}"),
+            Err(FixtureErr::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn test_limit_order_fixture() {
+        let input = include_str!("../examples/limit_order/main.ps");
+        let program = parse(tokenise(input).collect::<Result<_, _>>().unwrap()).unwrap();
+        let mut compiler = Compiler::new();
+        compiler.push_function(&program.functions[0]).unwrap();
+
+        let fixture =
+            Fixture::from_toml(include_str!("../examples/limit_order/fill.toml")).unwrap();
+        let ctx = fixture.context().unwrap();
+        let args = fixture.args(&program.functions[0].params).unwrap();
+        match vm::run(&compiler.compile(), &ctx, args, vm::DEFAULT_GAS_LIMIT) {
+            Ok(Outcome::Success { ret, outputs }) => {
+                assert_eq!(ret, Some(Value::Int(ValueType::U32, BigInt::from(1))));
+                assert_eq!(outputs[0].amount, 200);
+                assert_eq!(outputs[0].coloured_address, vec![3; 20]);
+            }
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+    }
+}
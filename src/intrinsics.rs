@@ -0,0 +1,367 @@
+//! Builtin objects and types, and their members.
+//!
+//! Scripts inspect the transaction spending them through the builtin objects
+//! `XPU` and `this`, and through values of the builtin types `Output` and
+//! `Input`. Every member of a builtin object or type is an intrinsic, which
+//! the type checker resolves through `lookup` and code generation lowers to
+//! its opcode. Arguments are pushed in order before the opcode, so the last
+//! argument is on top of the stack. Members of types take the value they are
+//! accessed on as their first argument.
+//!
+//! Test functions additionally have the builtin object `Test`, which mocks
+//! the transaction. Its intrinsics are never valid in compiled scripts.
+
+use crate::ast::ValueType;
+use crate::bytecode::OpCode;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Builtin objects and types, whose members are intrinsics.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Object {
+    /// `XPU`, the virtual machine and the transaction being validated
+    Xpu,
+
+    /// `this`, the output whose script is running. Besides its own members,
+    /// `this` has the members of `Output`.
+    This,
+
+    /// Values of type `Output`
+    Output,
+
+    /// Values of type `Input`
+    Input,
+
+    /// `Test`, the mocked transaction in test functions
+    Test,
+}
+
+impl Object {
+    /// Returns the builtin object named `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "XPU" => Some(Object::Xpu),
+            "this" => Some(Object::This),
+            "Test" => Some(Object::Test),
+            _ => None,
+        }
+    }
+
+    /// Returns the builtin type whose values have members.
+    pub fn of_type(ty: &ValueType) -> Option<Self> {
+        match ty {
+            ValueType::Output => Some(Object::Output),
+            ValueType::Input => Some(Object::Input),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Object::Xpu => "XPU",
+            Object::This => "this",
+            Object::Output => "Output",
+            Object::Input => "Input",
+            Object::Test => "Test",
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Intrinsic {
+    pub object: Object,
+    pub name: &'static str,
+
+    /// Parameter types of methods, `None` for fields
+    pub params: Option<Vec<ValueType>>,
+
+    /// Type of the pushed value, `None` if nothing is pushed
+    pub ret: Option<ValueType>,
+    pub opcode: OpCode,
+
+    /// Execution cost in gas, in addition to the cost of the arguments
+    pub cost: u32,
+}
+
+impl Intrinsic {
+    pub fn is_method(&self) -> bool {
+        self.params.is_some()
+    }
+}
+
+impl fmt::Display for Intrinsic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.object, self.name)
+    }
+}
+
+/// Returns all intrinsics. Methods may be overloaded by their number of
+/// parameters.
+pub fn intrinsics() -> &'static [Intrinsic] {
+    static INTRINSICS: OnceLock<Vec<Intrinsic>> = OnceLock::new();
+    INTRINSICS.get_or_init(|| {
+        let bytes = || ValueType::Array(Box::new(ValueType::U8));
+
+        vec![
+            Intrinsic {
+                object: Object::Xpu,
+                name: "blockTimestamp",
+                params: None,
+                ret: Some(ValueType::I64),
+                opcode: OpCode::BlockTimestamp,
+                cost: 2,
+            },
+            Intrinsic {
+                object: Object::Xpu,
+                name: "outputsLen",
+                params: Some(vec![]),
+                ret: Some(ValueType::U32),
+                opcode: OpCode::OutputsLen,
+                cost: 2,
+            },
+            Intrinsic {
+                object: Object::Xpu,
+                name: "getOutputAt",
+                params: Some(vec![ValueType::U32]),
+                ret: Some(ValueType::Output),
+                opcode: OpCode::GetOutputAt,
+                cost: 5,
+            },
+            Intrinsic {
+                object: Object::Xpu,
+                name: "verifyEd25519",
+                params: Some(vec![bytes(), bytes(), ValueType::PublicKey, bytes()]),
+                ret: None,
+                opcode: OpCode::VerifyEd25519,
+                cost: 3000,
+            },
+            Intrinsic {
+                object: Object::Xpu,
+                name: "pushOut",
+                params: Some(vec![
+                    ValueType::I128,
+                    ValueType::Address,
+                    ValueType::Address,
+                ]),
+                ret: Some(ValueType::U32),
+                opcode: OpCode::PushOut,
+                cost: 100,
+            },
+            Intrinsic {
+                object: Object::Xpu,
+                name: "pushOut",
+                params: Some(vec![ValueType::Output]),
+                ret: Some(ValueType::U32),
+                opcode: OpCode::PushOutput,
+                cost: 100,
+            },
+            Intrinsic {
+                object: Object::This,
+                name: "inputBytes",
+                params: None,
+                ret: Some(bytes()),
+                opcode: OpCode::InputBytes,
+                cost: 3,
+            },
+            field(
+                Object::Output,
+                "amount",
+                ValueType::I128,
+                OpCode::OutputAmount,
+            ),
+            field(
+                Object::Output,
+                "colourHash",
+                ValueType::Asset,
+                OpCode::OutputColourHash,
+            ),
+            field(
+                Object::Output,
+                "colouredAddress",
+                ValueType::Address,
+                OpCode::OutputColouredAddress,
+            ),
+            field(
+                Object::Output,
+                "scriptHash",
+                ValueType::Address,
+                OpCode::OutputScriptHash,
+            ),
+            field(Object::Output, "hash", ValueType::Hash, OpCode::OutputHash),
+            field(
+                Object::Output,
+                "input",
+                ValueType::Input,
+                OpCode::OutputInput,
+            ),
+            field(
+                Object::Input,
+                "publicKey",
+                ValueType::PublicKey,
+                OpCode::InputPublicKey,
+            ),
+            field(
+                Object::Input,
+                "outputHash",
+                ValueType::Hash,
+                OpCode::InputOutputHash,
+            ),
+            test(
+                "setBlockTimestamp",
+                vec![ValueType::I64],
+                None,
+                OpCode::SetBlockTimestamp,
+            ),
+            test("setInputBytes", vec![bytes()], None, OpCode::SetInputBytes),
+            test("setThis", vec![ValueType::Output], None, OpCode::SetThis),
+            test(
+                "addOutput",
+                vec![ValueType::Output],
+                None,
+                OpCode::AddOutput,
+            ),
+            test(
+                "output",
+                vec![
+                    ValueType::I128,
+                    ValueType::Asset,
+                    ValueType::Address,
+                    ValueType::Address,
+                ],
+                Some(ValueType::Output),
+                OpCode::MockOutput,
+            ),
+            test(
+                "output",
+                vec![
+                    ValueType::I128,
+                    ValueType::Asset,
+                    ValueType::Address,
+                    ValueType::Address,
+                    ValueType::PublicKey,
+                ],
+                Some(ValueType::Output),
+                OpCode::MockOutputWithKey,
+            ),
+            test(
+                "publicKey",
+                vec![bytes()],
+                Some(ValueType::PublicKey),
+                OpCode::DerivePublicKey,
+            ),
+            test("sign", vec![bytes(), bytes()], Some(bytes()), OpCode::Sign),
+            // The reason is a string literal like the reason of `revert`,
+            // which the type checker checks itself
+            test(
+                "expectRevert",
+                vec![ValueType::Any],
+                None,
+                OpCode::ExpectRevert,
+            ),
+            test("pushedLen", vec![], Some(ValueType::U32), OpCode::PushedLen),
+            test(
+                "pushedAt",
+                vec![ValueType::U32],
+                Some(ValueType::Output),
+                OpCode::PushedAt,
+            ),
+        ]
+    })
+}
+
+/// A field of a builtin type, which reads the field from the value on top of
+/// the stack.
+fn field(object: Object, name: &'static str, ty: ValueType, opcode: OpCode) -> Intrinsic {
+    Intrinsic {
+        object,
+        name,
+        params: None,
+        ret: Some(ty),
+        opcode,
+        cost: 1,
+    }
+}
+
+/// A method of `Test`. They only run in tests and cost nothing.
+fn test(
+    name: &'static str,
+    params: Vec<ValueType>,
+    ret: Option<ValueType>,
+    opcode: OpCode,
+) -> Intrinsic {
+    Intrinsic {
+        object: Object::Test,
+        name,
+        params: Some(params),
+        ret,
+        opcode,
+        cost: 0,
+    }
+}
+
+/// Returns the gas cost of an instruction. Instructions of intrinsics cost
+/// the cost of their intrinsic, and every other instruction costs 1 gas.
+pub fn cost(opcode: OpCode) -> u32 {
+    static COSTS: OnceLock<[u32; 256]> = OnceLock::new();
+    COSTS.get_or_init(|| {
+        let mut costs = [1; 256];
+        for intrinsic in intrinsics().iter() {
+            costs[intrinsic.opcode as usize] = intrinsic.cost;
+        }
+        costs
+    })[opcode as usize]
+}
+
+/// Returns the intrinsics of `object` named `name`. Methods with the same
+/// name only differ in their parameters.
+pub fn lookup(object: Object, name: &str) -> impl Iterator<Item = &'static Intrinsic> + '_ {
+    intrinsics()
+        .iter()
+        .filter(move |intrinsic| intrinsic.object == object && intrinsic.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cost, intrinsics, lookup, Object};
+    use crate::ast::ValueType;
+    use crate::bytecode::OpCode;
+
+    #[test]
+    fn test_lookup() {
+        let found: Vec<_> = lookup(Object::Xpu, "blockTimestamp").collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].ret, Some(ValueType::I64));
+        assert!(!found[0].is_method());
+        assert_eq!(found[0].to_string(), "XPU.blockTimestamp");
+
+        assert_eq!(lookup(Object::This, "blockTimestamp").count(), 0);
+        assert_eq!(lookup(Object::Xpu, "unknown").count(), 0);
+    }
+
+    #[test]
+    fn test_cost() {
+        assert_eq!(cost(OpCode::VerifyEd25519), 3000);
+        assert_eq!(cost(OpCode::OutputAmount), 1);
+        assert_eq!(cost(OpCode::PushedLen), 0);
+        assert_eq!(cost(OpCode::Add), 1);
+    }
+
+    #[test]
+    fn test_opcodes_are_unique() {
+        for (i, intrinsic) in intrinsics().iter().enumerate() {
+            for other in intrinsics()[i + 1..].iter() {
+                assert_ne!(
+                    intrinsic.opcode, other.opcode,
+                    "{} and {}",
+                    intrinsic, other
+                );
+            }
+        }
+    }
+}
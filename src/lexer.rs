@@ -1,8 +1,16 @@
+use crate::number::{Number, NumberErr};
+use crate::source_map::FileId;
 use std::iter::Peekable;
+use std::ops::Range;
 use std::str::Chars;
 
-pub fn tokenise(input: &str) -> Tokens {
-    Tokens::new(input)
+pub fn tokenise(input: &str) -> Tokens<'_> {
+    Tokens::new(input, 0)
+}
+
+/// Tokenises the contents of `file`. Positions of the tokens refer to `file`.
+pub fn tokenise_file(input: &str, file: FileId) -> Tokens<'_> {
+    Tokens::new(input, file)
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -27,10 +35,34 @@ pub enum Symbol {
     Colon,
     Semicolon,
     Dot,
+    DotDot,
     Comma,
+    ExclamationMark,
+    EqualEqual,
+    ExclamationMarkEqual,
+    LesserThanEqual,
+    GreaterThanEqual,
+    AmpersandAmpersand,
+    VerticalBarVerticalBar,
+    PlusPlus,
+    PlusEqual,
+    MinusMinus,
+    MinusEqual,
+    MinusGreaterThan,
+    AsteriskEqual,
+    SlashEqual,
+    PercentEqual,
+    CaretEqual,
+    VerticalBarEqual,
+    AmpersandEqual,
+    LesserThanLesserThan,
+    GreaterThanGreaterThan,
+    LesserThanLesserThanEqual,
+    GreaterThanGreaterThanEqual,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Keyword {
     Function,
     U8,
@@ -49,9 +81,12 @@ pub enum Keyword {
     F64,
     Decimal,
     As,
+    Break,
+    Continue,
     Else,
     For,
     If,
+    Import,
     Let,
     Return,
     Revert,
@@ -59,6 +94,11 @@ pub enum Keyword {
     Malleable,
     Asset,
     Address,
+    Bool,
+    True,
+    False,
+    This,
+    Test,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -67,27 +107,125 @@ pub enum TokenKind {
     NumberLiteral(String),
     Keyword(Keyword),
     String(String),
+
+    /// A byte string `b"..."` or a hex literal `x"..."`
+    Bytes(Vec<u8>),
     Symbol(Symbol),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Location of a token in a source file.
+///
+/// Lines and columns start at 1. Lines end at `\n`, which includes `\r\n`, and
+/// a lone `\r` is whitespace. Columns count characters rather than bytes, and
+/// a tab is a single character, so `\tlet` starts `let` at column 2 no matter
+/// how wide the tab is displayed.
+///
+/// Two positions are equal if they point at the same line and column of the
+/// same file. The byte span is not compared, as it follows from the line and
+/// column except for its end.
+#[derive(Debug, Clone)]
 pub struct Position {
     pub column: usize,
     pub line: usize,
+    pub file: FileId,
+
+    /// Byte offset of the first character
+    pub start: usize,
+
+    /// Byte offset after the last character
+    pub end: usize,
 }
 
 impl Position {
-    fn new(column: usize, line: usize) -> Self {
-        Self { column, line }
+    pub fn new(column: usize, line: usize) -> Self {
+        Self {
+            column,
+            line,
+            file: 0,
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Byte range covered in the source file
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.end
     }
 }
 
+impl PartialEq for Position {
+    fn eq(&self, other: &Self) -> bool {
+        self.file == other.file && self.line == other.line && self.column == other.column
+    }
+}
+
+impl Eq for Position {}
+
 impl Default for Position {
     fn default() -> Position {
         Position::new(1, 1)
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LexError {
+    /// A string literal without the closing quote. Points at the opening quote.
+    UnterminatedString(Position),
+
+    /// A `/*` comment without the closing `*/`. Points at the opening slash.
+    UnterminatedBlockComment(Position),
+
+    /// A character that cannot start any token.
+    InvalidCharacter(char, Position),
+
+    /// A number literal such as `1.2.3` or `0b102`.
+    MalformedNumber(Position),
+
+    /// A number literal with a suffix which is not a number type, such as
+    /// `12ab`, or with an integer suffix and a fractional part.
+    InvalidNumberSuffix(String, Position),
+
+    /// An identifier with a character which is not ASCII, such as `café`.
+    /// Points at the start of the identifier.
+    NonAsciiIdentifier(char, Position),
+
+    /// An unknown escape sequence such as `\q`, or `\x80` in a text string.
+    /// Points at the backslash.
+    InvalidEscape(Position),
+
+    /// A hex literal with an odd number of digits or a character which is
+    /// not a hex digit.
+    InvalidHexLiteral(Position),
+}
+
+impl LexError {
+    pub fn position(&self) -> &Position {
+        match self {
+            LexError::UnterminatedString(position)
+            | LexError::UnterminatedBlockComment(position)
+            | LexError::InvalidCharacter(_, position)
+            | LexError::MalformedNumber(position)
+            | LexError::InvalidNumberSuffix(_, position)
+            | LexError::NonAsciiIdentifier(_, position)
+            | LexError::InvalidEscape(position)
+            | LexError::InvalidHexLiteral(position) => position,
+        }
+    }
+
+    fn position_mut(&mut self) -> &mut Position {
+        match self {
+            LexError::UnterminatedString(position)
+            | LexError::UnterminatedBlockComment(position)
+            | LexError::InvalidCharacter(_, position)
+            | LexError::MalformedNumber(position)
+            | LexError::InvalidNumberSuffix(_, position)
+            | LexError::NonAsciiIdentifier(_, position)
+            | LexError::InvalidEscape(position)
+            | LexError::InvalidHexLiteral(position) => position,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Token {
     pub kind: TokenKind,
@@ -102,71 +240,218 @@ impl Token {
 
 pub struct Tokens<'a> {
     chars: Peekable<Chars<'a>>,
+
+    /// Position of the next character. Its `start` is the current byte offset.
     position: Position,
-    state: LexerState,
 }
 
 impl<'a> Tokens<'a> {
-    fn new(input: &'a str) -> Self {
+    fn new(input: &'a str, file: FileId) -> Self {
         Self {
             chars: input.chars().peekable(),
-            position: Position::default(),
-            state: LexerState::Any,
+            position: Position {
+                file,
+                ..Position::default()
+            },
         }
     }
 
-    fn consume_number_literal(&mut self) -> String {
+    /// Consumes a number literal and validates it against the grammar in
+    /// `crate::number`. The token holds the literal as written.
+    fn consume_number_literal(&mut self) -> Result<String, LexError> {
+        let position = self.position.clone();
         let mut buf = String::new();
         while let Some(&character) = self.chars.peek() {
-            if character.is_ascii_digit() || character == '.' {
+            let prefixed = buf.starts_with("0x") || buf.starts_with("0b") || buf.starts_with("0o");
+            let exponent_sign = (character == '+' || character == '-')
+                && !prefixed
+                && buf.ends_with(['e', 'E'])
+                && self.peek_second().is_some_and(|next| next.is_ascii_digit());
+
+            if character == '.' && self.peek_second() == Some('.') {
+                // A range such as `0..4`
+                break;
+            } else if character.is_ascii_alphanumeric()
+                || character == '_'
+                || character == '$'
+                || character == '.'
+                || exponent_sign
+            {
+                // Everything which could be part of the literal is consumed,
+                // so that e.g. `12ab` is an error instead of two tokens
                 self.consume_character();
                 buf.push(character);
             } else {
                 break;
             }
         }
-        buf
+
+        match Number::parse(&buf) {
+            Ok(_) => Ok(buf),
+            Err(NumberErr::Malformed) => Err(LexError::MalformedNumber(position)),
+            Err(NumberErr::InvalidSuffix(suffix)) => {
+                Err(LexError::InvalidNumberSuffix(suffix, position))
+            }
+        }
     }
 
-    fn consume_character_literal(&mut self) -> char {
+    fn consume_string_literal(&mut self, quote: char) -> Result<String, LexError> {
+        let position = self.position.clone();
+        let bytes = self.consume_quoted(quote, false, position)?;
+
+        // Escapes of text strings are characters, so the bytes are UTF-8
+        Ok(String::from_utf8(bytes).unwrap())
+    }
+
+    /// Consumes a byte string such as `b"abc\xff"`.
+    fn consume_byte_string_literal(&mut self) -> Result<Vec<u8>, LexError> {
+        let position = self.position.clone();
         self.consume_character();
-        let character = self.consume_character();
+        self.consume_quoted('"', true, position)
+    }
+
+    /// Consumes a hex literal such as `x"deadbeef"`.
+    fn consume_hex_literal(&mut self) -> Result<Vec<u8>, LexError> {
+        let position = self.position.clone();
         self.consume_character();
-        character.unwrap()
+        self.consume_character();
+
+        let mut digits = vec![];
+        let mut valid = true;
+        loop {
+            match self.consume_character() {
+                Some('"') => break,
+                Some(character) => match character.to_digit(16) {
+                    Some(digit) => digits.push(digit as u8),
+                    None => valid = false,
+                },
+                None => return Err(LexError::UnterminatedString(position)),
+            }
+        }
+
+        if !valid || digits.len() % 2 != 0 {
+            return Err(LexError::InvalidHexLiteral(position));
+        }
+
+        Ok(digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect())
     }
 
-    fn consume_string_literal(&mut self, c: char) -> String {
-        let mut string = String::new();
+    /// Consumes the contents of a string from the opening to the closing
+    /// quote and returns them with escapes resolved. `position` is the start
+    /// of the literal.
+    fn consume_quoted(
+        &mut self,
+        quote: char,
+        bytes: bool,
+        position: Position,
+    ) -> Result<Vec<u8>, LexError> {
+        let mut out = vec![];
         self.consume_character();
-        while let Some(character) = self.consume_character() {
-            if character == c {
-                break;
+        while let Some(&character) = self.chars.peek() {
+            if character == quote {
+                self.consume_character();
+                return Ok(out);
+            } else if character == '\\' {
+                self.consume_escape(bytes, &mut out)?;
+            } else if character == '\r' && self.peek_second() == Some('\n') {
+                // Line breaks in strings are `\n` no matter how the file was saved
+                self.consume_character();
             } else {
-                string.push(character);
+                self.consume_character();
+                let mut buf = [0; 4];
+                out.extend_from_slice(character.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+        Err(LexError::UnterminatedString(position))
+    }
+
+    /// Consumes an escape sequence and appends the bytes it stands for.
+    /// `\xHH` is any byte in byte strings but only ASCII in text strings,
+    /// which take `\u{H..}` for other characters.
+    fn consume_escape(&mut self, bytes: bool, out: &mut Vec<u8>) -> Result<(), LexError> {
+        let position = self.position.clone();
+        let invalid = || LexError::InvalidEscape(position.clone());
+        self.consume_character();
+
+        match self.consume_character().ok_or_else(invalid)? {
+            'n' => out.push(b'\n'),
+            'r' => out.push(b'\r'),
+            't' => out.push(b'\t'),
+            '0' => out.push(b'\0'),
+            '\\' => out.push(b'\\'),
+            '"' => out.push(b'"'),
+            '\'' => out.push(b'\''),
+            'x' => {
+                let high = self.consume_hex_digit().ok_or_else(invalid)?;
+                let low = self.consume_hex_digit().ok_or_else(invalid)?;
+                let byte = (high << 4 | low) as u8;
+                if !bytes && !byte.is_ascii() {
+                    return Err(invalid());
+                }
+                out.push(byte);
             }
+            'u' if !bytes => {
+                if self.consume_character() != Some('{') {
+                    return Err(invalid());
+                }
+                let mut value = 0u32;
+                let mut digits = 0;
+                while let Some(digit) = self.consume_hex_digit() {
+                    value = value << 4 | digit;
+                    digits += 1;
+                }
+                if digits == 0 || digits > 6 || self.consume_character() != Some('}') {
+                    return Err(invalid());
+                }
+                let character = char::from_u32(value).ok_or_else(invalid)?;
+                let mut buf = [0; 4];
+                out.extend_from_slice(character.encode_utf8(&mut buf).as_bytes());
+            }
+            _ => return Err(invalid()),
         }
-        string
+
+        Ok(())
     }
 
-    fn consume_identifier(&mut self) -> String {
+    /// Consumes the next character if it is a hex digit and returns its value.
+    fn consume_hex_digit(&mut self) -> Option<u32> {
+        let digit = self.chars.peek()?.to_digit(16)?;
+        self.consume_character();
+        Some(digit)
+    }
+
+    /// Consumes an identifier, which is
+    ///
+    /// ```text
+    /// identifier = (letter | "_" | "$") (letter | digit | "_" | "$")*
+    /// ```
+    ///
+    /// where letters and digits are ASCII. Non-ASCII letters and digits are
+    /// consumed as well, so that the error covers the whole identifier.
+    fn consume_identifier(&mut self) -> Result<String, LexError> {
+        let position = self.position.clone();
         let mut string = String::new();
         while let Some(&character) = self.chars.peek() {
-            // Allow ascii alphanumeric, underscores, and dollars in identifiers
-            if character.is_ascii_alphanumeric() || character == '_' || character == '$' {
+            if character.is_alphanumeric() || character == '_' || character == '$' {
                 string.push(character);
                 self.consume_character();
             } else {
                 break;
             }
         }
-        string
+
+        match string.chars().find(|character| !character.is_ascii()) {
+            Some(character) => Err(LexError::NonAsciiIdentifier(character, position)),
+            None => Ok(string),
+        }
     }
 
     fn consume_whitespaces(&mut self) {
         while let Some(&character) = self.chars.peek() {
-            if character == '\n' {
-                self.consume_new_line();
-            } else if character.is_whitespace() {
+            if character.is_whitespace() {
                 self.consume_character();
             } else {
                 break;
@@ -174,112 +459,122 @@ impl<'a> Tokens<'a> {
         }
     }
 
-    fn consume_comment(&mut self) {
-        self.state = LexerState::CommentStart;
+    /// Consumes a comment starting at a slash. Returns `false` if the slash
+    /// does not start a comment, in which case only the slash is consumed.
+    fn consume_comment(&mut self) -> Result<bool, LexError> {
+        let position = self.position.clone();
         self.consume_character();
-        loop {
-            match self.consume_character() {
-                // Multiline comment
-                Some('*') => match self.state {
-                    LexerState::CommentStart => {
-                        self.state = LexerState::CommentMultiLineHitStartAsterisk;
-                    }
-
-                    LexerState::CommentMultiLineHitStartAsterisk => {
-                        self.state = LexerState::CommentMultiLineHitEndAsterisk;
-                    }
-
-                    _ => {}
-                },
 
-                // Single line comment
-                Some('/') => match self.state {
-                    LexerState::CommentStart => {
-                        self.state = LexerState::CommentSingleLine;
-                    }
-
-                    LexerState::CommentMultiLineHitEndAsterisk => {
-                        self.state = LexerState::Any;
+        match self.chars.peek() {
+            // Single line comment
+            Some('/') => {
+                while let Some(&character) = self.chars.peek() {
+                    if character == '\n' {
                         break;
                     }
+                    self.consume_character();
+                }
+                Ok(true)
+            }
 
-                    _ => {}
-                },
-
-                Some('\n') => {
-                    match self.state {
-                        LexerState::CommentSingleLine => {
-                            self.position.column = 1;
-                            self.position.line += 1;
-                            self.state = LexerState::Any;
-                            break;
-                        }
-
-                        LexerState::CommentStart => {
-                            unimplemented!(); // TODO: Throw error here
-                            break;
-                        }
-
-                        LexerState::CommentMultiLineHitEndAsterisk => {
-                            self.state = LexerState::CommentMultiLineHitStartAsterisk;
-                            self.position.column = 1;
-                            self.position.line += 1;
+            // Multiline comment
+            Some('*') => {
+                self.consume_character();
+                loop {
+                    match self.chars.peek() {
+                        Some('*') => {
+                            self.consume_character();
+                            if let Some('/') = self.chars.peek() {
+                                self.consume_character();
+                                return Ok(true);
+                            }
                         }
-
-                        _ => {
-                            self.position.column = 1;
-                            self.position.line += 1;
+                        Some(_) => {
+                            self.consume_character();
                         }
+                        None => return Err(LexError::UnterminatedBlockComment(position)),
                     }
                 }
+            }
 
-                None => {
-                    self.state = LexerState::Any;
-                    break;
-                }
+            _ => Ok(false),
+        }
+    }
 
-                Some(c) if c.is_whitespace() => match self.state {
-                    LexerState::CommentStart => {
-                        self.state = LexerState::HitSlash;
-                        break;
-                    }
-                    LexerState::CommentMultiLineHitEndAsterisk => {
-                        self.state = LexerState::CommentMultiLineHitStartAsterisk;
-                    }
-                    _ => {}
-                },
+    /// Consumes a symbol of one or two characters. The longest match wins.
+    fn consume_symbol(&mut self, single: Symbol, compounds: &[(char, Symbol)]) -> Symbol {
+        self.consume_character();
 
-                _ => {
-                    match self.state {
-                        LexerState::CommentStart => {
-                            unimplemented!(); // TODO: Throw error here
-                        }
-                        LexerState::CommentMultiLineHitEndAsterisk => {
-                            self.state = LexerState::CommentMultiLineHitStartAsterisk;
-                        }
-                        _ => {}
-                    }
+        if let Some(&next) = self.chars.peek() {
+            for (character, symbol) in compounds.iter() {
+                if *character == next {
+                    self.consume_character();
+                    return symbol.clone();
                 }
             }
         }
+
+        single
+    }
+
+    /// Extends a shift to the shift assignment `<<=` or `>>=` if `=`
+    /// follows.
+    fn consume_shift_assignment(&mut self, symbol: Symbol) -> Symbol {
+        let compound = match symbol {
+            Symbol::LesserThanLesserThan => Symbol::LesserThanLesserThanEqual,
+            Symbol::GreaterThanGreaterThan => Symbol::GreaterThanGreaterThanEqual,
+            _ => return symbol,
+        };
+
+        if self.chars.peek() == Some(&'=') {
+            self.consume_character();
+            compound
+        } else {
+            symbol
+        }
     }
 
-    fn consume_new_line(&mut self) -> Option<char> {
-        self.position.column = 1;
-        self.position.line += 1;
-        self.chars.next()
+    /// Returns the character after the next one.
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.chars.clone();
+        chars.next();
+        chars.next()
     }
 
+    /// Consumes the next character and advances the position past it. Only
+    /// `\n` starts a new line, so `\r\n` is a single line break whose `\r`
+    /// is the last column of its line.
     fn consume_character(&mut self) -> Option<char> {
-        self.position.column += 1;
-        self.chars.next()
+        let character = self.chars.next()?;
+        self.position.start += character.len_utf8();
+        if character == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(character)
     }
 }
 
-impl Iterator for Tokens<'_> {
-    type Item = Token;
+impl Tokens<'_> {
+    /// Lexes the next token and ends its span, or the span of the error,
+    /// at the current offset.
+    fn next_token(&mut self) -> Result<Option<Token>, LexError> {
+        match self.lex_token() {
+            Ok(Some(mut token)) => {
+                token.position.end = self.position.start;
+                Ok(Some(token))
+            }
+            Err(mut err) => {
+                err.position_mut().end = self.position.start;
+                Err(err)
+            }
+            Ok(None) => Ok(None),
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    fn lex_token(&mut self) -> Result<Option<Token>, LexError> {
         let mut token = None;
         loop {
             self.consume_whitespaces();
@@ -296,64 +591,113 @@ impl Iterator for Tokens<'_> {
                         token = Some(Token::new(TokenKind::Symbol(Symbol::Semicolon), position));
                     }
                     '+' => {
-                        self.consume_character();
-                        token = Some(Token::new(TokenKind::Symbol(Symbol::Plus), position));
+                        let symbol = self.consume_symbol(
+                            Symbol::Plus,
+                            &[('+', Symbol::PlusPlus), ('=', Symbol::PlusEqual)],
+                        );
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     '-' => {
-                        self.consume_character();
-                        token = Some(Token::new(TokenKind::Symbol(Symbol::Minus), position));
+                        let symbol = self.consume_symbol(
+                            Symbol::Minus,
+                            &[
+                                ('-', Symbol::MinusMinus),
+                                ('=', Symbol::MinusEqual),
+                                ('>', Symbol::MinusGreaterThan),
+                            ],
+                        );
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     '=' => {
-                        self.consume_character();
-                        token = Some(Token::new(TokenKind::Symbol(Symbol::Equal), position));
+                        let symbol =
+                            self.consume_symbol(Symbol::Equal, &[('=', Symbol::EqualEqual)]);
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     '>' => {
-                        self.consume_character();
-                        token = Some(Token::new(TokenKind::Symbol(Symbol::GreaterThan), position));
+                        let symbol = self.consume_symbol(
+                            Symbol::GreaterThan,
+                            &[
+                                ('=', Symbol::GreaterThanEqual),
+                                ('>', Symbol::GreaterThanGreaterThan),
+                            ],
+                        );
+                        let symbol = self.consume_shift_assignment(symbol);
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     '<' => {
-                        self.consume_character();
-                        token = Some(Token::new(TokenKind::Symbol(Symbol::LesserThan), position));
+                        let symbol = self.consume_symbol(
+                            Symbol::LesserThan,
+                            &[
+                                ('=', Symbol::LesserThanEqual),
+                                ('<', Symbol::LesserThanLesserThan),
+                            ],
+                        );
+                        let symbol = self.consume_shift_assignment(symbol);
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     '^' => {
-                        self.consume_character();
-                        token = Some(Token::new(TokenKind::Symbol(Symbol::Caret), position));
+                        let symbol =
+                            self.consume_symbol(Symbol::Caret, &[('=', Symbol::CaretEqual)]);
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     '%' => {
-                        self.consume_character();
-                        token = Some(Token::new(TokenKind::Symbol(Symbol::Percent), position));
+                        let symbol =
+                            self.consume_symbol(Symbol::Percent, &[('=', Symbol::PercentEqual)]);
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     '|' => {
-                        self.consume_character();
-                        token = Some(Token::new(TokenKind::Symbol(Symbol::VerticalBar), position));
+                        let symbol = self.consume_symbol(
+                            Symbol::VerticalBar,
+                            &[
+                                ('|', Symbol::VerticalBarVerticalBar),
+                                ('=', Symbol::VerticalBarEqual),
+                            ],
+                        );
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     '&' => {
-                        self.consume_character();
-                        token = Some(Token::new(TokenKind::Symbol(Symbol::Ampersand), position));
+                        let symbol = self.consume_symbol(
+                            Symbol::Ampersand,
+                            &[
+                                ('&', Symbol::AmpersandAmpersand),
+                                ('=', Symbol::AmpersandEqual),
+                            ],
+                        );
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     '*' => {
-                        self.consume_character();
-                        token = Some(Token::new(TokenKind::Symbol(Symbol::Asterisk), position));
+                        let symbol =
+                            self.consume_symbol(Symbol::Asterisk, &[('=', Symbol::AsteriskEqual)]);
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
+                    }
+                    '!' => {
+                        let symbol = self.consume_symbol(
+                            Symbol::ExclamationMark,
+                            &[('=', Symbol::ExclamationMarkEqual)],
+                        );
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     '.' => {
-                        self.consume_character();
-                        token = Some(Token::new(TokenKind::Symbol(Symbol::Dot), position));
+                        let symbol = self.consume_symbol(Symbol::Dot, &[('.', Symbol::DotDot)]);
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     ',' => {
                         self.consume_character();
                         token = Some(Token::new(TokenKind::Symbol(Symbol::Comma), position));
                     }
                     '/' => {
-                        self.consume_comment();
-
-                        // Not a comment but division.
-                        if let LexerState::HitSlash = self.state {
-                            self.state = LexerState::Any;
-                            token = Some(Token::new(TokenKind::Symbol(Symbol::Slash), position));
-                            break;
+                        if self.consume_comment()? {
+                            continue;
                         }
 
-                        continue;
+                        // Not a comment but division.
+                        let symbol = if self.chars.peek() == Some(&'=') {
+                            self.consume_character();
+                            Symbol::SlashEqual
+                        } else {
+                            Symbol::Slash
+                        };
+                        token = Some(Token::new(TokenKind::Symbol(symbol), position));
                     }
                     '(' => {
                         self.consume_character();
@@ -389,21 +733,29 @@ impl Iterator for Tokens<'_> {
                         ));
                     }
                     '"' => {
-                        let value = self.consume_string_literal('"');
+                        let value = self.consume_string_literal('"')?;
                         token = Some(Token::new(TokenKind::String(value), position));
                     }
                     '\'' => {
-                        let value = self.consume_string_literal('\'');
+                        let value = self.consume_string_literal('\'')?;
                         token = Some(Token::new(TokenKind::String(value), position));
                     }
+                    'b' if self.peek_second() == Some('"') => {
+                        let value = self.consume_byte_string_literal()?;
+                        token = Some(Token::new(TokenKind::Bytes(value), position));
+                    }
+                    'x' if self.peek_second() == Some('"') => {
+                        let value = self.consume_hex_literal()?;
+                        token = Some(Token::new(TokenKind::Bytes(value), position));
+                    }
                     _ => {
                         if character.is_ascii_digit() {
                             token = Some(Token::new(
-                                TokenKind::NumberLiteral(self.consume_number_literal()),
+                                TokenKind::NumberLiteral(self.consume_number_literal()?),
                                 position,
                             ));
-                        } else if character.is_ascii_alphabetic() {
-                            let name = self.consume_identifier();
+                        } else if is_identifier_start(character) {
+                            let name = self.consume_identifier()?;
                             match &*name {
                                 "function" => {
                                     token = Some(Token::new(
@@ -559,10 +911,61 @@ impl Iterator for Tokens<'_> {
                                         position,
                                     ));
                                 }
+                                "continue" => {
+                                    token = Some(Token::new(
+                                        TokenKind::Keyword(Keyword::Continue),
+                                        position,
+                                    ));
+                                }
+                                "break" => {
+                                    token = Some(Token::new(
+                                        TokenKind::Keyword(Keyword::Break),
+                                        position,
+                                    ));
+                                }
+                                "import" => {
+                                    token = Some(Token::new(
+                                        TokenKind::Keyword(Keyword::Import),
+                                        position,
+                                    ));
+                                }
+                                "bool" => {
+                                    token = Some(Token::new(
+                                        TokenKind::Keyword(Keyword::Bool),
+                                        position,
+                                    ));
+                                }
+                                "true" => {
+                                    token = Some(Token::new(
+                                        TokenKind::Keyword(Keyword::True),
+                                        position,
+                                    ));
+                                }
+                                "false" => {
+                                    token = Some(Token::new(
+                                        TokenKind::Keyword(Keyword::False),
+                                        position,
+                                    ));
+                                }
+                                "this" => {
+                                    token = Some(Token::new(
+                                        TokenKind::Keyword(Keyword::This),
+                                        position,
+                                    ));
+                                }
+                                "test" => {
+                                    token = Some(Token::new(
+                                        TokenKind::Keyword(Keyword::Test),
+                                        position,
+                                    ));
+                                }
                                 _ => {
                                     token = Some(Token::new(TokenKind::Identifier(name), position));
                                 }
                             }
+                        } else {
+                            self.consume_character();
+                            return Err(LexError::InvalidCharacter(character, position));
                         }
                     }
                 }
@@ -571,38 +974,70 @@ impl Iterator for Tokens<'_> {
                 break;
             }
         }
-        token
+        Ok(token)
     }
 }
 
-enum LexerState {
-    Any,
-    CommentStart,
-    CommentMultiLineHitStartAsterisk,
-    CommentMultiLineHitEndAsterisk,
-    CommentSingleLine,
-    HitSlash,
+/// Returns true if `character` starts an identifier. Non-ASCII letters start
+/// an identifier as well, which is then rejected with a dedicated error.
+fn is_identifier_start(character: char) -> bool {
+    character.is_alphabetic() || character == '_' || character == '$'
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token().transpose()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{tokenise, Keyword, Position, Symbol, Token, TokenKind};
+    use super::{tokenise, tokenise_file, Keyword, LexError, Position, Symbol, Token, TokenKind};
+
+    #[test]
+    fn test_tokenise_spans() {
+        let tokens: Vec<Token> = tokenise_file("\"€\" +=\n  abc", 3)
+            .map(Result::unwrap)
+            .collect();
+        let spans: Vec<_> = tokens
+            .iter()
+            .map(|token| (token.position.file, token.position.span()))
+            .collect();
+        assert_eq!(spans, vec![(3, 0..5), (3, 6..8), (3, 11..14)]);
+        assert_eq!(
+            tokens[2].position,
+            Position {
+                file: 3,
+                ..Position::new(3, 2)
+            }
+        );
+    }
+
+    #[test]
+    fn test_lex_error_spans() {
+        let err = tokenise("a = 1.2.3;").find_map(Result::err).unwrap();
+        assert_eq!(err.position().span(), 4..9);
+        let err = tokenise("/* a\n b").find_map(Result::err).unwrap();
+        assert_eq!(err.position().span(), 0..7);
+    }
 
     #[test]
     fn test_tokenise_empty_string() {
-        let tokens: Vec<Token> = tokenise("").collect();
+        let tokens: Vec<Token> = tokenise("").map(Result::unwrap).collect();
         assert_eq!(tokens, vec![]);
     }
 
     #[test]
     fn test_tokenise_whitespace() {
-        let tokens: Vec<Token> = tokenise(" ").collect();
+        let tokens: Vec<Token> = tokenise(" ").map(Result::unwrap).collect();
         assert_eq!(tokens, vec![]);
     }
 
     #[test]
     fn test_tokenise_integer_and_plus_and_minus() {
-        let tokens: Vec<Token> = tokenise("1 + 2 - 3").collect();
+        let tokens: Vec<Token> = tokenise("1 + 2 - 3").map(Result::unwrap).collect();
         assert_eq!(
             tokens,
             vec![
@@ -626,7 +1061,7 @@ mod tests {
 
     #[test]
     fn test_tokenise_string() {
-        let tokens: Vec<Token> = tokenise("\"dummy\"").collect();
+        let tokens: Vec<Token> = tokenise("\"dummy\"").map(Result::unwrap).collect();
         assert_eq!(
             tokens,
             vec![Token::new(
@@ -638,7 +1073,7 @@ mod tests {
 
     #[test]
     fn test_tokenise_string_2() {
-        let tokens: Vec<Token> = tokenise("'dummy'").collect();
+        let tokens: Vec<Token> = tokenise("'dummy'").map(Result::unwrap).collect();
         assert_eq!(
             tokens,
             vec![Token::new(
@@ -648,9 +1083,81 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tokenise_string_escapes() {
+        let tokens: Vec<Token> = tokenise(r#""a\"b\n\t\\\x41\u{20ac}" '\'\0'"#)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(
+                    TokenKind::String("a\"b\n\t\\A€".to_string()),
+                    Position::new(1, 1)
+                ),
+                Token::new(TokenKind::String("'\0".to_string()), Position::new(26, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_byte_strings() {
+        let tokens: Vec<Token> = tokenise(r#"b"a\xff\"" x"DEadbeef" x"" b"#)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(
+                    TokenKind::Bytes(vec![b'a', 0xff, b'"']),
+                    Position::new(1, 1)
+                ),
+                Token::new(
+                    TokenKind::Bytes(vec![0xde, 0xad, 0xbe, 0xef]),
+                    Position::new(12, 1)
+                ),
+                Token::new(TokenKind::Bytes(vec![]), Position::new(24, 1)),
+                Token::new(TokenKind::Identifier("b".to_string()), Position::new(28, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_invalid_escapes() {
+        let first_err = |input| tokenise(input).find_map(Result::err).unwrap();
+        assert_eq!(
+            first_err(r#""ab\q""#),
+            LexError::InvalidEscape(Position::new(4, 1))
+        );
+        assert_eq!(
+            first_err(r#""\x80""#),
+            LexError::InvalidEscape(Position::new(2, 1))
+        );
+        assert_eq!(
+            first_err(r#"b"\u{41}""#),
+            LexError::InvalidEscape(Position::new(3, 1))
+        );
+        assert_eq!(
+            first_err(r#""\u{110000}""#),
+            LexError::InvalidEscape(Position::new(2, 1))
+        );
+        assert_eq!(
+            first_err(r#"x"abc""#),
+            LexError::InvalidHexLiteral(Position::new(1, 1))
+        );
+        assert_eq!(
+            first_err(r#"x"0g""#),
+            LexError::InvalidHexLiteral(Position::new(1, 1))
+        );
+        assert_eq!(
+            first_err(r#"x"00"#),
+            LexError::UnterminatedString(Position::new(1, 1))
+        );
+    }
+
     #[test]
     fn test_tokenise_assign() {
-        let tokens: Vec<Token> = tokenise("a = 1").collect();
+        let tokens: Vec<Token> = tokenise("a = 1").map(Result::unwrap).collect();
         assert_eq!(
             tokens,
             vec![
@@ -666,7 +1173,9 @@ mod tests {
 
     #[test]
     fn test_tokenise_if() {
-        let tokens: Vec<Token> = tokenise("if (1) { 2 } else { 3 }").collect();
+        let tokens: Vec<Token> = tokenise("if (1) { 2 } else { 3 }")
+            .map(Result::unwrap)
+            .collect();
         assert_eq!(
             tokens,
             vec![
@@ -702,7 +1211,9 @@ mod tests {
 
     #[test]
     fn test_tokenise_gt() {
-        let tokens: Vec<Token> = tokenise("if (1 > 2) { 2 } else { 3 }").collect();
+        let tokens: Vec<Token> = tokenise("if (1 > 2) { 2 } else { 3 }")
+            .map(Result::unwrap)
+            .collect();
         assert_eq!(
             tokens,
             vec![
@@ -743,13 +1254,12 @@ mod tests {
 
     #[test]
     fn test_tokenise_caret() {
-        let tokens: Vec<Token> = tokenise("a ^= 10;").collect();
+        let tokens: Vec<Token> = tokenise("a ^= 10;").map(Result::unwrap).collect();
         assert_eq!(
             tokens,
             vec![
                 Token::new(TokenKind::Identifier("a".to_string()), Position::new(1, 1)),
-                Token::new(TokenKind::Symbol(Symbol::Caret), Position::new(3, 1)),
-                Token::new(TokenKind::Symbol(Symbol::Equal), Position::new(4, 1)),
+                Token::new(TokenKind::Symbol(Symbol::CaretEqual), Position::new(3, 1)),
                 Token::new(
                     TokenKind::NumberLiteral("10".to_owned()),
                     Position::new(6, 1)
@@ -761,13 +1271,15 @@ mod tests {
 
     #[test]
     fn test_tokenise_vertical_bar() {
-        let tokens: Vec<Token> = tokenise("a |= 10;").collect();
+        let tokens: Vec<Token> = tokenise("a |= 10;").map(Result::unwrap).collect();
         assert_eq!(
             tokens,
             vec![
                 Token::new(TokenKind::Identifier("a".to_string()), Position::new(1, 1)),
-                Token::new(TokenKind::Symbol(Symbol::VerticalBar), Position::new(3, 1)),
-                Token::new(TokenKind::Symbol(Symbol::Equal), Position::new(4, 1)),
+                Token::new(
+                    TokenKind::Symbol(Symbol::VerticalBarEqual),
+                    Position::new(3, 1)
+                ),
                 Token::new(
                     TokenKind::NumberLiteral("10".to_owned()),
                     Position::new(6, 1)
@@ -779,13 +1291,12 @@ mod tests {
 
     #[test]
     fn test_tokenise_percent() {
-        let tokens: Vec<Token> = tokenise("a %= 10;").collect();
+        let tokens: Vec<Token> = tokenise("a %= 10;").map(Result::unwrap).collect();
         assert_eq!(
             tokens,
             vec![
                 Token::new(TokenKind::Identifier("a".to_string()), Position::new(1, 1)),
-                Token::new(TokenKind::Symbol(Symbol::Percent), Position::new(3, 1)),
-                Token::new(TokenKind::Symbol(Symbol::Equal), Position::new(4, 1)),
+                Token::new(TokenKind::Symbol(Symbol::PercentEqual), Position::new(3, 1)),
                 Token::new(
                     TokenKind::NumberLiteral("10".to_owned()),
                     Position::new(6, 1)
@@ -797,7 +1308,9 @@ mod tests {
 
     #[test]
     fn test_tokenise_lt() {
-        let tokens: Vec<Token> = tokenise("if (1 < 2) { 2 } else { 3 }").collect();
+        let tokens: Vec<Token> = tokenise("if (1 < 2) { 2 } else { 3 }")
+            .map(Result::unwrap)
+            .collect();
         assert_eq!(
             tokens,
             vec![
@@ -838,7 +1351,9 @@ mod tests {
 
     #[test]
     fn test_tokenise_while() {
-        let tokens: Vec<Token> = tokenise("while (1) return 2;").collect();
+        let tokens: Vec<Token> = tokenise("while (1) return 2;")
+            .map(Result::unwrap)
+            .collect();
         assert_eq!(
             tokens,
             vec![
@@ -867,7 +1382,7 @@ mod tests {
 
     #[test]
     fn test_tokenise_new_line() {
-        let tokens: Vec<Token> = tokenise("1\n;").collect();
+        let tokens: Vec<Token> = tokenise("1\n;").map(Result::unwrap).collect();
         assert_eq!(
             tokens,
             vec![
@@ -880,9 +1395,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tokenise_crlf_and_mixed_line_endings() {
+        let input = "a\r\n\tb // c\r\n/* d\r\n*/ \"e\r\nf\"\n\r\ng\rh";
+        let tokens: Vec<Token> = tokenise(input).map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenKind::Identifier("a".to_string()), Position::new(1, 1)),
+                Token::new(TokenKind::Identifier("b".to_string()), Position::new(2, 2)),
+                Token::new(TokenKind::String("e\nf".to_string()), Position::new(4, 4)),
+                Token::new(TokenKind::Identifier("g".to_string()), Position::new(1, 7)),
+                Token::new(TokenKind::Identifier("h".to_string()), Position::new(3, 7)),
+            ]
+        );
+
+        // Spans are byte offsets, which include the `\r`
+        assert_eq!(tokens[1].position.span(), 4..5);
+        assert_eq!(tokens[4].position.span(), 32..33);
+    }
+
+    #[test]
+    fn test_tokenise_columns_count_characters() {
+        let tokens: Vec<Token> = tokenise("\"€\"\t\tx").map(Result::unwrap).collect();
+        assert_eq!(tokens[1].position, Position::new(6, 1));
+        assert_eq!(tokens[1].position.span(), 7..8);
+    }
+
     #[test]
     fn test_tokenise_float() {
-        let tokens: Vec<Token> = tokenise("1.342\n;").collect();
+        let tokens: Vec<Token> = tokenise("1.342\n;").map(Result::unwrap).collect();
         assert_eq!(
             tokens,
             vec![
@@ -895,10 +1437,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tokenise_number_literals() {
+        let tokens: Vec<Token> = tokenise("0xff 1_000 10u64 1.5e-3-1")
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(
+                    TokenKind::NumberLiteral("0xff".to_owned()),
+                    Position::new(1, 1)
+                ),
+                Token::new(
+                    TokenKind::NumberLiteral("1_000".to_owned()),
+                    Position::new(6, 1)
+                ),
+                Token::new(
+                    TokenKind::NumberLiteral("10u64".to_owned()),
+                    Position::new(12, 1)
+                ),
+                Token::new(
+                    TokenKind::NumberLiteral("1.5e-3".to_owned()),
+                    Position::new(18, 1)
+                ),
+                Token::new(TokenKind::Symbol(Symbol::Minus), Position::new(24, 1)),
+                Token::new(
+                    TokenKind::NumberLiteral("1".to_owned()),
+                    Position::new(25, 1)
+                ),
+            ]
+        );
+    }
+
     #[test]
     fn test_tokenises_division() {
-        let tokens: Vec<Token> =
-            tokenise("//this is a comment\nwhile (2 / 2 == 1) return 2;").collect();
+        let tokens: Vec<Token> = tokenise("//this is a comment\nwhile (2 / 2 == 1) return 2;")
+            .map(Result::unwrap)
+            .collect();
         assert_eq!(
             tokens,
             vec![
@@ -916,8 +1492,7 @@ mod tests {
                     TokenKind::NumberLiteral("2".to_owned()),
                     Position::new(12, 2)
                 ),
-                Token::new(TokenKind::Symbol(Symbol::Equal), Position::new(14, 2)),
-                Token::new(TokenKind::Symbol(Symbol::Equal), Position::new(15, 2)),
+                Token::new(TokenKind::Symbol(Symbol::EqualEqual), Position::new(14, 2)),
                 Token::new(
                     TokenKind::NumberLiteral("1".to_owned()),
                     Position::new(17, 2)
@@ -938,7 +1513,9 @@ mod tests {
 
     #[test]
     fn test_doesnt_tokenise_comments() {
-        let tokens: Vec<Token> = tokenise("//this is a comment\nwhile (1) return 2;").collect();
+        let tokens: Vec<Token> = tokenise("//this is a comment\nwhile (1) return 2;")
+            .map(Result::unwrap)
+            .collect();
         assert_eq!(
             tokens,
             vec![
@@ -967,7 +1544,9 @@ mod tests {
 
     #[test]
     fn test_doesnt_tokenise_multiline_comments() {
-        let tokens: Vec<Token> = tokenise("/*this is a comment*/\nwhile (1) return 2;").collect();
+        let tokens: Vec<Token> = tokenise("/*this is a comment*/\nwhile (1) return 2;")
+            .map(Result::unwrap)
+            .collect();
         assert_eq!(
             tokens,
             vec![
@@ -997,7 +1576,9 @@ mod tests {
     #[test]
     fn test_doesnt_tokenise_multiline_comments_2() {
         let tokens: Vec<Token> =
-            tokenise("/*this\nis\na\nmultiline\ncomment*/\nwhile (1) return 2;").collect();
+            tokenise("/*this\nis\na\nmultiline\ncomment*/\nwhile (1) return 2;")
+                .map(Result::unwrap)
+                .collect();
         assert_eq!(
             tokens,
             vec![
@@ -1027,7 +1608,9 @@ mod tests {
     #[test]
     fn test_doesnt_tokenise_multiline_comments_with_random_asterisks() {
         let tokens: Vec<Token> =
-            tokenise("/*this\n *is\na\n*multiline*\ncomment*/\nwhile (1) return 2;").collect();
+            tokenise("/*this\n *is\na\n*multiline*\ncomment*/\nwhile (1) return 2;")
+                .map(Result::unwrap)
+                .collect();
         assert_eq!(
             tokens,
             vec![
@@ -1054,9 +1637,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tokenise_loop_control() {
+        let tokens: Vec<Token> = tokenise("continue; break").map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenKind::Keyword(Keyword::Continue), Position::new(1, 1)),
+                Token::new(TokenKind::Symbol(Symbol::Semicolon), Position::new(9, 1)),
+                Token::new(TokenKind::Keyword(Keyword::Break), Position::new(11, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_booleans() {
+        let tokens: Vec<Token> = tokenise("bool true false truely")
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenKind::Keyword(Keyword::Bool), Position::new(1, 1)),
+                Token::new(TokenKind::Keyword(Keyword::True), Position::new(6, 1)),
+                Token::new(TokenKind::Keyword(Keyword::False), Position::new(11, 1)),
+                Token::new(
+                    TokenKind::Identifier("truely".to_owned()),
+                    Position::new(17, 1)
+                ),
+            ]
+        );
+    }
+
     #[test]
     fn test_tokenise_for() {
-        let tokens: Vec<Token> = tokenise("for (i = 10; i; i = i - 1) 2;").collect();
+        let tokens: Vec<Token> = tokenise("for (i = 10; i; i = i - 1) 2;")
+            .map(Result::unwrap)
+            .collect();
         assert_eq!(
             tokens,
             vec![
@@ -1097,7 +1714,9 @@ mod tests {
 
     #[test]
     fn test_tokenise_integer_declaration() {
-        let tokens: Vec<Token> = tokenise("let a: u16 = 54354;").collect();
+        let tokens: Vec<Token> = tokenise("let a: u16 = 54354;")
+            .map(Result::unwrap)
+            .collect();
         assert_eq!(
             tokens,
             vec![
@@ -1117,7 +1736,7 @@ mod tests {
 
     #[test]
     fn test_tokenise_array() {
-        let tokens: Vec<Token> = tokenise("a[0]").collect();
+        let tokens: Vec<Token> = tokenise("a[0]").map(Result::unwrap).collect();
         assert_eq!(
             tokens,
             vec![
@@ -1131,4 +1750,350 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_tokenise_range() {
+        let tokens: Vec<Token> = tokenise("a[1..2.5]").map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenKind::Identifier("a".to_string()), Position::new(1, 1)),
+                Token::new(TokenKind::Symbol(Symbol::BracketLeft), Position::new(2, 1)),
+                Token::new(
+                    TokenKind::NumberLiteral("1".to_owned()),
+                    Position::new(3, 1)
+                ),
+                Token::new(TokenKind::Symbol(Symbol::DotDot), Position::new(4, 1)),
+                Token::new(
+                    TokenKind::NumberLiteral("2.5".to_owned()),
+                    Position::new(6, 1)
+                ),
+                Token::new(TokenKind::Symbol(Symbol::BracketRight), Position::new(9, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_comparison_operators() {
+        let tokens: Vec<Token> = tokenise("a == b != c <= d >= e < f > g")
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenKind::Identifier("a".to_string()), Position::new(1, 1)),
+                Token::new(TokenKind::Symbol(Symbol::EqualEqual), Position::new(3, 1)),
+                Token::new(TokenKind::Identifier("b".to_string()), Position::new(6, 1)),
+                Token::new(
+                    TokenKind::Symbol(Symbol::ExclamationMarkEqual),
+                    Position::new(8, 1)
+                ),
+                Token::new(TokenKind::Identifier("c".to_string()), Position::new(11, 1)),
+                Token::new(
+                    TokenKind::Symbol(Symbol::LesserThanEqual),
+                    Position::new(13, 1)
+                ),
+                Token::new(TokenKind::Identifier("d".to_string()), Position::new(16, 1)),
+                Token::new(
+                    TokenKind::Symbol(Symbol::GreaterThanEqual),
+                    Position::new(18, 1)
+                ),
+                Token::new(TokenKind::Identifier("e".to_string()), Position::new(21, 1)),
+                Token::new(TokenKind::Symbol(Symbol::LesserThan), Position::new(23, 1)),
+                Token::new(TokenKind::Identifier("f".to_string()), Position::new(25, 1)),
+                Token::new(TokenKind::Symbol(Symbol::GreaterThan), Position::new(27, 1)),
+                Token::new(TokenKind::Identifier("g".to_string()), Position::new(29, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_logical_operators() {
+        let tokens: Vec<Token> = tokenise("!a && b || c & d | e")
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(
+                    TokenKind::Symbol(Symbol::ExclamationMark),
+                    Position::new(1, 1)
+                ),
+                Token::new(TokenKind::Identifier("a".to_string()), Position::new(2, 1)),
+                Token::new(
+                    TokenKind::Symbol(Symbol::AmpersandAmpersand),
+                    Position::new(4, 1)
+                ),
+                Token::new(TokenKind::Identifier("b".to_string()), Position::new(7, 1)),
+                Token::new(
+                    TokenKind::Symbol(Symbol::VerticalBarVerticalBar),
+                    Position::new(9, 1)
+                ),
+                Token::new(TokenKind::Identifier("c".to_string()), Position::new(12, 1)),
+                Token::new(TokenKind::Symbol(Symbol::Ampersand), Position::new(14, 1)),
+                Token::new(TokenKind::Identifier("d".to_string()), Position::new(16, 1)),
+                Token::new(TokenKind::Symbol(Symbol::VerticalBar), Position::new(18, 1)),
+                Token::new(TokenKind::Identifier("e".to_string()), Position::new(20, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_increment_and_compound_assignment() {
+        let tokens: Vec<Token> = tokenise("i++; a += 1; b -= 2;")
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenKind::Identifier("i".to_string()), Position::new(1, 1)),
+                Token::new(TokenKind::Symbol(Symbol::PlusPlus), Position::new(2, 1)),
+                Token::new(TokenKind::Symbol(Symbol::Semicolon), Position::new(4, 1)),
+                Token::new(TokenKind::Identifier("a".to_string()), Position::new(6, 1)),
+                Token::new(TokenKind::Symbol(Symbol::PlusEqual), Position::new(8, 1)),
+                Token::new(
+                    TokenKind::NumberLiteral("1".to_owned()),
+                    Position::new(11, 1)
+                ),
+                Token::new(TokenKind::Symbol(Symbol::Semicolon), Position::new(12, 1)),
+                Token::new(TokenKind::Identifier("b".to_string()), Position::new(14, 1)),
+                Token::new(TokenKind::Symbol(Symbol::MinusEqual), Position::new(16, 1)),
+                Token::new(
+                    TokenKind::NumberLiteral("2".to_owned()),
+                    Position::new(19, 1)
+                ),
+                Token::new(TokenKind::Symbol(Symbol::Semicolon), Position::new(20, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_compound_assignments() {
+        let tokens: Vec<Token> = tokenise("i-- *= /= &= <<= >>= <<== >>>")
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenKind::Identifier("i".to_string()), Position::new(1, 1)),
+                Token::new(TokenKind::Symbol(Symbol::MinusMinus), Position::new(2, 1)),
+                Token::new(
+                    TokenKind::Symbol(Symbol::AsteriskEqual),
+                    Position::new(5, 1)
+                ),
+                Token::new(TokenKind::Symbol(Symbol::SlashEqual), Position::new(8, 1)),
+                Token::new(
+                    TokenKind::Symbol(Symbol::AmpersandEqual),
+                    Position::new(11, 1)
+                ),
+                Token::new(
+                    TokenKind::Symbol(Symbol::LesserThanLesserThanEqual),
+                    Position::new(14, 1)
+                ),
+                Token::new(
+                    TokenKind::Symbol(Symbol::GreaterThanGreaterThanEqual),
+                    Position::new(18, 1)
+                ),
+                Token::new(
+                    TokenKind::Symbol(Symbol::LesserThanLesserThanEqual),
+                    Position::new(22, 1)
+                ),
+                Token::new(TokenKind::Symbol(Symbol::Equal), Position::new(25, 1)),
+                Token::new(
+                    TokenKind::Symbol(Symbol::GreaterThanGreaterThan),
+                    Position::new(27, 1)
+                ),
+                Token::new(TokenKind::Symbol(Symbol::GreaterThan), Position::new(29, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_arrow() {
+        let tokens: Vec<Token> = tokenise(") -> u8 - >").map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(
+                    TokenKind::Symbol(Symbol::ParenthesisRight),
+                    Position::new(1, 1)
+                ),
+                Token::new(
+                    TokenKind::Symbol(Symbol::MinusGreaterThan),
+                    Position::new(3, 1)
+                ),
+                Token::new(TokenKind::Keyword(Keyword::U8), Position::new(6, 1)),
+                Token::new(TokenKind::Symbol(Symbol::Minus), Position::new(9, 1)),
+                Token::new(TokenKind::Symbol(Symbol::GreaterThan), Position::new(11, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_shifts() {
+        let tokens: Vec<Token> = tokenise("a << 2 >> 1").map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenKind::Identifier("a".to_string()), Position::new(1, 1)),
+                Token::new(
+                    TokenKind::Symbol(Symbol::LesserThanLesserThan),
+                    Position::new(3, 1)
+                ),
+                Token::new(
+                    TokenKind::NumberLiteral("2".to_owned()),
+                    Position::new(6, 1)
+                ),
+                Token::new(
+                    TokenKind::Symbol(Symbol::GreaterThanGreaterThan),
+                    Position::new(8, 1)
+                ),
+                Token::new(
+                    TokenKind::NumberLiteral("1".to_owned()),
+                    Position::new(11, 1)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_longest_match() {
+        // Symbols are matched greedily from left to right.
+        let tokens: Vec<Token> = tokenise("+++==").map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenKind::Symbol(Symbol::PlusPlus), Position::new(1, 1)),
+                Token::new(TokenKind::Symbol(Symbol::PlusEqual), Position::new(3, 1)),
+                Token::new(TokenKind::Symbol(Symbol::Equal), Position::new(5, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_division_without_whitespace() {
+        let tokens: Vec<Token> = tokenise("a/b /\nc").map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenKind::Identifier("a".to_string()), Position::new(1, 1)),
+                Token::new(TokenKind::Symbol(Symbol::Slash), Position::new(2, 1)),
+                Token::new(TokenKind::Identifier("b".to_string()), Position::new(3, 1)),
+                Token::new(TokenKind::Symbol(Symbol::Slash), Position::new(5, 1)),
+                Token::new(TokenKind::Identifier("c".to_string()), Position::new(1, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_unterminated_string() {
+        let tokens: Vec<_> = tokenise("a \"dummy").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Ok(Token::new(
+                    TokenKind::Identifier("a".to_string()),
+                    Position::new(1, 1)
+                )),
+                Err(LexError::UnterminatedString(Position::new(3, 1))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_unterminated_block_comment() {
+        let tokens: Vec<_> = tokenise("a /* comment\n * ").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Ok(Token::new(
+                    TokenKind::Identifier("a".to_string()),
+                    Position::new(1, 1)
+                )),
+                Err(LexError::UnterminatedBlockComment(Position::new(3, 1))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_invalid_character() {
+        let tokens: Vec<_> = tokenise("a @ # b").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Ok(Token::new(
+                    TokenKind::Identifier("a".to_string()),
+                    Position::new(1, 1)
+                )),
+                Err(LexError::InvalidCharacter('@', Position::new(3, 1))),
+                Err(LexError::InvalidCharacter('#', Position::new(5, 1))),
+                Ok(Token::new(
+                    TokenKind::Identifier("b".to_string()),
+                    Position::new(7, 1)
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenise_identifiers() {
+        let tokens: Vec<Token> = tokenise("_a $b c_$1 _").map(Result::unwrap).collect();
+        let names: Vec<_> = tokens
+            .into_iter()
+            .map(|token| match token.kind {
+                TokenKind::Identifier(name) => name,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(names, vec!["_a", "$b", "c_$1", "_"]);
+    }
+
+    #[test]
+    fn test_tokenise_non_ascii_identifier() {
+        let tokens: Vec<_> = tokenise("a café; λ").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Ok(Token::new(
+                    TokenKind::Identifier("a".to_string()),
+                    Position::new(1, 1)
+                )),
+                Err(LexError::NonAsciiIdentifier('é', Position::new(3, 1))),
+                Ok(Token::new(
+                    TokenKind::Symbol(Symbol::Semicolon),
+                    Position::new(7, 1)
+                )),
+                Err(LexError::NonAsciiIdentifier('λ', Position::new(9, 1))),
+            ]
+        );
+        assert_eq!(tokens[1].as_ref().unwrap_err().position().span(), 2..7);
+    }
+
+    #[test]
+    fn test_tokenise_malformed_number() {
+        let tokens: Vec<_> = tokenise("1.2.3; 12ab; 1.;").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Err(LexError::MalformedNumber(Position::new(1, 1))),
+                Ok(Token::new(
+                    TokenKind::Symbol(Symbol::Semicolon),
+                    Position::new(6, 1)
+                )),
+                Err(LexError::InvalidNumberSuffix(
+                    "ab".to_owned(),
+                    Position::new(8, 1)
+                )),
+                Ok(Token::new(
+                    TokenKind::Symbol(Symbol::Semicolon),
+                    Position::new(12, 1)
+                )),
+                Err(LexError::MalformedNumber(Position::new(14, 1))),
+                Ok(Token::new(
+                    TokenKind::Symbol(Symbol::Semicolon),
+                    Position::new(16, 1)
+                )),
+            ]
+        );
+    }
 }
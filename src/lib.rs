@@ -0,0 +1,13 @@
+pub mod ast;
+pub mod bytecode;
+pub mod compiler;
+pub mod diagnostics;
+pub mod fixture;
+pub mod intrinsics;
+pub mod lexer;
+pub mod loader;
+pub mod number;
+pub mod parser;
+pub mod source_map;
+pub mod typeck;
+pub mod vm;
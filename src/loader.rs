@@ -0,0 +1,297 @@
+//! Loading of source files and grouping them into compilation units.
+//!
+//! Every file which declares `main` is the root of its own compilation unit.
+//! A unit consists of its root and every file reachable from it through
+//! `import` declarations. Files are only ever shared between units through
+//! imports, so two contracts in the same directory never see each other.
+
+use crate::ast::Program;
+use crate::compiler::CompilerErr;
+use crate::lexer::tokenise_file;
+use crate::parser::parse;
+use crate::source_map::{FileId, SourceMap};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct LoadedFile {
+    pub path: PathBuf,
+
+    /// Parsed contents of the file. Empty if the file failed to parse.
+    pub program: Program,
+
+    /// Files imported by this file, in declaration order
+    pub imports: Vec<FileId>,
+}
+
+impl LoadedFile {
+    /// Returns true if the file is the root of a compilation unit.
+    pub fn declares_main(&self) -> bool {
+        self.program
+            .functions
+            .iter()
+            .any(|function| function.is_main())
+    }
+}
+
+/// Loads source files and their imports. Every file is read and parsed only
+/// once, no matter how many units import it.
+pub struct Loader<R> {
+    read: R,
+    sources: SourceMap,
+    files: Vec<LoadedFile>,
+    ids: HashMap<PathBuf, FileId>,
+    errors: Vec<CompilerErr>,
+}
+
+impl<R: FnMut(&Path) -> io::Result<String>> Loader<R> {
+    /// Creates a loader which reads files through `read`.
+    pub fn new(read: R) -> Self {
+        Self {
+            read,
+            sources: SourceMap::new(),
+            files: vec![],
+            ids: HashMap::new(),
+            errors: vec![],
+        }
+    }
+
+    /// Loads the file at `path` along with everything it imports. Files are
+    /// added to the source map in the order they are loaded, so the id of a
+    /// file is also its index in `Loader::files`.
+    ///
+    /// Only failing to read `path` itself is returned as an error. Errors in
+    /// the contents of the loaded files are collected and available through
+    /// `Loader::errors`.
+    pub fn load(&mut self, path: &Path) -> io::Result<FileId> {
+        let path = normalize(path);
+        if let Some(id) = self.ids.get(&path) {
+            return Ok(*id);
+        }
+
+        let source = (self.read)(&path)?;
+        let id = self.sources.add(path.display().to_string(), source);
+        self.ids.insert(path.clone(), id);
+        self.files.push(LoadedFile {
+            path,
+            program: Program::default(),
+            imports: vec![],
+        });
+
+        let source = &self.sources.get(id).unwrap().source;
+        let parsed = tokenise_file(source, id)
+            .collect::<Result<_, _>>()
+            .map_err(CompilerErr::from)
+            .and_then(parse);
+        let program = match parsed {
+            Ok(program) => program,
+            Err(err) => {
+                self.errors.push(err);
+                return Ok(id);
+            }
+        };
+
+        let dir = self.files[id]
+            .path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        // Store the program before loading imports, so that cyclic imports
+        // see whether this file declares `main`.
+        let imports = program.imports.clone();
+        self.files[id].program = program;
+
+        for import in imports.iter() {
+            let import_path = dir.join(&import.path);
+            match self.load(&import_path) {
+                Ok(imported) => {
+                    if self.files[imported].declares_main() {
+                        self.errors
+                            .push(CompilerErr::ImportedMain(import.position.clone()));
+                    }
+                    self.files[id].imports.push(imported);
+                }
+                Err(_) => {
+                    let resolved = normalize(&import_path).display().to_string();
+                    self.errors.push(CompilerErr::UnresolvedImport(
+                        resolved,
+                        import.position.clone(),
+                    ));
+                }
+            }
+        }
+
+        Ok(id)
+    }
+
+    /// Returns the files making up the unit rooted at `root`. Imports come
+    /// before the files importing them and the root comes last.
+    pub fn unit(&self, root: FileId) -> Vec<FileId> {
+        let mut unit = vec![];
+        let mut visited = vec![false; self.files.len()];
+        self.visit(root, &mut visited, &mut unit);
+        unit
+    }
+
+    fn visit(&self, id: FileId, visited: &mut [bool], unit: &mut Vec<FileId>) {
+        if visited[id] {
+            return;
+        }
+
+        visited[id] = true;
+        for imported in self.files[id].imports.iter() {
+            self.visit(*imported, visited, unit);
+        }
+        unit.push(id);
+    }
+
+    pub fn file(&self, id: FileId) -> &LoadedFile {
+        &self.files[id]
+    }
+
+    pub fn files(&self) -> &[LoadedFile] {
+        &self.files
+    }
+
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// Errors found while loading. Their positions refer to the file they
+    /// occured in.
+    pub fn errors(&self) -> &[CompilerErr] {
+        &self.errors
+    }
+}
+
+/// Removes `.` and `..` components without touching the file system, so that
+/// the same file imported through different paths is only loaded once.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) {
+                    normalized.pop();
+                } else {
+                    normalized.push(component);
+                }
+            }
+            _ => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, Loader};
+    use crate::compiler::CompilerErr;
+    use crate::lexer::Position;
+    use crate::source_map::FileId;
+    use std::collections::HashMap;
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    fn loader(files: &[(&str, &str)]) -> Loader<impl FnMut(&Path) -> io::Result<String>> {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(path, source)| (PathBuf::from(path), source.to_string()))
+            .collect();
+
+        Loader::new(move |path: &Path| {
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        })
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("a/./b/../c.ps")), Path::new("a/c.ps"));
+        assert_eq!(
+            normalize(Path::new("../a/../../b.ps")),
+            Path::new("../../b.ps")
+        );
+    }
+
+    #[test]
+    fn test_unit_contains_imports_only() {
+        let mut loader = loader(&[
+            (
+                "a/main.ps",
+                "import \"../lib/math.ps\";\nfunction main() {}",
+            ),
+            ("b/main.ps", "function main() {}"),
+            ("lib/math.ps", "import \"./util.ps\";\nfunction double() {}"),
+            ("lib/util.ps", "import \"math.ps\";\nfunction id() {}"),
+        ]);
+
+        let a = loader.load(Path::new("a/main.ps")).unwrap();
+        let b = loader.load(Path::new("b/main.ps")).unwrap();
+        assert!(loader.errors().is_empty());
+
+        let paths = |unit: Vec<FileId>| -> Vec<_> {
+            unit.iter()
+                .map(|id| loader.file(*id).path.to_str().unwrap().to_owned())
+                .collect()
+        };
+        assert_eq!(
+            paths(loader.unit(a)),
+            vec!["lib/util.ps", "lib/math.ps", "a/main.ps"]
+        );
+        assert_eq!(paths(loader.unit(b)), vec!["b/main.ps"]);
+
+        // Each file is loaded once
+        assert_eq!(loader.files().len(), 4);
+        assert_eq!(loader.load(Path::new("lib/../a/main.ps")).unwrap(), a);
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let mut loader = loader(&[
+            (
+                "main.ps",
+                "import \"other.ps\";\nimport \"missing.ps\";\nfunction main() {}",
+            ),
+            ("other.ps", "function main() {}"),
+        ]);
+
+        loader.load(Path::new("main.ps")).unwrap();
+        assert_eq!(
+            loader.errors(),
+            &[
+                CompilerErr::ImportedMain(Position::new(1, 1)),
+                CompilerErr::UnresolvedImport("missing.ps".to_owned(), Position::new(1, 2)),
+            ]
+        );
+        assert!(loader.load(Path::new("nope.ps")).is_err());
+    }
+
+    #[test]
+    fn test_errors_refer_to_their_file() {
+        let mut loader = loader(&[
+            ("main.ps", "import \"lib.ps\";\nfunction main() {}"),
+            ("lib.ps", "import lib;"),
+        ]);
+
+        loader.load(Path::new("main.ps")).unwrap();
+        let err = &loader.errors()[0];
+        let position = Position {
+            file: 1,
+            ..Position::new(8, 1)
+        };
+        assert_eq!(err, &CompilerErr::ExpectedImportPath(position));
+        assert_eq!(err.position().span(), 7..10);
+        assert_eq!(loader.sources().get(1).unwrap().name, "lib.ps");
+    }
+}
@@ -1,38 +1,485 @@
-use crate::compiler::Compiler;
-use crate::lexer::tokenise;
+use base64::Engine;
+use clap::{Parser, Subcommand, ValueEnum};
 use glob::glob;
+use purple_script::ast::Param;
+use purple_script::compiler::{Compiler, CompilerErr, TestFunction};
+use purple_script::diagnostics::Diagnostic;
+use purple_script::fixture::Fixture;
+use purple_script::loader::Loader;
+use purple_script::source_map::{FileId, SourceMap};
+use purple_script::vm::{self, Outcome, TestResult};
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-fn main() {
-    let mut tokens: Vec<_> = vec![];
+/// Compiler for PurpleScript contracts
+#[derive(Parser)]
+#[command(name = "purple_script", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compile scripts to bytecode
+    Build {
+        /// A `.ps` file, or a directory which is searched recursively for `.ps` files
+        input: PathBuf,
+
+        /// Where to write the compiled script. Defaults to stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Encoding of the compiled script
+        #[arg(long, value_enum, default_value_t = Format::Hex)]
+        format: Format,
+
+        /// Where to write the revert reasons of the script. Defaults to the
+        /// output file with the extension `reasons.json`.
+        #[arg(long)]
+        reasons: Option<PathBuf>,
+
+        /// Leave the revert reasons out of the compiled script, so that
+        /// reverts only carry their code
+        #[arg(long)]
+        strip_reasons: bool,
+    },
+
+    /// Check scripts for errors without writing any output
+    Check {
+        /// A `.ps` file, or a directory which is searched recursively for `.ps` files
+        input: PathBuf,
+    },
 
-    for entry in glob("./**/*.ps").expect("Failed to read .ps files") {
-        match entry {
-            Ok(path) => {
-                let contents =
-                    fs::read_to_string(path).expect("Should have been able to read the file");
-                tokens.extend(tokenise(&contents));
+    /// Run a script in the transaction described by a fixture
+    Run {
+        /// A `.ps` file declaring `main`
+        script: PathBuf,
+
+        /// A `.json` or `.toml` file with the transaction and the arguments of `main`
+        fixture: PathBuf,
+
+        /// Gas available to the script, which aborts once it runs out
+        #[arg(long, default_value_t = vm::DEFAULT_GAS_LIMIT)]
+        gas_limit: u64,
+    },
+
+    /// Run the test functions of scripts
+    Test {
+        /// A `.ps` file, or a directory which is searched recursively for `.ps` files
+        input: PathBuf,
+
+        /// Gas available to each test, including the calls of `main`
+        #[arg(long, default_value_t = vm::DEFAULT_GAS_LIMIT)]
+        gas_limit: u64,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Hex,
+    Bin,
+    Base64,
+}
+
+/// Exit code when the scripts have errors.
+const EXIT_COMPILER_ERR: u8 = 1;
+
+/// Exit code when the input or output files could not be accessed.
+const EXIT_IO_ERR: u8 = 2;
+
+/// Exit code when a script reverts or aborts, or a test fails.
+const EXIT_SCRIPT_FAILED: u8 = 3;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Build {
+            input,
+            output,
+            format,
+            reasons,
+            strip_reasons,
+        } => {
+            let is_dir = input.is_dir();
+            if is_dir && output.is_none() {
+                eprintln!(
+                    "error: an output directory is required when building a directory, use `-o`"
+                );
+                return ExitCode::from(EXIT_IO_ERR);
+            }
+            if is_dir && reasons.is_some() {
+                eprintln!("error: `--reasons` cannot be used when building a directory");
+                return ExitCode::from(EXIT_IO_ERR);
             }
-            Err(e) => println!("{:?}", e),
+
+            let scripts = match compile_input(&input, strip_reasons) {
+                Ok(scripts) => scripts,
+                Err(code) => return ExitCode::from(code),
+            };
+
+            if scripts.is_empty() {
+                eprintln!(
+                    "error: no script declaring `main` found in {}",
+                    input.display()
+                );
+                return ExitCode::from(EXIT_COMPILER_ERR);
+            }
+
+            for Script {
+                path,
+                compiled,
+                reasons: table,
+                ..
+            } in scripts
+            {
+                let encoded = match format {
+                    Format::Hex => hex::encode(compiled).into_bytes(),
+                    Format::Bin => compiled,
+                    Format::Base64 => base64::engine::general_purpose::STANDARD
+                        .encode(compiled)
+                        .into_bytes(),
+                };
+
+                let destination = match &output {
+                    // Mirror the layout of the input directory
+                    Some(dir) if is_dir => {
+                        let relative = path.strip_prefix(&input).unwrap_or(&path);
+                        Some(dir.join(relative).with_extension(format.extension()))
+                    }
+                    Some(file) => Some(file.clone()),
+                    None => None,
+                };
+
+                let written = match &destination {
+                    Some(destination) => destination
+                        .parent()
+                        .map_or(Ok(()), fs::create_dir_all)
+                        .and_then(|_| fs::write(destination, &encoded)),
+                    None => io::stdout().write_all(&encoded),
+                };
+
+                // Scripts without revert reasons have no reasons file
+                let reasons_path = reasons.clone().or_else(|| {
+                    destination.map(|destination| destination.with_extension("reasons.json"))
+                });
+                let written = match reasons_path {
+                    Some(reasons_path) if !table.is_empty() => {
+                        written.and_then(|_| fs::write(reasons_path, reasons_json(&table)))
+                    }
+                    _ => written,
+                };
+
+                if let Err(err) = written {
+                    eprintln!("error: could not write output: {}", err);
+                    return ExitCode::from(EXIT_IO_ERR);
+                }
+            }
+
+            ExitCode::SUCCESS
         }
+
+        Command::Check { input } => match compile_input(&input, false) {
+            Ok(_) => ExitCode::SUCCESS,
+            Err(code) => ExitCode::from(code),
+        },
+
+        Command::Run {
+            script,
+            fixture,
+            gas_limit,
+        } => match run_script(&script, &fixture, gas_limit) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(code) => ExitCode::from(code),
+        },
+
+        Command::Test { input, gas_limit } => match run_tests(&input, gas_limit) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(code) => ExitCode::from(code),
+        },
     }
+}
 
-    let mut compiler = Compiler::new();
+/// Runs the test functions of every script found at `input` and prints
+/// their results.
+fn run_tests(input: &Path, gas_limit: u64) -> Result<(), u8> {
+    let scripts = compile_input(input, false)?;
+    let mut passed = 0;
+    let mut failed = 0;
 
-    for t in tokens {
-        match compiler.push_token(t) {
-            Ok(()) => {}
-            Err(err) => {
-                println!("Compiler err: {:?}", err); // TODO: Pretty errors
+    for script in scripts.iter() {
+        for test in script.tests.iter() {
+            let result = match vm::run_test(&script.tested, test.func, gas_limit) {
+                Ok(result) => result,
+                Err(err) => {
+                    eprintln!("error: invalid bytecode: {:?}", err);
+                    return Err(EXIT_COMPILER_ERR);
+                }
+            };
+
+            let name = format!("{}::{}", script.path.display(), test.name);
+            match result {
+                TestResult::Passed => {
+                    println!("test {} ... ok", name);
+                    passed += 1;
+                }
+                TestResult::Failed(reason) => {
+                    println!("test {} ... FAILED: {}", name, reason);
+                    failed += 1;
+                }
             }
         }
     }
 
+    let status = if failed == 0 { "ok" } else { "FAILED" };
     println!(
-        "Compiled successfuly! Output: \n\n{}",
-        hex::encode(compiler.compile())
+        "\ntest result: {}. {} passed, {} failed",
+        status, passed, failed
     );
+
+    if failed > 0 {
+        return Err(EXIT_SCRIPT_FAILED);
+    }
+    Ok(())
 }
 
-mod compiler;
-mod lexer;
+/// Runs the script at `path` in the transaction described by `fixture` and
+/// prints the outcome.
+fn run_script(path: &Path, fixture: &Path, gas_limit: u64) -> Result<(), u8> {
+    let script = match compile_input(path, false)?.pop() {
+        Some(script) => script,
+        None => {
+            eprintln!("error: {} does not declare `main`", path.display());
+            return Err(EXIT_COMPILER_ERR);
+        }
+    };
+
+    let text = fs::read_to_string(fixture).map_err(|err| {
+        eprintln!("error: could not read {}: {}", fixture.display(), err);
+        EXIT_IO_ERR
+    })?;
+
+    let parsed = match fixture.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => Fixture::from_json(&text),
+        Some("toml") => Fixture::from_toml(&text),
+        _ => {
+            eprintln!(
+                "error: {} is neither a `.json` nor a `.toml` file",
+                fixture.display()
+            );
+            return Err(EXIT_IO_ERR);
+        }
+    };
+    let (ctx, args) =
+        match parsed.and_then(|parsed| Ok((parsed.context()?, parsed.args(&script.params)?))) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                eprintln!("error: invalid fixture {}: {}", fixture.display(), err);
+                return Err(EXIT_IO_ERR);
+            }
+        };
+
+    let outcome = match vm::run(&script.compiled, &ctx, args, gas_limit) {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            eprintln!("error: invalid bytecode: {:?}", err);
+            return Err(EXIT_COMPILER_ERR);
+        }
+    };
+
+    match outcome {
+        Outcome::Success { ret, outputs } => {
+            match ret {
+                Some(value) => println!("success, returned {}", value),
+                None => println!("success"),
+            }
+            if !outputs.is_empty() {
+                println!("pushed outputs:");
+                for (i, output) in outputs.iter().enumerate() {
+                    println!("  {}: {}", i, output);
+                }
+            }
+            Ok(())
+        }
+        Outcome::Revert(revert) => {
+            match revert.reason {
+                _ if revert.code == 0 => println!("reverted"),
+                Some(reason) => println!("reverted with code {}: {}", revert.code, reason),
+                None => println!("reverted with code {}", revert.code),
+            }
+            Err(EXIT_SCRIPT_FAILED)
+        }
+        Outcome::Abort(abort) => {
+            println!("aborted: {}", abort);
+            Err(EXIT_SCRIPT_FAILED)
+        }
+    }
+}
+
+impl Format {
+    /// File extension used when writing into an output directory
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Hex => "hex",
+            Format::Bin => "psbc",
+            Format::Base64 => "b64",
+        }
+    }
+}
+
+/// A compiled compilation unit.
+struct Script {
+    /// Path of the root file of the unit
+    path: PathBuf,
+    compiled: Vec<u8>,
+
+    /// Parameters of the main function
+    params: Vec<Param>,
+
+    /// The compiled script along with its test functions
+    tested: Vec<u8>,
+
+    /// Test functions declared in the root file
+    tests: Vec<TestFunction>,
+
+    /// Revert reasons, the reason at index `i` having the code `i + 1`
+    reasons: Vec<String>,
+}
+
+/// Compiles every compilation unit found at `input`. Diagnostics are printed
+/// to stderr and the exit code is returned on failure.
+fn compile_input(input: &Path, strip_reasons: bool) -> Result<Vec<Script>, u8> {
+    let paths = match find_scripts(input) {
+        Ok(paths) => paths,
+        Err(err) => {
+            eprintln!("error: could not read {}: {}", input.display(), err);
+            return Err(EXIT_IO_ERR);
+        }
+    };
+
+    let mut loader = Loader::new(|path: &Path| fs::read_to_string(path));
+    let mut roots = vec![];
+
+    for path in paths.iter() {
+        match loader.load(path) {
+            Ok(idx) => {
+                if loader.file(idx).declares_main() {
+                    roots.push((path.clone(), idx));
+                }
+            }
+            Err(err) => {
+                eprintln!("error: could not read {}: {}", path.display(), err);
+                return Err(EXIT_IO_ERR);
+            }
+        }
+    }
+
+    for err in loader.errors().iter() {
+        report(loader.sources(), err);
+    }
+
+    let mut failed = !loader.errors().is_empty();
+    let mut scripts = vec![];
+    for (path, root) in roots {
+        let unit = loader.unit(root);
+        if loader
+            .errors()
+            .iter()
+            .any(|err| unit.contains(&err.position().file))
+        {
+            continue;
+        }
+
+        match compile_unit(&loader, path, &unit, strip_reasons) {
+            Ok(script) => scripts.push(script),
+            Err(()) => failed = true,
+        }
+    }
+
+    if failed {
+        return Err(EXIT_COMPILER_ERR);
+    }
+
+    Ok(scripts)
+}
+
+/// Compiles the files of a unit into a single script, reporting the first
+/// error. `path` is the path of the root file, which comes last in `unit`.
+fn compile_unit<R>(
+    loader: &Loader<R>,
+    path: PathBuf,
+    unit: &[FileId],
+    strip_reasons: bool,
+) -> Result<Script, ()>
+where
+    R: FnMut(&Path) -> io::Result<String>,
+{
+    let mut compiler = Compiler::new();
+    compiler.set_strip_reasons(strip_reasons);
+
+    let functions = || {
+        unit.iter()
+            .flat_map(|id| loader.file(*id).program.functions.iter())
+    };
+
+    // Declare everything first, so that functions can be called before
+    // they are defined
+    let result = functions()
+        .try_for_each(|function| compiler.declare_function(function))
+        .and_then(|_| functions().try_for_each(|function| compiler.push_function(function)));
+
+    if let Err(err) = result {
+        report(loader.sources(), &err);
+        return Err(());
+    }
+
+    let params = functions()
+        .find(|function| function.is_main())
+        .map_or(vec![], |main| main.params.clone());
+
+    // Only the tests of the root file run, the tests of an imported file
+    // would run once for every script importing it
+    let (tested, mut tests) = compiler.compile_tests();
+    tests.retain(|test| Some(&test.position.file) == unit.last());
+
+    Ok(Script {
+        path,
+        compiled: compiler.compile(),
+        params,
+        tested,
+        tests,
+        reasons: compiler.revert_reasons().to_vec(),
+    })
+}
+
+/// Returns the reasons file of a script, a JSON object mapping the codes of
+/// its revert reasons to the reasons.
+fn reasons_json(reasons: &[String]) -> String {
+    let codes: BTreeMap<_, _> = (1u16..).zip(reasons.iter()).collect();
+    let mut json = serde_json::to_string_pretty(&codes).unwrap();
+    json.push('\n');
+    json
+}
+
+fn report(sources: &SourceMap, err: &CompilerErr) {
+    eprintln!("{}", Diagnostic::from(err).render(sources));
+}
+
+/// Returns `input` if it is a file, or all `.ps` files below it if it is a directory.
+fn find_scripts(input: &Path) -> io::Result<Vec<PathBuf>> {
+    if !fs::metadata(input)?.is_dir() {
+        return Ok(vec![input.to_path_buf()]);
+    }
+
+    let pattern = input.join("**").join("*.ps");
+    let paths = glob(&pattern.to_string_lossy())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.into_error())?;
+
+    Ok(paths)
+}
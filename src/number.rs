@@ -0,0 +1,309 @@
+//! Number literals.
+//!
+//! ```text
+//! number   = (integer | float) suffix?
+//! integer  = "0x" hex+ | "0b" bin+ | "0o" oct+ | dec+
+//! float    = dec+ ("." dec+)? (("e" | "E") ("+" | "-")? dec+)?
+//! suffix   = "_"? ("u8" | "u16" | "u32" | "u64" | "u128" | "ubig"
+//!                | "i8" | "i16" | "i32" | "i64" | "i128" | "ibig" | "f32" | "f64")
+//! ```
+//!
+//! Digits may be separated by underscores, e.g. `1_000_000` or `0xffff_ffff`.
+//! Literals with a fractional part or an exponent are fractional and cannot
+//! have an integer suffix. Hexadecimal literals cannot have a float suffix,
+//! as `f32` and `f64` would be digits.
+
+use crate::ast::ValueType;
+use num_bigint::{BigInt, BigUint, Sign};
+use std::fmt;
+
+/// Largest exponent of a scaled literal. Values this large do not fit any
+/// fixed width type, and scaling by bigger exponents would take long.
+const MAX_EXPONENT: i64 = 128;
+
+/// The value of a number literal, which is `value * 10^exponent`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Number {
+    pub value: BigUint,
+    pub exponent: i64,
+
+    /// True if the literal has a fractional part or an exponent
+    pub fractional: bool,
+
+    /// Type given by the suffix of the literal, e.g. `u64` for `10u64`
+    pub suffix: Option<ValueType>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum NumberErr {
+    Malformed,
+
+    /// The suffix is not a type, or an integer type on a fractional literal
+    InvalidSuffix(String),
+}
+
+impl Number {
+    /// An integer literal without a suffix.
+    pub fn integer(value: u64) -> Self {
+        Self {
+            value: value.into(),
+            exponent: 0,
+            fractional: false,
+            suffix: None,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, NumberErr> {
+        let (radix, digits) = match text.get(..2) {
+            Some("0x") => (16, &text[2..]),
+            Some("0b") => (2, &text[2..]),
+            Some("0o") => (8, &text[2..]),
+            _ => (10, text),
+        };
+
+        let mut rest = digits;
+        let integer = take_digits(&mut rest, radix)?;
+
+        let mut fraction = String::new();
+        let mut exponent = 0i64;
+        let mut fractional = false;
+        if radix == 10 {
+            if let Some(after) = rest.strip_prefix('.') {
+                rest = after;
+                fraction = take_digits(&mut rest, 10)?;
+                fractional = true;
+            }
+
+            let after = rest.strip_prefix(['e', 'E']).filter(|after| {
+                // `e` must start an exponent, not a suffix
+                after.starts_with(|c: char| c.is_ascii_digit() || c == '+' || c == '-')
+            });
+            if let Some(after) = after {
+                let (negative, after) = match after.strip_prefix('-') {
+                    Some(after) => (true, after),
+                    None => (false, after.strip_prefix('+').unwrap_or(after)),
+                };
+                rest = after;
+                let digits = take_digits(&mut rest, 10)?;
+                exponent = digits.parse().map_err(|_| NumberErr::Malformed)?;
+                if negative {
+                    exponent = -exponent;
+                }
+                fractional = true;
+            }
+        }
+
+        let suffix = parse_suffix(rest, radix, fractional)?;
+        let value = BigUint::parse_bytes(format!("{}{}", integer, fraction).as_bytes(), radix)
+            .ok_or(NumberErr::Malformed)?;
+
+        Ok(Self {
+            value,
+            exponent: exponent - fraction.len() as i64,
+            fractional,
+            suffix,
+        })
+    }
+
+    /// Returns the value if it is an integer.
+    pub fn to_integer(&self, negative: bool) -> Option<BigInt> {
+        if self.fractional {
+            return None;
+        }
+
+        self.scaled(negative, 0)
+    }
+
+    /// Returns the value multiplied by `10^scale`, if the result is an integer.
+    pub fn scaled(&self, negative: bool, scale: u32) -> Option<BigInt> {
+        let exponent = self.exponent + scale as i64;
+        let magnitude = if self.value == BigUint::ZERO {
+            BigUint::ZERO
+        } else if exponent >= 0 {
+            if exponent > MAX_EXPONENT {
+                return None;
+            }
+            &self.value * BigUint::from(10u32).pow(exponent as u32)
+        } else {
+            // A value with less digits than the divisor is not a multiple of it
+            if exponent.unsigned_abs() as usize > self.value.to_string().len() {
+                return None;
+            }
+            let divisor = BigUint::from(10u32).pow(exponent.unsigned_abs() as u32);
+            if &self.value % &divisor != BigUint::ZERO {
+                return None;
+            }
+            &self.value / divisor
+        };
+
+        let sign = if negative { Sign::Minus } else { Sign::Plus };
+        Some(BigInt::from_biguint(sign, magnitude))
+    }
+
+    /// Returns the value rounded to the nearest `f64`.
+    pub fn to_f64(&self, negative: bool) -> f64 {
+        self.float_text(negative).parse().unwrap_or(f64::NAN)
+    }
+
+    /// Returns the value rounded to the nearest `f32`.
+    pub fn to_f32(&self, negative: bool) -> f32 {
+        self.float_text(negative).parse().unwrap_or(f32::NAN)
+    }
+
+    fn float_text(&self, negative: bool) -> String {
+        let sign = if negative { "-" } else { "" };
+        format!("{}{}e{}", sign, self.value, self.exponent)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.value.to_string();
+        if self.exponent >= 0 {
+            write!(f, "{}{}", digits, "0".repeat(self.exponent as usize))?;
+            if self.fractional {
+                f.write_str(".0")?;
+            }
+        } else {
+            let point = self.exponent.unsigned_abs() as usize;
+            let digits = format!("{:0>width$}", digits, width = point + 1);
+            let (integer, fraction) = digits.split_at(digits.len() - point);
+            write!(f, "{}.{}", integer, fraction)?;
+        }
+
+        match &self.suffix {
+            Some(suffix) => write!(f, "{}", suffix),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Takes the leading digits of `radix` and underscores from `text`. Returns
+/// the digits without underscores, of which there must be at least one.
+fn take_digits(text: &mut &str, radix: u32) -> Result<String, NumberErr> {
+    let end = text
+        .find(|c: char| !c.is_digit(radix) && c != '_')
+        .unwrap_or(text.len());
+    let digits: String = text[..end].chars().filter(|c| *c != '_').collect();
+    *text = &text[end..];
+
+    if digits.is_empty() {
+        return Err(NumberErr::Malformed);
+    }
+
+    Ok(digits)
+}
+
+fn parse_suffix(text: &str, radix: u32, fractional: bool) -> Result<Option<ValueType>, NumberErr> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    // Digits which are not digits of the radix, or a second `.`
+    if text.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return Err(NumberErr::Malformed);
+    }
+
+    let name = text.strip_prefix('_').unwrap_or(text);
+    let ty = match name {
+        "u8" => ValueType::U8,
+        "u16" => ValueType::U16,
+        "u32" => ValueType::U32,
+        "u64" => ValueType::U64,
+        "u128" => ValueType::U128,
+        "ubig" => ValueType::UBIG,
+        "i8" => ValueType::I8,
+        "i16" => ValueType::I16,
+        "i32" => ValueType::I32,
+        "i64" => ValueType::I64,
+        "i128" => ValueType::I128,
+        "ibig" => ValueType::IBIG,
+        "f32" if radix == 10 => ValueType::F32,
+        "f64" if radix == 10 => ValueType::F64,
+        _ => return Err(NumberErr::InvalidSuffix(name.to_owned())),
+    };
+
+    if fractional && !matches!(ty, ValueType::F32 | ValueType::F64) {
+        return Err(NumberErr::InvalidSuffix(name.to_owned()));
+    }
+
+    Ok(Some(ty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Number, NumberErr};
+    use crate::ast::ValueType;
+    use num_bigint::BigInt;
+
+    fn integer(text: &str) -> Option<BigInt> {
+        Number::parse(text).unwrap().to_integer(false)
+    }
+
+    #[test]
+    fn test_parse_integers() {
+        assert_eq!(integer("0"), Some(0.into()));
+        assert_eq!(integer("1_000_000"), Some(1_000_000.into()));
+        assert_eq!(integer("0xff"), Some(255.into()));
+        assert_eq!(integer("0xFFFF_ffff"), Some(0xffff_ffffu32.into()));
+        assert_eq!(integer("0b1010"), Some(10.into()));
+        assert_eq!(integer("0o17"), Some(15.into()));
+        assert_eq!(
+            integer("340282366920938463463374607431768211456"),
+            Some(BigInt::from(u128::MAX) + 1)
+        );
+    }
+
+    #[test]
+    fn test_parse_fractional() {
+        let number = Number::parse("1.5e-3").unwrap();
+        assert!(number.fractional);
+        assert_eq!(number.to_integer(false), None);
+        assert_eq!(number.scaled(false, 4), Some(15.into()));
+        assert_eq!(number.scaled(false, 3), None);
+        assert_eq!(number.to_f64(true), -0.0015);
+
+        assert_eq!(
+            Number::parse("2E+2").unwrap().scaled(false, 0),
+            Some(200.into())
+        );
+        assert_eq!(
+            Number::parse("1_000.000_1").unwrap().to_string(),
+            "1000.0001"
+        );
+        assert_eq!(Number::parse("0.05").unwrap().to_string(), "0.05");
+        assert_eq!(Number::parse("1e400").unwrap().scaled(false, 18), None);
+    }
+
+    #[test]
+    fn test_parse_suffixes() {
+        let suffix = |text| Number::parse(text).unwrap().suffix;
+        assert_eq!(suffix("10u64"), Some(ValueType::U64));
+        assert_eq!(suffix("10_i8"), Some(ValueType::I8));
+        assert_eq!(suffix("0xffu8"), Some(ValueType::U8));
+        assert_eq!(suffix("1.5f32"), Some(ValueType::F32));
+        assert_eq!(suffix("2ubig"), Some(ValueType::UBIG));
+        assert_eq!(Number::parse("0x1f32").unwrap().value, 0x1f32u32.into());
+        assert_eq!(Number::parse("10u64").unwrap().to_string(), "10u64");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for text in ["1.2.3", "1.", "0x", "0b102", "1e+", "0x.5", "1.e5"] {
+            assert_eq!(Number::parse(text), Err(NumberErr::Malformed), "{}", text);
+        }
+
+        let invalid = |text, suffix: &str| {
+            assert_eq!(
+                Number::parse(text),
+                Err(NumberErr::InvalidSuffix(suffix.to_owned()))
+            );
+        };
+        invalid("12ab", "ab");
+        invalid("1.5u8", "u8");
+        invalid("1e3i64", "i64");
+        invalid("0xfg", "g");
+        invalid("1e", "e");
+        invalid("10decimal", "decimal");
+    }
+}
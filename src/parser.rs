@@ -0,0 +1,1296 @@
+use crate::ast::{
+    BinaryOp, Block, Expr, ExprKind, Function, Import, Param, Program, Stmt, StmtKind, UnaryOp,
+    ValueType,
+};
+use crate::bytecode::MAX_TYPE_DEPTH;
+use crate::compiler::CompilerErr;
+use crate::lexer::{Keyword, LexError, Position, Symbol, Token, TokenKind};
+use crate::number::Number;
+use crate::typeck;
+
+pub fn parse(tokens: Vec<Token>) -> Result<Program, CompilerErr> {
+    Parser::new(tokens).parse_program()
+}
+
+/// Number of expressions and blocks which may be nested in each other. The
+/// parser and later stages recurse over them, so deeper nesting could
+/// overflow the stack.
+const MAX_NESTING: usize = 256;
+
+/// Recursive descent parser turning a stream of tokens into a `Program`.
+pub struct Parser {
+    tokens: Vec<Token>,
+    cursor: usize,
+
+    /// Number of expressions and blocks being parsed
+    depth: usize,
+
+    /// Number of blocks being parsed
+    blocks: usize,
+
+    /// Height of the expression parsed last, which is `1` for literals
+    height: usize,
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            cursor: 0,
+            depth: 0,
+            blocks: 0,
+            height: 0,
+        }
+    }
+
+    pub fn parse_program(&mut self) -> Result<Program, CompilerErr> {
+        let mut program = Program::default();
+
+        while let Some(token) = self.next() {
+            match token.kind {
+                TokenKind::Keyword(Keyword::Function) => {
+                    program
+                        .functions
+                        .push(self.parse_function(token.position, false)?);
+                }
+                TokenKind::Keyword(Keyword::Test) => {
+                    let next = self.expect_next()?;
+                    if next.kind != TokenKind::Keyword(Keyword::Function) {
+                        return Err(CompilerErr::ExpectedFunctionDefinition(next.position));
+                    }
+                    program
+                        .functions
+                        .push(self.parse_function(token.position, true)?);
+                }
+                TokenKind::Keyword(Keyword::Import) => {
+                    program.imports.push(self.parse_import(token.position)?);
+                }
+                _ => return Err(CompilerErr::ExpectedFunctionDefinition(token.position)),
+            }
+        }
+
+        Ok(program)
+    }
+
+    fn parse_import(&mut self, position: Position) -> Result<Import, CompilerErr> {
+        let token = self.expect_next()?;
+        let path = match token.kind {
+            TokenKind::String(path) => path,
+            _ => return Err(CompilerErr::ExpectedImportPath(token.position)),
+        };
+        self.expect_symbol(Symbol::Semicolon, CompilerErr::ExpectedSemicolon)?;
+
+        Ok(Import { path, position })
+    }
+
+    /// Parses a function after `function`, or after `test function` if `test`
+    /// is true.
+    fn parse_function(&mut self, position: Position, test: bool) -> Result<Function, CompilerErr> {
+        let name = self.expect_identifier()?;
+        self.expect_symbol(
+            Symbol::ParenthesisLeft,
+            CompilerErr::ExpectedLeftParanthesis,
+        )?;
+        let params = self.parse_params()?;
+        let ret = if self.eat_symbol(Symbol::MinusGreaterThan) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        self.expect_symbol(Symbol::BraceLeft, CompilerErr::ExpectedLeftBrace)?;
+        let body = self.parse_block()?;
+
+        Ok(Function {
+            name,
+            params,
+            ret,
+            body,
+            position,
+            test,
+        })
+    }
+
+    /// Parses function parameters up to and including the right paranthesis.
+    fn parse_params(&mut self) -> Result<Vec<Param>, CompilerErr> {
+        let mut params = vec![];
+
+        loop {
+            // We allow the right paranthesis after a comma.
+            if self.eat_symbol(Symbol::ParenthesisRight) {
+                return Ok(params);
+            }
+
+            let position = self.peek_position();
+            let malleable = self.eat_keyword(Keyword::Malleable);
+            let name = self.expect_identifier()?;
+            let typed = self.eat_symbol(Symbol::Colon);
+            let ty = if typed {
+                self.parse_type()?
+            } else {
+                ValueType::Any
+            };
+
+            params.push(Param {
+                name,
+                ty,
+                malleable,
+                position,
+            });
+
+            let token = self.expect_next()?;
+            match token.kind {
+                TokenKind::Symbol(Symbol::Comma) => {}
+                TokenKind::Symbol(Symbol::ParenthesisRight) => return Ok(params),
+                _ if !typed => {
+                    return Err(CompilerErr::ExpectedColonCommaOrRightParanthesis(
+                        token.position,
+                    ));
+                }
+                _ => return Err(CompilerErr::ExpectedCommaOrRightParanthesis(token.position)),
+            }
+        }
+    }
+
+    fn parse_type(&mut self) -> Result<ValueType, CompilerErr> {
+        self.parse_nested_type(0)
+    }
+
+    /// Parses the element type of `depth` enclosing array types.
+    fn parse_nested_type(&mut self, depth: usize) -> Result<ValueType, CompilerErr> {
+        let token = self.expect_next()?;
+        let ty = match token.kind {
+            // Deeper types could not be decoded from the bytecode
+            TokenKind::Symbol(Symbol::BracketLeft) if depth == MAX_TYPE_DEPTH => {
+                return Err(CompilerErr::TypeNestedTooDeeply(token.position))
+            }
+            TokenKind::Symbol(Symbol::BracketLeft) => return self.parse_array_type(depth),
+            TokenKind::Keyword(Keyword::Bool) => ValueType::Bool,
+            TokenKind::Keyword(Keyword::U8) => ValueType::U8,
+            TokenKind::Keyword(Keyword::U16) => ValueType::U16,
+            TokenKind::Keyword(Keyword::U32) => ValueType::U32,
+            TokenKind::Keyword(Keyword::U64) => ValueType::U64,
+            TokenKind::Keyword(Keyword::U128) => ValueType::U128,
+            TokenKind::Keyword(Keyword::UBIG) => ValueType::UBIG,
+            TokenKind::Keyword(Keyword::I8) => ValueType::I8,
+            TokenKind::Keyword(Keyword::I16) => ValueType::I16,
+            TokenKind::Keyword(Keyword::I32) => ValueType::I32,
+            TokenKind::Keyword(Keyword::I64) => ValueType::I64,
+            TokenKind::Keyword(Keyword::I128) => ValueType::I128,
+            TokenKind::Keyword(Keyword::IBIG) => ValueType::IBIG,
+            TokenKind::Keyword(Keyword::F32) => ValueType::F32,
+            TokenKind::Keyword(Keyword::F64) => ValueType::F64,
+            TokenKind::Keyword(Keyword::Decimal) => ValueType::Decimal,
+            TokenKind::Keyword(Keyword::Address) => ValueType::Address,
+            TokenKind::Keyword(Keyword::Asset) => ValueType::Asset,
+            TokenKind::Identifier(name) => ValueType::builtin(&name)
+                .ok_or_else(|| CompilerErr::ExpectedType(token.position.clone()))?,
+            _ => return Err(CompilerErr::ExpectedType(token.position)),
+        };
+
+        Ok(ty)
+    }
+
+    /// Parses an array type after its left bracket.
+    fn parse_array_type(&mut self, depth: usize) -> Result<ValueType, CompilerErr> {
+        let element = Box::new(self.parse_nested_type(depth + 1)?);
+        if !self.eat_symbol(Symbol::Semicolon) {
+            self.expect_symbol(Symbol::BracketRight, CompilerErr::ExpectedRightBracket)?;
+            return Ok(ValueType::Array(element));
+        }
+
+        let token = self.expect_next()?;
+        let len = match token.kind {
+            TokenKind::NumberLiteral(len) => Number::parse(&len)
+                .ok()
+                .filter(|len| len.suffix.as_ref().is_none_or(typeck::is_integer))
+                .and_then(|len| u32::try_from(len.to_integer(false)?).ok())
+                .ok_or(CompilerErr::ExpectedArrayLength(token.position))?,
+            _ => return Err(CompilerErr::ExpectedArrayLength(token.position)),
+        };
+        self.expect_symbol(Symbol::BracketRight, CompilerErr::ExpectedRightBracket)?;
+
+        Ok(ValueType::FixedArray(element, len))
+    }
+
+    /// Parses statements up to and including the right brace.
+    fn parse_block(&mut self) -> Result<Block, CompilerErr> {
+        self.nest()?;
+        self.blocks += 1;
+        let mut block = vec![];
+
+        while !self.eat_symbol(Symbol::BraceRight) {
+            block.push(self.parse_stmt()?);
+        }
+
+        self.blocks -= 1;
+        self.depth -= 1;
+        Ok(block)
+    }
+
+    fn parse_stmt(&mut self) -> Result<Stmt, CompilerErr> {
+        let position = self.peek_position();
+
+        // Statements ending in a block are not followed by a semicolon
+        let kind = if self.eat_keyword(Keyword::If) {
+            self.parse_if()?
+        } else if self.eat_keyword(Keyword::While) {
+            let cond = self.parse_condition()?;
+            self.expect_symbol(Symbol::BraceLeft, CompilerErr::ExpectedLeftBrace)?;
+            let body = self.parse_block()?;
+            StmtKind::While { cond, body }
+        } else if self.eat_keyword(Keyword::For) {
+            self.parse_for()?
+        } else {
+            let kind = self.parse_simple_stmt()?;
+            self.expect_symbol(Symbol::Semicolon, CompilerErr::ExpectedSemicolon)?;
+            kind
+        };
+
+        Ok(Stmt { kind, position })
+    }
+
+    /// Parses a statement which is terminated by a semicolon, without the
+    /// semicolon.
+    fn parse_simple_stmt(&mut self) -> Result<StmtKind, CompilerErr> {
+        if self.eat_keyword(Keyword::Let) {
+            return self.parse_let();
+        }
+
+        if self.eat_keyword(Keyword::Return) {
+            if self.peek_symbol(Symbol::Semicolon) {
+                return Ok(StmtKind::Return(None));
+            }
+
+            return Ok(StmtKind::Return(Some(self.parse_expr()?)));
+        }
+
+        if self.eat_keyword(Keyword::Revert) {
+            if !self.eat_symbol(Symbol::ParenthesisLeft) {
+                return Ok(StmtKind::Revert(None));
+            }
+
+            let token = self.expect_next()?;
+            let reason = match token.kind {
+                TokenKind::String(reason) => reason,
+                _ => return Err(CompilerErr::ExpectedRevertReason(token.position)),
+            };
+            self.expect_symbol(
+                Symbol::ParenthesisRight,
+                CompilerErr::ExpectedRightParanthesis,
+            )?;
+            return Ok(StmtKind::Revert(Some(reason)));
+        }
+
+        if self.eat_keyword(Keyword::Continue) {
+            return Ok(StmtKind::Continue);
+        }
+
+        if self.eat_keyword(Keyword::Break) {
+            return Ok(StmtKind::Break);
+        }
+
+        let expr = self.parse_expr()?;
+        let op = match self.peek().map(|token| &token.kind) {
+            Some(TokenKind::Symbol(Symbol::Equal)) => None,
+            Some(TokenKind::Symbol(Symbol::PlusEqual)) => Some(BinaryOp::Add),
+            Some(TokenKind::Symbol(Symbol::MinusEqual)) => Some(BinaryOp::Sub),
+            Some(TokenKind::Symbol(Symbol::AsteriskEqual)) => Some(BinaryOp::Mul),
+            Some(TokenKind::Symbol(Symbol::SlashEqual)) => Some(BinaryOp::Div),
+            Some(TokenKind::Symbol(Symbol::PercentEqual)) => Some(BinaryOp::Rem),
+            Some(TokenKind::Symbol(Symbol::LesserThanLesserThanEqual)) => Some(BinaryOp::Shl),
+            Some(TokenKind::Symbol(Symbol::GreaterThanGreaterThanEqual)) => Some(BinaryOp::Shr),
+            Some(TokenKind::Symbol(Symbol::AmpersandEqual)) => Some(BinaryOp::BitAnd),
+            Some(TokenKind::Symbol(Symbol::CaretEqual)) => Some(BinaryOp::BitXor),
+            Some(TokenKind::Symbol(Symbol::VerticalBarEqual)) => Some(BinaryOp::BitOr),
+            Some(TokenKind::Symbol(Symbol::PlusPlus)) => Some(BinaryOp::Add),
+            Some(TokenKind::Symbol(Symbol::MinusMinus)) => Some(BinaryOp::Sub),
+            _ => return Ok(StmtKind::Expr(expr)),
+        };
+
+        let name = match expr.kind {
+            ExprKind::Identifier(name) => name,
+            _ => return Err(CompilerErr::InvalidAssignmentTarget(expr.position)),
+        };
+
+        let value = if self.eat_symbol(Symbol::PlusPlus) || self.eat_symbol(Symbol::MinusMinus) {
+            Expr::new(ExprKind::Number(Number::integer(1)), expr.position)
+        } else {
+            self.cursor += 1;
+            self.parse_expr()?
+        };
+
+        Ok(StmtKind::Assign { name, op, value })
+    }
+
+    fn parse_let(&mut self) -> Result<StmtKind, CompilerErr> {
+        let name = self.expect_identifier()?;
+        let ty = if self.eat_symbol(Symbol::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        self.expect_symbol(Symbol::Equal, CompilerErr::ExpectedEqual)?;
+        let value = self.parse_expr()?;
+
+        Ok(StmtKind::Let { name, ty, value })
+    }
+
+    fn parse_if(&mut self) -> Result<StmtKind, CompilerErr> {
+        let cond = self.parse_condition()?;
+        self.expect_symbol(Symbol::BraceLeft, CompilerErr::ExpectedLeftBrace)?;
+        let then = self.parse_block()?;
+
+        let otherwise = if self.eat_keyword(Keyword::Else) {
+            let position = self.peek_position();
+            if self.eat_keyword(Keyword::If) {
+                let kind = self.parse_if()?;
+                Some(vec![Stmt { kind, position }])
+            } else {
+                self.expect_symbol(Symbol::BraceLeft, CompilerErr::ExpectedLeftBrace)?;
+                Some(self.parse_block()?)
+            }
+        } else {
+            None
+        };
+
+        Ok(StmtKind::If {
+            cond,
+            then,
+            otherwise,
+        })
+    }
+
+    fn parse_for(&mut self) -> Result<StmtKind, CompilerErr> {
+        self.expect_symbol(
+            Symbol::ParenthesisLeft,
+            CompilerErr::ExpectedLeftParanthesis,
+        )?;
+
+        let init = self.parse_for_clause(Symbol::Semicolon)?;
+        self.expect_symbol(Symbol::Semicolon, CompilerErr::ExpectedSemicolon)?;
+
+        let cond = if self.peek_symbol(Symbol::Semicolon) {
+            None
+        } else {
+            Some(self.parse_expr()?)
+        };
+        self.expect_symbol(Symbol::Semicolon, CompilerErr::ExpectedSemicolon)?;
+
+        let update = self.parse_for_clause(Symbol::ParenthesisRight)?;
+        self.expect_symbol(
+            Symbol::ParenthesisRight,
+            CompilerErr::ExpectedRightParanthesis,
+        )?;
+
+        self.expect_symbol(Symbol::BraceLeft, CompilerErr::ExpectedLeftBrace)?;
+        let body = self.parse_block()?;
+
+        Ok(StmtKind::For {
+            init,
+            cond,
+            update,
+            body,
+        })
+    }
+
+    /// Parses the initializer or the update of a `for` loop, which is empty
+    /// if it is directly followed by `end`.
+    fn parse_for_clause(&mut self, end: Symbol) -> Result<Option<Box<Stmt>>, CompilerErr> {
+        if self.peek_symbol(end) {
+            return Ok(None);
+        }
+
+        let position = self.peek_position();
+        let kind = self.parse_simple_stmt()?;
+        Ok(Some(Box::new(Stmt { kind, position })))
+    }
+
+    /// Parses a parenthesized condition of an `if` or a loop.
+    fn parse_condition(&mut self) -> Result<Expr, CompilerErr> {
+        self.expect_symbol(
+            Symbol::ParenthesisLeft,
+            CompilerErr::ExpectedLeftParanthesis,
+        )?;
+        let cond = self.parse_expr()?;
+        self.expect_symbol(
+            Symbol::ParenthesisRight,
+            CompilerErr::ExpectedRightParanthesis,
+        )?;
+
+        Ok(cond)
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, CompilerErr> {
+        self.nest()?;
+        let expr = self.parse_binary(0)?;
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    /// Precedence climbing over binary operators. Only operators binding
+    /// tighter than `min_precedence` are consumed. See `BinaryOp::precedence`
+    /// for the precedence table.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, CompilerErr> {
+        let mut lhs = self.parse_cast()?;
+        let mut height = self.height;
+
+        while let Some(op) = self.peek_binary_op() {
+            if op.precedence() <= min_precedence {
+                break;
+            }
+
+            self.cursor += 1;
+            let rhs = self.parse_binary(op.precedence())?;
+            let position = lhs.position.clone();
+            height = self.grow(height.max(self.height), &position)?;
+            lhs = Expr::new(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), position);
+        }
+
+        self.height = height;
+        Ok(lhs)
+    }
+
+    fn parse_cast(&mut self) -> Result<Expr, CompilerErr> {
+        let mut expr = self.parse_unary()?;
+        let mut height = self.height;
+
+        while self.eat_keyword(Keyword::As) {
+            let ty = self.parse_type()?;
+            let position = expr.position.clone();
+            height = self.grow(height, &position)?;
+            expr = Expr::new(ExprKind::Cast(Box::new(expr), ty), position);
+        }
+
+        self.height = height;
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, CompilerErr> {
+        let position = self.peek_position();
+
+        // Decrements are statements, so `--a` negates twice
+        let (op, count) = if self.eat_symbol(Symbol::Minus) {
+            (UnaryOp::Neg, 1)
+        } else if self.eat_symbol(Symbol::MinusMinus) {
+            (UnaryOp::Neg, 2)
+        } else if self.eat_symbol(Symbol::ExclamationMark) {
+            (UnaryOp::Not, 1)
+        } else {
+            return self.parse_postfix();
+        };
+
+        self.nest()?;
+        let mut expr = self.parse_unary()?;
+        self.depth -= 1;
+        for _ in 0..count {
+            self.height = self.grow(self.height, &position)?;
+            expr = Expr::new(ExprKind::Unary(op, Box::new(expr)), position.clone());
+        }
+        Ok(expr)
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, CompilerErr> {
+        let mut expr = self.parse_primary()?;
+        let mut height = self.height;
+
+        loop {
+            let position = expr.position.clone();
+            let kind = if self.eat_symbol(Symbol::ParenthesisLeft) {
+                let args = self.parse_args()?;
+                height = height.max(self.height);
+                ExprKind::Call(Box::new(expr), args)
+            } else if self.eat_symbol(Symbol::Dot) {
+                ExprKind::Member(Box::new(expr), self.expect_identifier()?)
+            } else if self.eat_symbol(Symbol::BracketLeft) {
+                let kind = self.parse_index(expr)?;
+                height = height.max(self.height);
+                self.expect_symbol(Symbol::BracketRight, CompilerErr::ExpectedRightBracket)?;
+                kind
+            } else {
+                self.height = height;
+                return Ok(expr);
+            };
+
+            height = self.grow(height, &position)?;
+            expr = Expr::new(kind, position);
+        }
+    }
+
+    /// Parses an index or a slice of `expr` after the left bracket. Sets
+    /// `height` to the height of the highest bound.
+    fn parse_index(&mut self, expr: Expr) -> Result<ExprKind, CompilerErr> {
+        let mut height = 0;
+        let start = if self.peek_symbol(Symbol::DotDot) {
+            None
+        } else {
+            let index = self.parse_expr()?;
+            height = self.height;
+            if !self.peek_symbol(Symbol::DotDot) {
+                return Ok(ExprKind::Index(Box::new(expr), Box::new(index)));
+            }
+            Some(Box::new(index))
+        };
+
+        self.eat_symbol(Symbol::DotDot);
+        let end = if self.peek_symbol(Symbol::BracketRight) {
+            None
+        } else {
+            let end = self.parse_expr()?;
+            height = height.max(self.height);
+            Some(Box::new(end))
+        };
+
+        self.height = height;
+        Ok(ExprKind::Slice(Box::new(expr), start, end))
+    }
+
+    /// Parses call arguments up to and including the right paranthesis. Sets
+    /// `height` to the height of the highest argument.
+    fn parse_args(&mut self) -> Result<Vec<Expr>, CompilerErr> {
+        let mut args = vec![];
+        let mut height = 0;
+
+        loop {
+            // We allow the right paranthesis after a comma.
+            if self.eat_symbol(Symbol::ParenthesisRight) {
+                self.height = height;
+                return Ok(args);
+            }
+
+            args.push(self.parse_expr()?);
+            height = height.max(self.height);
+
+            let token = self.expect_next()?;
+            match token.kind {
+                TokenKind::Symbol(Symbol::Comma) => {}
+                TokenKind::Symbol(Symbol::ParenthesisRight) => {
+                    self.height = height;
+                    return Ok(args);
+                }
+                _ => return Err(CompilerErr::ExpectedCommaOrRightParanthesis(token.position)),
+            }
+        }
+    }
+
+    /// Returns the binary operator at the cursor.
+    fn peek_binary_op(&self) -> Option<BinaryOp> {
+        let symbol = match self.peek() {
+            Some(Token {
+                kind: TokenKind::Symbol(symbol),
+                ..
+            }) => symbol,
+            _ => return None,
+        };
+
+        let op = match symbol {
+            Symbol::Asterisk => BinaryOp::Mul,
+            Symbol::Slash => BinaryOp::Div,
+            Symbol::Percent => BinaryOp::Rem,
+            Symbol::Plus => BinaryOp::Add,
+            Symbol::Minus => BinaryOp::Sub,
+            Symbol::LesserThanLesserThan => BinaryOp::Shl,
+            Symbol::GreaterThanGreaterThan => BinaryOp::Shr,
+            Symbol::Ampersand => BinaryOp::BitAnd,
+            Symbol::Caret => BinaryOp::BitXor,
+            Symbol::VerticalBar => BinaryOp::BitOr,
+            Symbol::LesserThan => BinaryOp::Lt,
+            Symbol::LesserThanEqual => BinaryOp::Le,
+            Symbol::GreaterThan => BinaryOp::Gt,
+            Symbol::GreaterThanEqual => BinaryOp::Ge,
+            Symbol::EqualEqual => BinaryOp::Eq,
+            Symbol::ExclamationMarkEqual => BinaryOp::Ne,
+            Symbol::AmpersandAmpersand => BinaryOp::And,
+            Symbol::VerticalBarVerticalBar => BinaryOp::Or,
+            _ => return None,
+        };
+
+        Some(op)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, CompilerErr> {
+        let token = self.expect_next()?;
+        let kind = match token.kind {
+            TokenKind::Identifier(identifier) => ExprKind::Identifier(identifier),
+            TokenKind::NumberLiteral(number) => {
+                // The lexer only produces valid literals
+                let number = Number::parse(&number).map_err(|_| {
+                    CompilerErr::Lex(LexError::MalformedNumber(token.position.clone()))
+                })?;
+                ExprKind::Number(number)
+            }
+            TokenKind::Keyword(Keyword::True) => ExprKind::Bool(true),
+            TokenKind::Keyword(Keyword::False) => ExprKind::Bool(false),
+            TokenKind::Keyword(Keyword::This) => ExprKind::This,
+            TokenKind::String(string) => ExprKind::String(string),
+            TokenKind::Bytes(bytes) => ExprKind::Bytes(bytes),
+            // Parentheses are not part of the tree and keep the height
+            TokenKind::Symbol(Symbol::ParenthesisLeft) => {
+                let expr = self.parse_expr()?;
+                self.expect_symbol(
+                    Symbol::ParenthesisRight,
+                    CompilerErr::ExpectedRightParanthesis,
+                )?;
+                return Ok(expr);
+            }
+            _ => return Err(CompilerErr::UnexpectedToken(token.position)),
+        };
+
+        self.height = 1;
+        Ok(Expr::new(kind, token.position))
+    }
+
+    /// Enters an expression or a block, unless it would be nested too deeply.
+    /// The caller leaves it by decrementing `depth`.
+    fn nest(&mut self) -> Result<(), CompilerErr> {
+        if self.depth == MAX_NESTING {
+            return Err(CompilerErr::NestedTooDeeply(self.peek_position()));
+        }
+
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Returns the height of a node at `position` whose highest child has
+    /// the given height. Chains of operators, casts and postfixes are parsed
+    /// in a loop, so their height is checked in addition to `depth`.
+    fn grow(&self, height: usize, position: &Position) -> Result<usize, CompilerErr> {
+        if self.blocks + height >= MAX_NESTING {
+            return Err(CompilerErr::NestedTooDeeply(position.clone()));
+        }
+
+        Ok(height + 1)
+    }
+
+    fn expect_identifier(&mut self) -> Result<String, CompilerErr> {
+        let token = self.expect_next()?;
+        match token.kind {
+            TokenKind::Identifier(identifier) => Ok(identifier),
+            _ => Err(CompilerErr::ExpectedIdentifier(token.position)),
+        }
+    }
+
+    fn expect_symbol(
+        &mut self,
+        symbol: Symbol,
+        err: fn(Position) -> CompilerErr,
+    ) -> Result<(), CompilerErr> {
+        let token = self.expect_next()?;
+        if token.kind == TokenKind::Symbol(symbol) {
+            Ok(())
+        } else {
+            Err(err(token.position))
+        }
+    }
+
+    fn peek_symbol(&self, symbol: Symbol) -> bool {
+        self.peek()
+            .is_some_and(|token| token.kind == TokenKind::Symbol(symbol))
+    }
+
+    fn eat_symbol(&mut self, symbol: Symbol) -> bool {
+        self.eat(TokenKind::Symbol(symbol))
+    }
+
+    fn eat_keyword(&mut self, keyword: Keyword) -> bool {
+        self.eat(TokenKind::Keyword(keyword))
+    }
+
+    fn eat(&mut self, kind: TokenKind) -> bool {
+        match self.peek() {
+            Some(token) if token.kind == kind => {
+                self.cursor += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_next(&mut self) -> Result<Token, CompilerErr> {
+        let position = self.peek_position();
+        self.next()
+            .ok_or(CompilerErr::UnexpectedEndOfInput(position))
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.cursor).cloned();
+        if token.is_some() {
+            self.cursor += 1;
+        }
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor)
+    }
+
+    /// Position of the next token, or of the last token if we are at the end.
+    fn peek_position(&self) -> Position {
+        self.peek()
+            .or_else(|| self.tokens.last())
+            .map(|token| token.position.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use crate::ast::{Expr, ExprKind, Function, Import, Param, Program, Stmt, StmtKind, ValueType};
+    use crate::compiler::CompilerErr;
+    use crate::lexer::{tokenise, Position};
+
+    fn parse_str(input: &str) -> Result<Program, CompilerErr> {
+        parse(tokenise(input).collect::<Result<_, _>>()?)
+    }
+
+    /// Parses a single expression statement and renders it as an s-expression.
+    fn parse_expr_str(input: &str) -> String {
+        let program = parse_str(&format!("function main() {{ {}; }}", input)).unwrap();
+        match &program.functions[0].body[0].kind {
+            StmtKind::Expr(expr) => sexpr(expr),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Parses the body of `main` and renders its statements as s-expressions.
+    fn parse_stmts_str(input: &str) -> String {
+        let program = parse_str(&format!("function main() {{ {} }}", input)).unwrap();
+        sblock(&program.functions[0].body)
+    }
+
+    fn sblock(block: &[Stmt]) -> String {
+        let stmts: Vec<_> = block.iter().map(sstmt).collect();
+        format!("[{}]", stmts.join(" "))
+    }
+
+    fn sstmt(stmt: &Stmt) -> String {
+        let opt = |expr: &Option<Expr>| expr.as_ref().map_or("_".to_owned(), sexpr);
+        let opt_stmt = |stmt: &Option<Box<Stmt>>| stmt.as_deref().map_or("_".to_owned(), sstmt);
+        match &stmt.kind {
+            StmtKind::Expr(expr) => sexpr(expr),
+            StmtKind::Let { name, ty, value } => match ty {
+                Some(ty) => format!("(let {} {:?} {})", name, ty, sexpr(value)),
+                None => format!("(let {} {})", name, sexpr(value)),
+            },
+            StmtKind::Assign { name, op, value } => match op {
+                Some(op) => format!("({:?}= {} {})", op, name, sexpr(value)),
+                None => format!("(= {} {})", name, sexpr(value)),
+            },
+            StmtKind::If {
+                cond,
+                then,
+                otherwise,
+            } => match otherwise {
+                Some(otherwise) => {
+                    format!(
+                        "(if {} {} {})",
+                        sexpr(cond),
+                        sblock(then),
+                        sblock(otherwise)
+                    )
+                }
+                None => format!("(if {} {})", sexpr(cond), sblock(then)),
+            },
+            StmtKind::While { cond, body } => format!("(while {} {})", sexpr(cond), sblock(body)),
+            StmtKind::For {
+                init,
+                cond,
+                update,
+                body,
+            } => format!(
+                "(for {} {} {} {})",
+                opt_stmt(init),
+                opt(cond),
+                opt_stmt(update),
+                sblock(body)
+            ),
+            StmtKind::Return(expr) => format!("(return {})", opt(expr)),
+            StmtKind::Revert(reason) => format!("(revert {:?})", reason),
+            StmtKind::Continue => "continue".to_owned(),
+            StmtKind::Break => "break".to_owned(),
+        }
+    }
+
+    fn sexpr(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Identifier(identifier) => identifier.clone(),
+            ExprKind::Number(number) => number.to_string(),
+            ExprKind::Bool(value) => value.to_string(),
+            ExprKind::String(string) => format!("{:?}", string),
+            ExprKind::Bytes(bytes) => {
+                let digits: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                format!("x\"{}\"", digits)
+            }
+            ExprKind::Unary(op, operand) => format!("({:?} {})", op, sexpr(operand)),
+            ExprKind::Binary(op, lhs, rhs) => format!("({:?} {} {})", op, sexpr(lhs), sexpr(rhs)),
+            ExprKind::Cast(operand, ty) => format!("(as {} {:?})", sexpr(operand), ty),
+            ExprKind::Call(callee, args) => {
+                let args: Vec<_> = args.iter().map(sexpr).collect();
+                format!("(call {} [{}])", sexpr(callee), args.join(" "))
+            }
+            ExprKind::Member(object, member) => format!("(. {} {})", sexpr(object), member),
+            ExprKind::This => "this".to_owned(),
+            ExprKind::Intrinsic(intrinsic, args) => {
+                let args: Vec<_> = std::iter::once(intrinsic.to_string())
+                    .chain(args.iter().map(sexpr))
+                    .collect();
+                format!("({})", args.join(" "))
+            }
+            ExprKind::Index(object, index) => format!("([] {} {})", sexpr(object), sexpr(index)),
+            ExprKind::Slice(object, start, end) => {
+                let bound =
+                    |bound: &Option<Box<Expr>>| bound.as_deref().map_or("_".to_owned(), sexpr);
+                format!("([..] {} {} {})", sexpr(object), bound(start), bound(end))
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(parse_str(""), Ok(Program::default()));
+    }
+
+    #[test]
+    fn test_parse_main_with_params() {
+        let program =
+            parse_str("function main(a, b: u8, malleable c: address,) { return a; }").unwrap();
+        assert_eq!(
+            program.functions,
+            vec![Function {
+                name: "main".to_owned(),
+                params: vec![
+                    Param {
+                        name: "a".to_owned(),
+                        ty: ValueType::Any,
+                        malleable: false,
+                        position: Position::new(15, 1),
+                    },
+                    Param {
+                        name: "b".to_owned(),
+                        ty: ValueType::U8,
+                        malleable: false,
+                        position: Position::new(18, 1),
+                    },
+                    Param {
+                        name: "c".to_owned(),
+                        ty: ValueType::Address,
+                        malleable: true,
+                        position: Position::new(25, 1),
+                    },
+                ],
+                ret: None,
+                body: vec![Stmt {
+                    kind: StmtKind::Return(Some(Expr::new(
+                        ExprKind::Identifier("a".to_owned()),
+                        Position::new(57, 1),
+                    ))),
+                    position: Position::new(50, 1),
+                }],
+                position: Position::new(1, 1),
+                test: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_multiple_functions() {
+        let program = parse_str("function helper() { 1; }\nfunction main() { return; }").unwrap();
+        let names: Vec<_> = program.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["helper", "main"]);
+        assert_eq!(
+            program.functions[1].body,
+            vec![Stmt {
+                kind: StmtKind::Return(None),
+                position: Position::new(19, 2),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_test_functions() {
+        let program = parse_str("function main() {}\ntest function main() {}").unwrap();
+        assert!(!program.functions[0].test);
+        assert!(program.functions[1].test);
+        assert!(!program.functions[1].is_main());
+        assert_eq!(program.functions[1].position, Position::new(1, 2));
+        assert_eq!(
+            parse_str("test main() {}"),
+            Err(CompilerErr::ExpectedFunctionDefinition(Position::new(6, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_imports() {
+        let program = parse_str("import \"lib/math.ps\";\nfunction main() {}").unwrap();
+        assert_eq!(
+            program.imports,
+            vec![Import {
+                path: "lib/math.ps".to_owned(),
+                position: Position::new(1, 1),
+            }]
+        );
+        assert_eq!(
+            parse_str("import math;"),
+            Err(CompilerErr::ExpectedImportPath(Position::new(8, 1)))
+        );
+        assert_eq!(
+            parse_str("import \"math.ps\" function main() {}"),
+            Err(CompilerErr::ExpectedSemicolon(Position::new(18, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_return_type() {
+        let program = parse_str("function add(a: u8, b: u8) -> u16 { return a + b; }").unwrap();
+        assert_eq!(program.functions[0].ret, Some(ValueType::U16));
+        assert_eq!(
+            parse_str("function add() -> {}"),
+            Err(CompilerErr::ExpectedType(Position::new(19, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_nesting_limit() {
+        // Parsing at the limit takes more than the stack of test threads in
+        // debug builds
+        let parser = std::thread::Builder::new().stack_size(16 << 20);
+        let test = parser.spawn(|| {
+            let parens = |n| {
+                format!(
+                    "function f() {{ return {}1{}; }}",
+                    "(".repeat(n),
+                    ")".repeat(n)
+                )
+            };
+            assert!(parse_str(&parens(254)).is_ok());
+            assert_eq!(
+                parse_str(&parens(300)),
+                Err(CompilerErr::NestedTooDeeply(Position::new(278, 1)))
+            );
+            assert_eq!(
+                parse_str(&format!(
+                    "function f() {{ return {}true; }}",
+                    "!".repeat(50000)
+                )),
+                Err(CompilerErr::NestedTooDeeply(Position::new(278, 1)))
+            );
+
+            let chain = |n| {
+                format!(
+                    "function f(a: i64) -> i64 {{\nreturn {}; }}",
+                    vec!["a"; n].join(" + ")
+                )
+            };
+            assert!(parse_str(&chain(200)).is_ok());
+            assert_eq!(
+                parse_str(&chain(100000)),
+                Err(CompilerErr::NestedTooDeeply(Position::new(8, 2)))
+            );
+
+            let ifs = format!(
+                "function f() {{\n{}{}}}",
+                "if (true) {\n".repeat(300),
+                "}".repeat(300)
+            );
+            assert_eq!(
+                parse_str(&ifs),
+                Err(CompilerErr::NestedTooDeeply(Position::new(5, 257)))
+            );
+        });
+        test.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_parse_array_types() {
+        let program =
+            parse_str("function f(a: [u8], b: [[u8; 32]; 2]) -> [u8; 64] { return a; }").unwrap();
+        let u8_array = |len| ValueType::FixedArray(Box::new(ValueType::U8), len);
+        assert_eq!(
+            program.functions[0].params[0].ty,
+            ValueType::Array(Box::new(ValueType::U8))
+        );
+        assert_eq!(
+            program.functions[0].params[1].ty,
+            ValueType::FixedArray(Box::new(u8_array(32)), 2)
+        );
+        assert_eq!(program.functions[0].ret, Some(u8_array(64)));
+
+        let program =
+            parse_str("function f(a: [u8; 0x20], b: [u8; 1_000], c: [u8; 2u32]) {}").unwrap();
+        assert_eq!(program.functions[0].params[0].ty, u8_array(32));
+        assert_eq!(program.functions[0].params[1].ty, u8_array(1000));
+        assert_eq!(program.functions[0].params[2].ty, u8_array(2));
+
+        assert_eq!(
+            parse_str("function f(a: [u8; n]) {}"),
+            Err(CompilerErr::ExpectedArrayLength(Position::new(20, 1)))
+        );
+        for len in ["1.5", "2f64", "1e3", "0x1_0000_0000"] {
+            assert_eq!(
+                parse_str(&format!("function f(a: [u8; {}]) {{}}", len)),
+                Err(CompilerErr::ExpectedArrayLength(Position::new(20, 1)))
+            );
+        }
+        assert_eq!(
+            parse_str("function f(a: [u8) {}"),
+            Err(CompilerErr::ExpectedRightBracket(Position::new(18, 1)))
+        );
+
+        let nested = |depth| {
+            format!(
+                "function f(a: {}u8{}) {{}}",
+                "[".repeat(depth),
+                "]".repeat(depth)
+            )
+        };
+        assert!(parse_str(&nested(32)).is_ok());
+        assert_eq!(
+            parse_str(&nested(33)),
+            Err(CompilerErr::TypeNestedTooDeeply(Position::new(47, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_expected_function_definition() {
+        assert_eq!(
+            parse_str("let a = 1;"),
+            Err(CompilerErr::ExpectedFunctionDefinition(Position::new(1, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_expected_identifier() {
+        assert_eq!(
+            parse_str("function (a) {}"),
+            Err(CompilerErr::ExpectedIdentifier(Position::new(10, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_expected_type() {
+        assert_eq!(
+            parse_str("function main(a: b) {}"),
+            Err(CompilerErr::ExpectedType(Position::new(18, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_expected_colon_comma_or_right_paranthesis() {
+        assert_eq!(
+            parse_str("function main(a b) {}"),
+            Err(CompilerErr::ExpectedColonCommaOrRightParanthesis(
+                Position::new(17, 1)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_expected_comma_or_right_paranthesis() {
+        assert_eq!(
+            parse_str("function main(a: u8 b) {}"),
+            Err(CompilerErr::ExpectedCommaOrRightParanthesis(Position::new(
+                21, 1
+            )))
+        );
+    }
+
+    #[test]
+    fn test_parse_expected_semicolon() {
+        assert_eq!(
+            parse_str("function main() { return 1 }"),
+            Err(CompilerErr::ExpectedSemicolon(Position::new(28, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_unexpected_end_of_input() {
+        assert_eq!(
+            parse_str("function main() { return 1;"),
+            Err(CompilerErr::UnexpectedEndOfInput(Position::new(27, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_arithmetic_precedence() {
+        assert_eq!(parse_expr_str("1 + 2 * 3"), "(Add 1 (Mul 2 3))");
+        assert_eq!(parse_expr_str("1 * 2 + 3"), "(Add (Mul 1 2) 3)");
+        assert_eq!(parse_expr_str("(1 + 2) * 3"), "(Mul (Add 1 2) 3)");
+        assert_eq!(parse_expr_str("1 - 2 - 3"), "(Sub (Sub 1 2) 3)");
+        assert_eq!(parse_expr_str("a % b / c"), "(Div (Rem a b) c)");
+    }
+
+    #[test]
+    fn test_parse_comparison_and_logical_precedence() {
+        assert_eq!(
+            parse_expr_str("a == b && c == d || e"),
+            "(Or (And (Eq a b) (Eq c d)) e)"
+        );
+        assert_eq!(parse_expr_str("a < b == c >= d"), "(Eq (Lt a b) (Ge c d))");
+        assert_eq!(parse_expr_str("a + 1 <= b"), "(Le (Add a 1) b)");
+    }
+
+    #[test]
+    fn test_parse_bitwise_precedence() {
+        assert_eq!(
+            parse_expr_str("a | b ^ c & d << 1"),
+            "(BitOr a (BitXor b (BitAnd c (Shl d 1))))"
+        );
+        assert_eq!(parse_expr_str("a >> 2 + 1"), "(Shr a (Add 2 1))");
+    }
+
+    #[test]
+    fn test_parse_let_and_assignments() {
+        assert_eq!(
+            parse_stmts_str("let a = 1; let b: u16 = a; b = 2; b += a * 2; b -= 1; b++;"),
+            "[(let a 1) (let b U16 a) (= b 2) (Add= b (Mul a 2)) (Sub= b 1) (Add= b 1)]"
+        );
+        assert_eq!(
+            parse_stmts_str("b *= 2; b /= 2; b %= 2; b <<= 1; b >>= 1; b &= 3; b ^= 3; b |= 3; b--;"),
+            "[(Mul= b 2) (Div= b 2) (Rem= b 2) (Shl= b 1) (Shr= b 1) (BitAnd= b 3) (BitXor= b 3) (BitOr= b 3) (Sub= b 1)]"
+        );
+        assert_eq!(
+            parse_str("function main() { let a; }"),
+            Err(CompilerErr::ExpectedEqual(Position::new(24, 1)))
+        );
+        assert_eq!(
+            parse_str("function main() { a.b = 1; }"),
+            Err(CompilerErr::InvalidAssignmentTarget(Position::new(19, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_if_else() {
+        assert_eq!(
+            parse_stmts_str("if (a) { return 1; } else if (b) { return 2; } else { return; }"),
+            "[(if a [(return 1)] [(if b [(return 2)] [(return _)])])]"
+        );
+        assert_eq!(parse_stmts_str("if (a < b) {} a;"), "[(if (Lt a b) []) a]");
+        assert_eq!(
+            parse_str("function main() { if a {} }"),
+            Err(CompilerErr::ExpectedLeftParanthesis(Position::new(22, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_loops() {
+        assert_eq!(
+            parse_stmts_str("while (a) { break; continue; }"),
+            "[(while a [break continue])]"
+        );
+        assert_eq!(
+            parse_stmts_str("for (let i = 0; i < XPU.outputsLen(); i++) { f(i); }"),
+            "[(for (let i 0) (Lt i (call (. XPU outputsLen) [])) (Add= i 1) [(call f [i])])]"
+        );
+        assert_eq!(parse_stmts_str("for (;;) {}"), "[(for _ _ _ [])]");
+    }
+
+    #[test]
+    fn test_parse_revert() {
+        assert_eq!(
+            parse_stmts_str("revert; revert(\"deadline passed\");"),
+            "[(revert None) (revert Some(\"deadline passed\"))]"
+        );
+        assert_eq!(
+            parse_str("function main() { revert(1); }"),
+            Err(CompilerErr::ExpectedRevertReason(Position::new(26, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_separated_operator_tokens() {
+        // Operators made of multiple characters must not contain whitespace.
+        assert_eq!(
+            parse_str("function main() { a = = b; }"),
+            Err(CompilerErr::UnexpectedToken(Position::new(23, 1)))
+        );
+        assert_eq!(
+            parse_str("function main() { a < < b; }"),
+            Err(CompilerErr::UnexpectedToken(Position::new(23, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_not_equal() {
+        assert_eq!(parse_expr_str("a != b == c"), "(Eq (Ne a b) c)");
+        assert_eq!(parse_expr_str("a != b && c"), "(And (Ne a b) c)");
+    }
+
+    #[test]
+    fn test_parse_unary() {
+        assert_eq!(parse_expr_str("-a * b"), "(Mul (Neg a) b)");
+        assert_eq!(parse_expr_str("--a"), "(Neg (Neg a))");
+        assert_eq!(parse_expr_str("a - -1"), "(Sub a (Neg 1))");
+        assert_eq!(parse_expr_str("!a && !!b"), "(And (Not a) (Not (Not b)))");
+        assert_eq!(parse_expr_str("!a.b == c"), "(Eq (Not (. a b)) c)");
+    }
+
+    #[test]
+    fn test_parse_casts() {
+        assert_eq!(
+            parse_expr_str("(transferredSum as decimal * rate) as i128"),
+            "(as (Mul (as transferredSum Decimal) rate) I128)"
+        );
+        assert_eq!(parse_expr_str("a as u8 + b"), "(Add (as a U8) b)");
+        assert_eq!(parse_expr_str("-a as i64"), "(as (Neg a) I64)");
+        assert_eq!(parse_expr_str("a as u8 as u16"), "(as (as a U8) U16)");
+    }
+
+    #[test]
+    fn test_parse_bool_literals() {
+        assert_eq!(parse_expr_str("!true || false"), "(Or (Not true) false)");
+        let program = parse_str("function f(a: bool) -> bool { return a; }").unwrap();
+        assert_eq!(program.functions[0].params[0].ty, ValueType::Bool);
+    }
+
+    #[test]
+    fn test_parse_postfix() {
+        assert_eq!(
+            parse_expr_str("XPU.getOutputAt(i)"),
+            "(call (. XPU getOutputAt) [i])"
+        );
+        assert_eq!(
+            parse_expr_str("output.input.publicKey"),
+            "(. (. output input) publicKey)"
+        );
+        assert_eq!(
+            parse_expr_str("XPU.outputsLen()"),
+            "(call (. XPU outputsLen) [])"
+        );
+        assert_eq!(parse_expr_str("a[i + 1][0]"), "([] ([] a (Add i 1)) 0)");
+        assert_eq!(parse_expr_str("a[1..n - 1]"), "([..] a 1 (Sub n 1))");
+        assert_eq!(parse_expr_str("a[..2][i..]"), "([..] ([..] a _ 2) i _)");
+        assert_eq!(parse_expr_str("a[..]"), "([..] a _ _)");
+        assert_eq!(parse_expr_str("f(a, b,)"), "(call f [a b])");
+        assert_eq!(parse_expr_str("-a.b"), "(Neg (. a b))");
+    }
+
+    #[test]
+    fn test_parse_example_expressions() {
+        assert_eq!(
+            parse_expr_str(
+                "output.colourHash == assetIn && output.colouredAddress == lpReceiverAddress"
+            ),
+            "(And (Eq (. output colourHash) assetIn) (Eq (. output colouredAddress) lpReceiverAddress))"
+        );
+        assert_eq!(
+            parse_expr_str("XPU.blockTimestamp > deadline"),
+            "(Gt (. XPU blockTimestamp) deadline)"
+        );
+    }
+
+    #[test]
+    fn test_parse_expected_right_paranthesis() {
+        assert_eq!(
+            parse_str("function main() { (a + b; }"),
+            Err(CompilerErr::ExpectedRightParanthesis(Position::new(25, 1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_expected_right_bracket() {
+        assert_eq!(
+            parse_str("function main() { a[0; }"),
+            Err(CompilerErr::ExpectedRightBracket(Position::new(22, 1)))
+        );
+    }
+}
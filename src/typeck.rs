@@ -0,0 +1,1383 @@
+//! Static type checking.
+//!
+//! The checker annotates every expression with its type and makes implicit
+//! conversions explicit by wrapping the converted expressions in casts. Only
+//! lossless conversions are implicit, narrowing always requires `as`. Values
+//! of type `any` convert implicitly from and to every type and are checked
+//! when the script runs.
+//!
+//! Number literals take the type their context expects, e.g. the `1` in
+//! `a + 1` has the type of `a`. Without a context, integer literals are `i64`
+//! and literals with a fractional part are `decimal`. A variable declared
+//! without a type and initialized with an integer literal, such as `i` in
+//! `let i = 0`, gets the type of the first value it is combined with.
+
+use crate::ast::{BinaryOp, Block, Expr, ExprKind, Function, Stmt, StmtKind, UnaryOp, ValueType};
+use crate::bytecode::OpCode;
+use crate::compiler::{encode_number, CompilerErr, Signature};
+use crate::intrinsics::{self, Intrinsic, Object};
+use crate::lexer::Position;
+use crate::number::Number;
+use std::collections::HashMap;
+
+/// Type checks a function and annotates it with types.
+pub(crate) fn check_function(
+    function: &mut Function,
+    functions: &HashMap<String, Signature>,
+) -> Result<(), CompilerErr> {
+    // Main may return with or without a value of any type
+    let ret = if function.is_main() {
+        Returns::Any
+    } else {
+        match &function.ret {
+            Some(ty) => Returns::Value(ty.clone()),
+            None => Returns::Nothing,
+        }
+    };
+
+    // The first pass only infers the types of variables initialized with
+    // literals. Its errors are reported by the second pass.
+    let mut inferred = HashMap::new();
+    let mut first = Checker::new(functions, ret.clone(), &mut inferred, true);
+    first.testing = function.test;
+    first.declare_params(function);
+    let _ = first.check_block(&mut function.body.clone());
+
+    let mut checker = Checker::new(functions, ret, &mut inferred, false);
+    checker.testing = function.test;
+    checker.declare_params(function);
+    checker.check_block(&mut function.body)
+}
+
+#[derive(Clone)]
+enum Returns {
+    Nothing,
+    Value(ValueType),
+    Any,
+}
+
+struct Var {
+    name: String,
+    ty: ValueType,
+
+    /// Index of the declaring `let` if the variable is initialized with a
+    /// literal and has no declared type
+    inferrable: Option<usize>,
+}
+
+struct Checker<'a> {
+    functions: &'a HashMap<String, Signature>,
+    ret: Returns,
+
+    /// Variables in scope, innermost last
+    vars: Vec<Var>,
+
+    /// Number of `let` statements checked so far. Identifies the `let`
+    /// statements across both passes.
+    lets: usize,
+
+    /// Types inferred in the first pass, by `let` index
+    inferred: &'a mut HashMap<usize, ValueType>,
+
+    /// True in the first pass
+    inferring: bool,
+
+    /// True in test functions, which have the builtin object `Test`
+    testing: bool,
+}
+
+impl<'a> Checker<'a> {
+    fn new(
+        functions: &'a HashMap<String, Signature>,
+        ret: Returns,
+        inferred: &'a mut HashMap<usize, ValueType>,
+        inferring: bool,
+    ) -> Self {
+        Self {
+            functions,
+            ret,
+            vars: vec![],
+            lets: 0,
+            inferred,
+            inferring,
+            testing: false,
+        }
+    }
+
+    fn declare_params(&mut self, function: &Function) {
+        for param in function.params.iter() {
+            self.declare(&param.name, param.ty.clone(), None);
+        }
+    }
+
+    fn declare(&mut self, name: &str, ty: ValueType, inferrable: Option<usize>) {
+        self.vars.push(Var {
+            name: name.to_owned(),
+            ty,
+            inferrable,
+        });
+    }
+
+    fn check_block(&mut self, block: &mut Block) -> Result<(), CompilerErr> {
+        let scope = self.vars.len();
+        let result = block.iter_mut().try_for_each(|stmt| self.check_stmt(stmt));
+        self.vars.truncate(scope);
+        result
+    }
+
+    fn check_stmt(&mut self, stmt: &mut Stmt) -> Result<(), CompilerErr> {
+        match &mut stmt.kind {
+            StmtKind::Expr(expr) => {
+                if let ExprKind::Call(_, _) = expr.kind {
+                    let ty = self.check_call(expr)?;
+                    expr.ty = ty.unwrap_or(ValueType::Any);
+                } else {
+                    self.check_expr(expr, None)?;
+                }
+            }
+
+            StmtKind::Let { name, ty, value } => {
+                let id = self.lets;
+                self.lets += 1;
+
+                if ty.is_none() && !self.inferring {
+                    *ty = self.inferred.get(&id).cloned();
+                }
+
+                match ty {
+                    Some(ty) => {
+                        self.check_coerce(value, ty)?;
+                        self.declare(name, ty.clone(), None);
+                    }
+                    None => {
+                        let value_ty = self.check_expr(value, None)?;
+                        let inferrable = is_literal(value) && is_integer(&value_ty);
+                        self.declare(name, value_ty.clone(), inferrable.then_some(id));
+                        *ty = Some(value_ty);
+                    }
+                }
+            }
+
+            StmtKind::Assign { name, op, value } => {
+                let var = self
+                    .vars
+                    .iter()
+                    .rposition(|var| var.name == *name)
+                    .ok_or_else(|| CompilerErr::UnknownIdentifier(stmt.position.clone()))?;
+
+                let mut var_ty = self.vars[var].ty.clone();
+                let value_ty = self.check_expr(value, Some(&var_ty))?;
+                // Literals adopt the type of the variable, which tells
+                // nothing about it
+                if value_ty != var_ty && self.infer(var, &value_ty) {
+                    var_ty = value_ty.clone();
+                }
+
+                match op {
+                    Some(op @ (BinaryOp::Shl | BinaryOp::Shr)) => {
+                        check_operand(*op, &var_ty, &stmt.position)?;
+                        check_operand(*op, &value_ty, &value.position)?;
+                    }
+                    Some(op) => {
+                        check_operand(*op, &var_ty, &stmt.position)?;
+                        self.coerce(value, &value_ty, &var_ty)?;
+                    }
+                    None => self.coerce(value, &value_ty, &var_ty)?,
+                }
+            }
+
+            StmtKind::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.check_cond(cond)?;
+                self.check_block(then)?;
+                if let Some(otherwise) = otherwise {
+                    self.check_block(otherwise)?;
+                }
+            }
+
+            StmtKind::While { cond, body } => {
+                self.check_cond(cond)?;
+                self.check_block(body)?;
+            }
+
+            StmtKind::For {
+                init,
+                cond,
+                update,
+                body,
+            } => {
+                let scope = self.vars.len();
+                let result = self.check_for(init, cond, update, body);
+                self.vars.truncate(scope);
+                result?;
+            }
+
+            StmtKind::Return(None) => {
+                if let Returns::Value(_) = self.ret {
+                    return Err(CompilerErr::MissingReturnValue(stmt.position.clone()));
+                }
+            }
+
+            StmtKind::Return(Some(expr)) => match self.ret.clone() {
+                Returns::Nothing => {
+                    return Err(CompilerErr::UnexpectedReturnValue(expr.position.clone()));
+                }
+                Returns::Value(ty) => self.check_coerce(expr, &ty)?,
+                Returns::Any => {
+                    self.check_expr(expr, None)?;
+                }
+            },
+
+            StmtKind::Revert(_) | StmtKind::Continue | StmtKind::Break => {}
+        }
+
+        Ok(())
+    }
+
+    fn check_for(
+        &mut self,
+        init: &mut Option<Box<Stmt>>,
+        cond: &mut Option<Expr>,
+        update: &mut Option<Box<Stmt>>,
+        body: &mut Block,
+    ) -> Result<(), CompilerErr> {
+        if let Some(init) = init {
+            self.check_stmt(init)?;
+        }
+        if let Some(cond) = cond {
+            self.check_cond(cond)?;
+        }
+
+        // The update is checked before the body, as the variables of the
+        // body are not in scope in the update.
+        if let Some(update) = update {
+            self.check_stmt(update)?;
+        }
+        self.check_block(body)
+    }
+
+    /// Checks an operand which must be a `bool`, such as a condition.
+    /// Integers are not implicitly converted to `bool`.
+    fn check_cond(&mut self, cond: &mut Expr) -> Result<(), CompilerErr> {
+        match self.check_expr(cond, Some(&ValueType::Bool))? {
+            ValueType::Bool => Ok(()),
+            ValueType::Any => {
+                wrap_cast(cond, &ValueType::Bool);
+                Ok(())
+            }
+            ty => Err(CompilerErr::InvalidOperandType(ty, cond.position.clone())),
+        }
+    }
+
+    /// Checks an expression and converts it to `ty`.
+    fn check_coerce(&mut self, expr: &mut Expr, ty: &ValueType) -> Result<(), CompilerErr> {
+        let found = self.check_expr(expr, Some(ty))?;
+        self.coerce(expr, &found, ty)
+    }
+
+    /// Converts `expr` of type `from` to `to`, wrapping it in a cast if needed.
+    fn coerce(
+        &mut self,
+        expr: &mut Expr,
+        from: &ValueType,
+        to: &ValueType,
+    ) -> Result<(), CompilerErr> {
+        if from == to || *to == ValueType::Any {
+            return Ok(());
+        }
+
+        if *from == ValueType::Any || widens(from, to) {
+            wrap_cast(expr, to);
+            return Ok(());
+        }
+
+        if let Some(var) = self.inferrable_var(expr) {
+            if self.infer(var, to) {
+                return Ok(());
+            }
+        }
+
+        Err(CompilerErr::TypeMismatch(
+            to.clone(),
+            from.clone(),
+            expr.position.clone(),
+        ))
+    }
+
+    /// Checks an expression, annotates it with its type and returns the type.
+    /// `expected` is the type the context expects, which number literals
+    /// adopt if possible.
+    fn check_expr(
+        &mut self,
+        expr: &mut Expr,
+        expected: Option<&ValueType>,
+    ) -> Result<ValueType, CompilerErr> {
+        let position = expr.position.clone();
+        let ty = match &mut expr.kind {
+            ExprKind::Identifier(identifier) => self
+                .vars
+                .iter()
+                .rev()
+                .find(|var| var.name == *identifier)
+                .map(|var| var.ty.clone())
+                .ok_or(CompilerErr::UnknownIdentifier(position))?,
+
+            ExprKind::Number(number) => literal_type(number, false, expected, &position)?,
+
+            ExprKind::Bool(_) => ValueType::Bool,
+
+            ExprKind::Bytes(bytes) => {
+                ValueType::FixedArray(Box::new(ValueType::U8), bytes.len() as u32)
+            }
+
+            ExprKind::Unary(UnaryOp::Neg, operand) => {
+                let ty = match &mut operand.kind {
+                    ExprKind::Number(number) => {
+                        let ty = literal_type(number, true, expected, &position)?;
+                        operand.ty = ty.clone();
+                        ty
+                    }
+                    _ => self.check_expr(operand, expected)?,
+                };
+
+                if !is_signed(&ty) && ty != ValueType::Any {
+                    return Err(CompilerErr::InvalidOperandType(ty, position));
+                }
+                ty
+            }
+
+            ExprKind::Unary(UnaryOp::Not, operand) => {
+                self.check_cond(operand)?;
+                ValueType::Bool
+            }
+
+            ExprKind::Binary(BinaryOp::And | BinaryOp::Or, lhs, rhs) => {
+                self.check_cond(lhs)?;
+                self.check_cond(rhs)?;
+                ValueType::Bool
+            }
+
+            ExprKind::Binary(op @ (BinaryOp::Shl | BinaryOp::Shr), lhs, rhs) => {
+                let ty = self.check_expr(lhs, expected)?;
+                check_operand(*op, &ty, &lhs.position)?;
+                let rhs_ty = self.check_expr(rhs, Some(&ValueType::U32))?;
+                check_operand(*op, &rhs_ty, &rhs.position)?;
+                ty
+            }
+
+            ExprKind::Binary(op, lhs, rhs) => {
+                let compares = is_comparison(*op);
+                let expected = if compares { None } else { expected };
+
+                // Check the literal last, so that it adopts the type of the
+                // other operand
+                let (lhs_ty, rhs_ty) = if is_literal(lhs) && !is_literal(rhs) {
+                    let rhs_ty = self.check_expr(rhs, expected)?;
+                    (self.check_expr(lhs, Some(&rhs_ty))?, rhs_ty)
+                } else {
+                    let lhs_ty = self.check_expr(lhs, expected)?;
+                    (lhs_ty.clone(), self.check_expr(rhs, Some(&lhs_ty))?)
+                };
+
+                if *op == BinaryOp::Add && (is_array(&lhs_ty) || is_array(&rhs_ty)) {
+                    let ty = concat(lhs, &lhs_ty, rhs, &rhs_ty)?;
+                    expr.ty = ty.clone();
+                    return Ok(ty);
+                }
+
+                let ty = self.unify(lhs, &lhs_ty, rhs, &rhs_ty)?;
+                check_operand(*op, &ty, &position)?;
+
+                if compares {
+                    ValueType::Bool
+                } else {
+                    ty
+                }
+            }
+
+            ExprKind::Cast(operand, to) => {
+                let from = self.check_expr(operand, None)?;
+                if !castable(&from, to) {
+                    return Err(CompilerErr::InvalidCast(from, to.clone(), position));
+                }
+                to.clone()
+            }
+
+            ExprKind::Call(_, _) => self
+                .check_call(expr)?
+                .ok_or(CompilerErr::NoReturnValue(position))?,
+
+            ExprKind::Member(object, name) => {
+                let (intrinsic, args) = match self.builtin_object(object, name) {
+                    Some(builtin) => (check_intrinsic(builtin, name, None, &position)?, vec![]),
+                    None => {
+                        // Fields of values, which are the first argument
+                        let ty = self.check_expr(object, None)?;
+                        let builtin = Object::of_type(&ty).ok_or_else(|| {
+                            CompilerErr::UnknownMember(
+                                ty.to_string(),
+                                name.clone(),
+                                position.clone(),
+                            )
+                        })?;
+                        let intrinsic = check_intrinsic(builtin, name, None, &position)?;
+                        let placeholder = Expr::new(ExprKind::Bool(false), position.clone());
+                        (
+                            intrinsic,
+                            vec![std::mem::replace(&mut **object, placeholder)],
+                        )
+                    }
+                };
+                expr.kind = ExprKind::Intrinsic(intrinsic, args);
+                intrinsic
+                    .ret
+                    .clone()
+                    .ok_or(CompilerErr::NoReturnValue(position))?
+            }
+
+            // Intrinsics are only created by the checker
+            ExprKind::Intrinsic(intrinsic, _) => intrinsic
+                .ret
+                .clone()
+                .ok_or(CompilerErr::NoReturnValue(position))?,
+
+            ExprKind::Index(object, index) => {
+                let ty = self.check_expr(object, None)?;
+                self.check_index(index)?;
+                match ty {
+                    ValueType::Array(element) | ValueType::FixedArray(element, _) => *element,
+                    ValueType::Any => ValueType::Any,
+                    _ => return Err(CompilerErr::InvalidOperandType(ty, object.position.clone())),
+                }
+            }
+
+            ExprKind::Slice(object, start, end) => {
+                let ty = self.check_expr(object, None)?;
+                for bound in [start, end].into_iter().flatten() {
+                    self.check_index(bound)?;
+                }
+                match ty {
+                    ValueType::Array(element) | ValueType::FixedArray(element, _) => {
+                        ValueType::Array(element)
+                    }
+                    ValueType::Any => ValueType::Any,
+                    _ => return Err(CompilerErr::InvalidOperandType(ty, object.position.clone())),
+                }
+            }
+
+            ExprKind::This => ValueType::Output,
+
+            ExprKind::String(_) => {
+                return Err(CompilerErr::UnsupportedExpression(position));
+            }
+        };
+
+        expr.ty = ty.clone();
+        Ok(ty)
+    }
+
+    /// Indices and bounds of slices are integers. Literals are `u32`, the
+    /// type of lengths.
+    fn check_index(&mut self, index: &mut Expr) -> Result<(), CompilerErr> {
+        let ty = self.check_expr(index, Some(&ValueType::U32))?;
+        if is_integer(&ty) || ty == ValueType::Any {
+            Ok(())
+        } else {
+            Err(CompilerErr::InvalidOperandType(ty, index.position.clone()))
+        }
+    }
+
+    /// Checks a call and returns the return type of the callee. Calls of
+    /// methods of builtin objects are replaced by their intrinsic.
+    fn check_call(&mut self, call: &mut Expr) -> Result<Option<ValueType>, CompilerErr> {
+        let (callee, args) = match &mut call.kind {
+            ExprKind::Call(callee, args) => (callee, args),
+            _ => unreachable!(),
+        };
+
+        let name = match &mut callee.kind {
+            ExprKind::Identifier(name) => name,
+            ExprKind::Member(object, method) => {
+                if let Some(object) = self.builtin_object(object, method) {
+                    let intrinsic = check_intrinsic(object, method, Some(args), &callee.position)?;
+                    if intrinsic.opcode == OpCode::ExpectRevert {
+                        if !matches!(args[0].kind, ExprKind::String(_)) {
+                            return Err(CompilerErr::ExpectedRevertReason(
+                                args[0].position.clone(),
+                            ));
+                        }
+                    } else {
+                        for (arg, ty) in args.iter_mut().zip(intrinsic.params.iter().flatten()) {
+                            self.check_coerce(arg, ty)?;
+                        }
+                    }
+                    call.kind = ExprKind::Intrinsic(intrinsic, std::mem::take(args));
+                    return Ok(intrinsic.ret.clone());
+                }
+
+                let ty = self.check_method(object, method, args, &callee.position)?;
+                callee.ty = ty.clone();
+                return Ok(Some(ty));
+            }
+            _ => return Err(CompilerErr::UnsupportedExpression(callee.position.clone())),
+        };
+
+        let functions = self.functions;
+        let signature = functions
+            .get(name)
+            .ok_or_else(|| CompilerErr::UnknownFunction(callee.position.clone()))?;
+
+        if args.len() != signature.params.len() {
+            return Err(CompilerErr::ArgumentCountMismatch(
+                signature.params.len(),
+                args.len(),
+                callee.position.clone(),
+            ));
+        }
+
+        for (arg, ty) in args.iter_mut().zip(signature.params.iter()) {
+            self.check_coerce(arg, ty)?;
+        }
+
+        Ok(signature.ret.clone())
+    }
+
+    /// Checks a call of a builtin array method and returns its return type.
+    ///
+    /// `len()` returns the length of an array. `takeFront(n)` and
+    /// `takeBack(n)` remove the first or last `n` elements from an array
+    /// variable and return them.
+    fn check_method(
+        &mut self,
+        object: &mut Expr,
+        method: &str,
+        args: &mut [Expr],
+        position: &Position,
+    ) -> Result<ValueType, CompilerErr> {
+        let params = match method {
+            "len" => 0,
+            "takeFront" | "takeBack" => 1,
+            _ => return Err(CompilerErr::UnsupportedExpression(position.clone())),
+        };
+
+        let ty = self.check_expr(object, None)?;
+        let element = match &ty {
+            ValueType::Array(element) => Some(element.clone()),
+            ValueType::FixedArray(element, _) if params == 0 => Some(element.clone()),
+            ValueType::Any => None,
+            _ => return Err(CompilerErr::InvalidOperandType(ty, object.position.clone())),
+        };
+
+        if args.len() != params {
+            return Err(CompilerErr::ArgumentCountMismatch(
+                params,
+                args.len(),
+                position.clone(),
+            ));
+        }
+
+        if params == 0 {
+            return Ok(ValueType::U32);
+        }
+
+        // The taken elements are removed from the variable
+        if !matches!(object.kind, ExprKind::Identifier(_)) {
+            return Err(CompilerErr::InvalidAssignmentTarget(
+                object.position.clone(),
+            ));
+        }
+        self.check_coerce(&mut args[0], &ValueType::U32)?;
+
+        Ok(element.map_or(ValueType::Any, ValueType::Array))
+    }
+
+    /// Returns the builtin object `expr` refers to, whose member `member` is
+    /// accessed. Variables hide builtin objects of the same name, and `this`
+    /// is an `Output` value unless `member` is one of its own members. `Test`
+    /// only exists in test functions.
+    fn builtin_object(&self, expr: &Expr, member: &str) -> Option<Object> {
+        match &expr.kind {
+            ExprKind::This => intrinsics::lookup(Object::This, member)
+                .next()
+                .map(|_| Object::This),
+            ExprKind::Identifier(name) if !self.vars.iter().any(|var| var.name == *name) => {
+                Object::from_name(name).filter(|object| *object != Object::Test || self.testing)
+            }
+            _ => None,
+        }
+    }
+
+    /// Converts the operands of a binary operator to a common type.
+    fn unify(
+        &mut self,
+        lhs: &mut Expr,
+        lhs_ty: &ValueType,
+        rhs: &mut Expr,
+        rhs_ty: &ValueType,
+    ) -> Result<ValueType, CompilerErr> {
+        if lhs_ty == rhs_ty {
+            return Ok(lhs_ty.clone());
+        }
+
+        // Either side may fix the type of an inferrable variable
+        for (expr, ty) in [(&*lhs, rhs_ty), (&*rhs, lhs_ty)] {
+            if let Some(var) = self.inferrable_var(expr) {
+                if self.infer(var, ty) {
+                    return Ok(ty.clone());
+                }
+            }
+        }
+
+        if *lhs_ty == ValueType::Any || widens(lhs_ty, rhs_ty) {
+            wrap_cast(lhs, rhs_ty);
+            Ok(rhs_ty.clone())
+        } else if *rhs_ty == ValueType::Any || widens(rhs_ty, lhs_ty) {
+            wrap_cast(rhs, lhs_ty);
+            Ok(lhs_ty.clone())
+        } else {
+            Err(CompilerErr::TypeMismatch(
+                lhs_ty.clone(),
+                rhs_ty.clone(),
+                rhs.position.clone(),
+            ))
+        }
+    }
+
+    /// Returns the variable `expr` refers to, if its type can still be inferred.
+    fn inferrable_var(&self, expr: &Expr) -> Option<usize> {
+        match &expr.kind {
+            ExprKind::Identifier(name) => self
+                .vars
+                .iter()
+                .rposition(|var| var.name == *name)
+                .filter(|var| self.vars[*var].inferrable.is_some()),
+            _ => None,
+        }
+    }
+
+    /// Infers the type of an inferrable variable in the first pass. Returns
+    /// true if the variable now has type `ty`.
+    fn infer(&mut self, var: usize, ty: &ValueType) -> bool {
+        let id = match self.vars[var].inferrable {
+            Some(id) if self.inferring && is_integer(ty) => id,
+            _ => return false,
+        };
+
+        if self.inferred.contains_key(&id) {
+            return false;
+        }
+
+        self.inferred.insert(id, ty.clone());
+        self.vars[var].ty = ty.clone();
+        true
+    }
+}
+
+/// Resolves the member `name` of a builtin object. `args` are the arguments
+/// if the member is called, which select among methods of the same name.
+fn check_intrinsic(
+    object: Object,
+    name: &str,
+    args: Option<&[Expr]>,
+    position: &Position,
+) -> Result<&'static Intrinsic, CompilerErr> {
+    let mut candidates = intrinsics::lookup(object, name).peekable();
+    let first = *candidates.peek().ok_or_else(|| {
+        CompilerErr::UnknownMember(object.to_string(), name.to_owned(), position.clone())
+    })?;
+
+    match (args, &first.params) {
+        (None, None) => Ok(first),
+        (None, Some(_)) => Err(CompilerErr::MethodNotCalled(
+            first.to_string(),
+            position.clone(),
+        )),
+        (Some(_), None) => Err(CompilerErr::NotAMethod(first.to_string(), position.clone())),
+        (Some(args), Some(params)) => candidates
+            .find(|intrinsic| intrinsic.params.as_ref().map(Vec::len) == Some(args.len()))
+            .ok_or_else(|| {
+                CompilerErr::ArgumentCountMismatch(params.len(), args.len(), position.clone())
+            }),
+    }
+}
+
+/// Checks the concatenation of two arrays with `+`. The result has a fixed
+/// length if both arrays have.
+fn concat(
+    lhs: &mut Expr,
+    lhs_ty: &ValueType,
+    rhs: &mut Expr,
+    rhs_ty: &ValueType,
+) -> Result<ValueType, CompilerErr> {
+    let element = match (array_element(lhs_ty), array_element(rhs_ty)) {
+        (Some(lhs), Some(rhs)) if lhs == rhs => lhs,
+        (Some(lhs), None) if *rhs_ty == ValueType::Any => {
+            wrap_cast(rhs, &ValueType::Array(Box::new(lhs.clone())));
+            lhs
+        }
+        (None, Some(rhs)) if *lhs_ty == ValueType::Any => {
+            wrap_cast(lhs, &ValueType::Array(Box::new(rhs.clone())));
+            rhs
+        }
+        _ => {
+            return Err(CompilerErr::TypeMismatch(
+                lhs_ty.clone(),
+                rhs_ty.clone(),
+                rhs.position.clone(),
+            ));
+        }
+    };
+
+    let ty = match (lhs_ty, rhs_ty) {
+        (ValueType::FixedArray(_, lhs), ValueType::FixedArray(_, rhs)) => {
+            match lhs.checked_add(*rhs) {
+                Some(len) => ValueType::FixedArray(Box::new(element), len),
+                None => ValueType::Array(Box::new(element)),
+            }
+        }
+        _ => ValueType::Array(Box::new(element)),
+    };
+
+    Ok(ty)
+}
+
+/// Checks that `ty` is a valid operand type of `op`.
+fn check_operand(op: BinaryOp, ty: &ValueType, position: &Position) -> Result<(), CompilerErr> {
+    let valid = *ty == ValueType::Any
+        || match op {
+            BinaryOp::Add
+            | BinaryOp::Sub
+            | BinaryOp::Mul
+            | BinaryOp::Div
+            | BinaryOp::Rem
+            | BinaryOp::Lt
+            | BinaryOp::Le
+            | BinaryOp::Gt
+            | BinaryOp::Ge => is_numeric(ty),
+            BinaryOp::Shl
+            | BinaryOp::Shr
+            | BinaryOp::BitAnd
+            | BinaryOp::BitXor
+            | BinaryOp::BitOr => is_integer(ty),
+            BinaryOp::And | BinaryOp::Or => *ty == ValueType::Bool,
+            BinaryOp::Eq | BinaryOp::Ne => true,
+        };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(CompilerErr::InvalidOperandType(
+            ty.clone(),
+            position.clone(),
+        ))
+    }
+}
+
+/// Returns the type of a number literal. `negative` is true if the literal
+/// is the operand of a negation.
+fn literal_type(
+    number: &Number,
+    negative: bool,
+    expected: Option<&ValueType>,
+    position: &Position,
+) -> Result<ValueType, CompilerErr> {
+    let fractional = number.fractional;
+    let ty = match (&number.suffix, expected) {
+        (Some(suffix), _) => suffix.clone(),
+        (_, Some(ty @ (ValueType::F32 | ValueType::F64 | ValueType::Decimal))) => ty.clone(),
+        (_, Some(ty)) if !fractional && is_integer(ty) => ty.clone(),
+        _ if fractional => ValueType::Decimal,
+        _ => ValueType::I64,
+    };
+
+    // The literal fits its type if it can be encoded as a constant of it
+    if encode_number(number, negative, &ty).is_none() {
+        return Err(CompilerErr::LiteralOutOfRange(ty, position.clone()));
+    }
+
+    Ok(ty)
+}
+
+/// Returns true if every value of `from` can be represented by `to`.
+fn widens(from: &ValueType, to: &ValueType) -> bool {
+    use ValueType::*;
+
+    let rank = |ty: &ValueType| match ty {
+        U8 | I8 => 1,
+        U16 | I16 => 2,
+        U32 | I32 => 3,
+        U64 | I64 => 4,
+        U128 | I128 => 5,
+        _ => 0,
+    };
+
+    match (from, to) {
+        (U8 | U16 | U32 | U64 | U128, U16 | U32 | U64 | U128) => rank(from) < rank(to),
+        (U8 | U16 | U32 | U64 | U128, I16 | I32 | I64 | I128) => rank(from) < rank(to),
+        (I8 | I16 | I32 | I64 | I128, I16 | I32 | I64 | I128) => rank(from) < rank(to),
+        (U8 | U16 | U32 | U64 | U128 | UBIG, UBIG | IBIG) => true,
+        (I8 | I16 | I32 | I64 | I128, IBIG) => true,
+
+        // Decimals are `i128` scaled by `10^18`, so they can hold 64 bit integers
+        (U8 | U16 | U32 | U64 | I8 | I16 | I32 | I64, Decimal) => true,
+        (F32, F64) => true,
+
+        // Fixed length arrays are arrays
+        (FixedArray(from, _), Array(to)) => from == to,
+        _ => false,
+    }
+}
+
+/// Returns true if `from` can be converted to `to` with `as`.
+fn castable(from: &ValueType, to: &ValueType) -> bool {
+    use ValueType::*;
+
+    match (from, to) {
+        _ if from == to || *from == Any || *to == Any => true,
+        _ if is_numeric(from) && is_numeric(to) => true,
+
+        // `true` is `1` and `false` is `0`
+        (Bool, _) => is_integer(to),
+
+        // Between arrays of the same element type, which fails at runtime
+        // if the length does not match
+        (Array(from) | FixedArray(from, _), Array(to) | FixedArray(to, _)) => from == to,
+
+        // Addresses, assets, keys and hashes to and from their bytes
+        (Address | Asset | PublicKey | Hash, Array(element) | FixedArray(element, _))
+        | (Array(element) | FixedArray(element, _), Address | Asset | PublicKey | Hash) => {
+            **element == U8
+        }
+        _ => false,
+    }
+}
+
+fn wrap_cast(expr: &mut Expr, ty: &ValueType) {
+    let position = expr.position.clone();
+    let inner = std::mem::replace(expr, Expr::new(ExprKind::Bool(false), position.clone()));
+    *expr = Expr::new(ExprKind::Cast(Box::new(inner), ty.clone()), position);
+    expr.ty = ty.clone();
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Number(_) => true,
+        ExprKind::Unary(UnaryOp::Neg, operand) => matches!(operand.kind, ExprKind::Number(_)),
+        _ => false,
+    }
+}
+
+fn is_array(ty: &ValueType) -> bool {
+    array_element(ty).is_some()
+}
+
+fn array_element(ty: &ValueType) -> Option<ValueType> {
+    match ty {
+        ValueType::Array(element) | ValueType::FixedArray(element, _) => Some((**element).clone()),
+        _ => None,
+    }
+}
+
+fn is_comparison(op: BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge | BinaryOp::Eq | BinaryOp::Ne
+    )
+}
+
+pub(crate) fn is_integer(ty: &ValueType) -> bool {
+    matches!(
+        ty,
+        ValueType::U8
+            | ValueType::U16
+            | ValueType::U32
+            | ValueType::U64
+            | ValueType::U128
+            | ValueType::UBIG
+            | ValueType::I8
+            | ValueType::I16
+            | ValueType::I32
+            | ValueType::I64
+            | ValueType::I128
+            | ValueType::IBIG
+    )
+}
+
+fn is_signed(ty: &ValueType) -> bool {
+    matches!(
+        ty,
+        ValueType::I8
+            | ValueType::I16
+            | ValueType::I32
+            | ValueType::I64
+            | ValueType::I128
+            | ValueType::IBIG
+            | ValueType::F32
+            | ValueType::F64
+            | ValueType::Decimal
+    )
+}
+
+fn is_numeric(ty: &ValueType) -> bool {
+    is_integer(ty) || matches!(ty, ValueType::F32 | ValueType::F64 | ValueType::Decimal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_function;
+    use crate::ast::{ExprKind, Function, StmtKind, ValueType};
+    use crate::compiler::{CompilerErr, Signature};
+    use crate::lexer::{tokenise, Position};
+    use crate::parser::parse;
+    use std::collections::HashMap;
+
+    fn check_str(input: &str) -> Result<Vec<Function>, CompilerErr> {
+        let mut functions = parse(tokenise(input).collect::<Result<_, _>>()?)?.functions;
+        let signatures: HashMap<_, _> = functions
+            .iter()
+            .enumerate()
+            .map(|(idx, function)| {
+                let signature = Signature {
+                    idx: idx + 1,
+                    params: function
+                        .params
+                        .iter()
+                        .map(|param| param.ty.clone())
+                        .collect(),
+                    ret: function.ret.clone(),
+                    position: function.position.clone(),
+                };
+                (function.name.clone(), signature)
+            })
+            .collect();
+
+        for function in functions.iter_mut() {
+            check_function(function, &signatures)?;
+        }
+        Ok(functions)
+    }
+
+    #[test]
+    fn test_check_rejects_narrowing() {
+        assert_eq!(
+            check_str("function f(a: u16) -> u8 { return a; }"),
+            Err(CompilerErr::TypeMismatch(
+                ValueType::U8,
+                ValueType::U16,
+                Position::new(35, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f(a: u32, b: i32) { a + b; }"),
+            Err(CompilerErr::TypeMismatch(
+                ValueType::U32,
+                ValueType::I32,
+                Position::new(34, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function g(a: u8) {} function f(a: u16) { g(a); }"),
+            Err(CompilerErr::TypeMismatch(
+                ValueType::U8,
+                ValueType::U16,
+                Position::new(45, 1)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_check_inserts_widening_casts() {
+        let functions = check_str("function f(a: u8, b: i16) -> i64 { return a + b; }").unwrap();
+        let ret = match &functions[0].body[0].kind {
+            StmtKind::Return(Some(expr)) => expr,
+            _ => unreachable!(),
+        };
+        assert_eq!(ret.ty, ValueType::I64);
+        let (lhs, rhs) = match &ret.kind {
+            ExprKind::Cast(operand, ValueType::I64) => match &operand.kind {
+                ExprKind::Binary(_, lhs, rhs) => (lhs, rhs),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        assert!(matches!(lhs.kind, ExprKind::Cast(_, ValueType::I16)));
+        assert_eq!(rhs.ty, ValueType::I16);
+    }
+
+    #[test]
+    fn test_check_literals_adopt_their_context() {
+        let functions =
+            check_str("function f(a: u8) -> u8 { let b: i8 = -128; return a + 1; }").unwrap();
+        let ret = match &functions[0].body[1].kind {
+            StmtKind::Return(Some(expr)) => expr,
+            _ => unreachable!(),
+        };
+        match &ret.kind {
+            ExprKind::Binary(_, lhs, rhs) => {
+                assert_eq!(lhs.ty, ValueType::U8);
+                assert_eq!(rhs.ty, ValueType::U8);
+            }
+            _ => unreachable!(),
+        }
+
+        assert_eq!(
+            check_str("function f() { let a: u8 = 256; }"),
+            Err(CompilerErr::LiteralOutOfRange(
+                ValueType::U8,
+                Position::new(28, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f() { let a: u64 = -1; }"),
+            Err(CompilerErr::LiteralOutOfRange(
+                ValueType::U64,
+                Position::new(29, 1)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_check_literal_suffixes_and_bounds() {
+        let functions = check_str("function f() -> u64 { return 10u8; }").unwrap();
+        let ret = match &functions[0].body[0].kind {
+            StmtKind::Return(Some(expr)) => expr,
+            _ => unreachable!(),
+        };
+        assert!(
+            matches!(&ret.kind, ExprKind::Cast(operand, ValueType::U64) if operand.ty == ValueType::U8)
+        );
+
+        assert!(check_str("function f() { let a: u8 = 0xff; let b: ubig = 0x1_0000_0000_0000_0000_0000_0000_0000_0000; }").is_ok());
+        assert_eq!(
+            check_str("function f() { let a: u8 = 0x100; }"),
+            Err(CompilerErr::LiteralOutOfRange(
+                ValueType::U8,
+                Position::new(28, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f() { 300u8; }"),
+            Err(CompilerErr::LiteralOutOfRange(
+                ValueType::U8,
+                Position::new(16, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f() { let a: ubig = -1; }"),
+            Err(CompilerErr::LiteralOutOfRange(
+                ValueType::UBIG,
+                Position::new(30, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f() { let a: decimal = 1e-19; }"),
+            Err(CompilerErr::LiteralOutOfRange(
+                ValueType::Decimal,
+                Position::new(33, 1)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_check_infers_let_types() {
+        let functions =
+            check_str("function f(n: u32) { let a = 1.5; for (let i = 0; i < n; i++) {} }")
+                .unwrap();
+        let body = &functions[0].body;
+        assert!(matches!(
+            &body[0].kind,
+            StmtKind::Let {
+                ty: Some(ValueType::Decimal),
+                ..
+            }
+        ));
+        let init = match &body[1].kind {
+            StmtKind::For {
+                init: Some(init), ..
+            } => init,
+            _ => unreachable!(),
+        };
+        assert!(matches!(
+            &init.kind,
+            StmtKind::Let {
+                ty: Some(ValueType::U32),
+                ..
+            }
+        ));
+
+        // Increments do not fix the type before the body is checked
+        assert!(check_str(
+            "function f() { let s: u64 = 0; for (let i = 0; i < 10; i++) { s += i; } }"
+        )
+        .is_ok());
+        let functions = check_str(
+            "function f() -> u64 { let s: u64 = 0; for (let i = 0; i < 10; i += 1) { s += i; } return s; }",
+        )
+        .unwrap();
+        let init = match &functions[0].body[1].kind {
+            StmtKind::For {
+                init: Some(init), ..
+            } => init,
+            _ => unreachable!(),
+        };
+        assert!(matches!(
+            &init.kind,
+            StmtKind::Let {
+                ty: Some(ValueType::U64),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_check_operand_types() {
+        assert_eq!(
+            check_str("function f(a: f64) { a << 1; }"),
+            Err(CompilerErr::InvalidOperandType(
+                ValueType::F64,
+                Position::new(22, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f(a: address) { a + a; }"),
+            Err(CompilerErr::InvalidOperandType(
+                ValueType::Address,
+                Position::new(26, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f(a: u8) { -a; }"),
+            Err(CompilerErr::InvalidOperandType(
+                ValueType::U8,
+                Position::new(21, 1)
+            ))
+        );
+        assert!(check_str("function f(a: address, b: address) { a == b; }").is_ok());
+    }
+
+    #[test]
+    fn test_check_casts() {
+        assert!(check_str("function f(a: u64) -> u8 { return a as u8; }").is_ok());
+        assert!(check_str("function f(a) -> address { return a as address; }").is_ok());
+        assert_eq!(
+            check_str("function f(a: address) -> u8 { return a as u8; }"),
+            Err(CompilerErr::InvalidCast(
+                ValueType::Address,
+                ValueType::U8,
+                Position::new(39, 1)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_check_arrays() {
+        assert!(
+            check_str("function f(a: [u8; 32], b: [u8; 32]) -> [u8; 64] { return a + b; }").is_ok()
+        );
+        assert!(check_str(
+            "function f(a: [u8], i: u8) -> u32 { let b = a.takeFront(i); return b[1..].len() + a[i]; }"
+        )
+        .is_ok());
+        assert!(check_str("function f(a: address) -> [u8; 32] { return a as [u8; 32]; }").is_ok());
+
+        assert_eq!(
+            check_str("function f(a: [u8], b: [u16]) { a + b; }"),
+            Err(CompilerErr::TypeMismatch(
+                ValueType::Array(Box::new(ValueType::U8)),
+                ValueType::Array(Box::new(ValueType::U16)),
+                Position::new(37, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f(a: [u8]) -> [u8; 64] { return a; }"),
+            Err(CompilerErr::TypeMismatch(
+                ValueType::FixedArray(Box::new(ValueType::U8), 64),
+                ValueType::Array(Box::new(ValueType::U8)),
+                Position::new(42, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f(a: [u8]) { a[1.5]; }"),
+            Err(CompilerErr::InvalidOperandType(
+                ValueType::Decimal,
+                Position::new(25, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f(a: [u8; 4]) { a.takeFront(1); }"),
+            Err(CompilerErr::InvalidOperandType(
+                ValueType::FixedArray(Box::new(ValueType::U8), 4),
+                Position::new(26, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f(a: u8) { a.len(); }"),
+            Err(CompilerErr::InvalidOperandType(
+                ValueType::U8,
+                Position::new(21, 1)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_check_intrinsics() {
+        let functions = check_str(
+            "function f(deadline: i32) -> bool { XPU.outputsLen(); return XPU.blockTimestamp > deadline; }",
+        )
+        .unwrap();
+        match &functions[0].body[0].kind {
+            StmtKind::Expr(expr) => {
+                assert!(matches!(&expr.kind, ExprKind::Intrinsic(intrinsic, args)
+                    if intrinsic.name == "outputsLen" && args.is_empty()));
+                assert_eq!(expr.ty, ValueType::U32);
+            }
+            _ => unreachable!(),
+        }
+
+        assert!(check_str(
+            "function f(a: PublicKey, s: [u8]) { XPU.verifyEd25519(this.inputBytes, s, a, s); }"
+        )
+        .is_ok());
+        assert_eq!(
+            check_str("function f() { let a = XPU.verifyEd25519(x\"\", x\"\", x\"\", x\"\"); }"),
+            Err(CompilerErr::TypeMismatch(
+                ValueType::PublicKey,
+                ValueType::FixedArray(Box::new(ValueType::U8), 0),
+                Position::new(52, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f() { XPU.blockHeight; }"),
+            Err(CompilerErr::UnknownMember(
+                "XPU".to_owned(),
+                "blockHeight".to_owned(),
+                Position::new(16, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f() { XPU.outputsLen; }"),
+            Err(CompilerErr::MethodNotCalled(
+                "XPU.outputsLen".to_owned(),
+                Position::new(16, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f() { this.inputBytes(); }"),
+            Err(CompilerErr::NotAMethod(
+                "this.inputBytes".to_owned(),
+                Position::new(16, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f() { XPU.pushOut(1, 2); }"),
+            Err(CompilerErr::ArgumentCountMismatch(
+                3,
+                2,
+                Position::new(16, 1)
+            ))
+        );
+
+        assert!(check_str("test function t() { Test.expectRevert(\"x\"); }").is_ok());
+        assert_eq!(
+            check_str("test function t() { Test.expectRevert(b\"x\"); }"),
+            Err(CompilerErr::ExpectedRevertReason(Position::new(39, 1)))
+        );
+
+        // Variables hide builtin objects
+        assert_eq!(
+            check_str("function f(XPU: u8) { XPU.outputsLen(); }"),
+            Err(CompilerErr::UnsupportedExpression(Position::new(23, 1)))
+        );
+    }
+
+    #[test]
+    fn test_check_output_fields() {
+        let functions = check_str(
+            "function f(i: u32) -> PublicKey { let o: Output = XPU.getOutputAt(i); let a: i128 = this.amount; return o.input.publicKey; }",
+        )
+        .unwrap();
+        let ret = match &functions[0].body[2].kind {
+            StmtKind::Return(Some(expr)) => expr,
+            _ => unreachable!(),
+        };
+        assert_eq!(ret.ty, ValueType::PublicKey);
+        match &ret.kind {
+            ExprKind::Intrinsic(intrinsic, args) => {
+                assert_eq!(intrinsic.to_string(), "Input.publicKey");
+                assert!(matches!(&args[0].kind, ExprKind::Intrinsic(intrinsic, args)
+                    if intrinsic.name == "input" && args[0].ty == ValueType::Output));
+            }
+            _ => unreachable!(),
+        }
+
+        assert!(check_str("function f(o: Output) -> bool { return o == this; }").is_ok());
+        assert!(
+            check_str("function f(k: [u8; 32]) -> PublicKey { return k as PublicKey; }").is_ok()
+        );
+        assert_eq!(
+            check_str("function f(o: Output) { o.publicKey; }"),
+            Err(CompilerErr::UnknownMember(
+                "Output".to_owned(),
+                "publicKey".to_owned(),
+                Position::new(25, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f(a: u8) { a.amount; }"),
+            Err(CompilerErr::UnknownMember(
+                "u8".to_owned(),
+                "amount".to_owned(),
+                Position::new(21, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f(o: Output) -> address { return o.colourHash; }"),
+            Err(CompilerErr::TypeMismatch(
+                ValueType::Address,
+                ValueType::Asset,
+                Position::new(43, 1)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_check_conditions_must_be_bool() {
+        assert!(check_str("function f(a: u8) { while (a > 0 && !false) {} }").is_ok());
+        assert!(check_str("function f(a) { if (a) {} }").is_ok());
+        assert!(check_str("function f(a: bool) -> u8 { return a as u8; }").is_ok());
+
+        assert_eq!(
+            check_str("function f(a: u8) { if (a) {} }"),
+            Err(CompilerErr::InvalidOperandType(
+                ValueType::U8,
+                Position::new(25, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f(a: u8, b: u8) { a && b; }"),
+            Err(CompilerErr::InvalidOperandType(
+                ValueType::U8,
+                Position::new(28, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f(a: u8) -> bool { return a as bool; }"),
+            Err(CompilerErr::InvalidCast(
+                ValueType::U8,
+                ValueType::Bool,
+                Position::new(36, 1)
+            ))
+        );
+        assert_eq!(
+            check_str("function f() -> u8 { return 1 == 1; }"),
+            Err(CompilerErr::TypeMismatch(
+                ValueType::U8,
+                ValueType::Bool,
+                Position::new(29, 1)
+            ))
+        );
+    }
+}
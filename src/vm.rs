@@ -0,0 +1,2028 @@
+//! Reference interpreter for compiled scripts.
+//!
+//! `run` executes the output of `Compiler::compile` against a mocked
+//! transaction context, so that scripts can be tested without a node. See
+//! `docs/bytecode.md` for the semantics of every instruction.
+//!
+//! Integer arithmetic is checked: a result which does not fit the type of
+//! its operands aborts the script, as do failed conversions, out of bounds
+//! accesses and invalid signatures. Aborts are part of the outcome of a
+//! script, while malformed bytecode is reported as a `VmErr`.
+//!
+//! Every instruction costs gas, as listed by `intrinsics::cost`, and every
+//! value an instruction creates or copies additionally costs gas for its
+//! size. Arrays and big integers are bounded. A script which runs out of the
+//! gas it was given aborts, so that every run ends.
+//!
+//! `run_test` executes a test function of a script compiled by
+//! `Compiler::compile_tests`. Tests start with an empty transaction, which
+//! they mock through the intrinsics of `Test` before calling main.
+
+use crate::ast::ValueType;
+use crate::bytecode::{Artifact, DecodeErr, OpCode, Reader};
+use crate::compiler::DECIMAL_SCALE;
+use crate::intrinsics;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use num_bigint::{BigInt, Sign};
+use num_traits::{FromPrimitive, One, ToPrimitive, Zero};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Maximum number of nested calls, including the call of main.
+const MAX_CALL_DEPTH: usize = 1024;
+
+/// Gas given to scripts and tests unless another limit is chosen.
+pub const DEFAULT_GAS_LIMIT: u64 = 1_000_000;
+
+/// Maximum number of elements of an array.
+pub const MAX_ARRAY_LEN: usize = 1 << 16;
+
+/// Maximum number of bits of the magnitude of a `ubig` or `ibig`.
+pub const MAX_BIG_BITS: u64 = 1 << 13;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Bool(bool),
+
+    /// Integer of the given integer type, which the value always fits
+    Int(ValueType, BigInt),
+    F32(f32),
+    F64(f64),
+
+    /// Decimal scaled by `10^18`
+    Decimal(i128),
+    Address(Vec<u8>),
+    Asset(Vec<u8>),
+    PublicKey([u8; 32]),
+    Hash([u8; 32]),
+    Output(Box<Output>),
+    Input(Input),
+
+    /// Array of any length. Arrays of fixed lengths are arrays too.
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// Returns an integer of the integer type `ty`, or `None` if the value
+    /// does not fit the type.
+    pub fn int(ty: ValueType, value: BigInt) -> Option<Self> {
+        int(ty, value).ok()
+    }
+
+    /// Returns an array of `u8` holding `bytes`.
+    pub fn bytes(bytes: &[u8]) -> Self {
+        Value::Array(
+            bytes
+                .iter()
+                .map(|byte| Value::Int(ValueType::U8, BigInt::from(*byte)))
+                .collect(),
+        )
+    }
+
+    /// Returns the type of the value. Arrays have the type of their first
+    /// element, or `[any]` if they are empty.
+    pub fn ty(&self) -> ValueType {
+        match self {
+            Value::Bool(_) => ValueType::Bool,
+            Value::Int(ty, _) => ty.clone(),
+            Value::F32(_) => ValueType::F32,
+            Value::F64(_) => ValueType::F64,
+            Value::Decimal(_) => ValueType::Decimal,
+            Value::Address(_) => ValueType::Address,
+            Value::Asset(_) => ValueType::Asset,
+            Value::PublicKey(_) => ValueType::PublicKey,
+            Value::Hash(_) => ValueType::Hash,
+            Value::Output(_) => ValueType::Output,
+            Value::Input(_) => ValueType::Input,
+            Value::Array(elements) => {
+                ValueType::Array(Box::new(elements.first().map_or(ValueType::Any, Value::ty)))
+            }
+        }
+    }
+}
+
+/// Formats values like literals. Arrays of `u8` are written as hex literals,
+/// and bytes of other types in hex.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Int(_, value) => write!(f, "{}", value),
+            Value::F32(value) => write!(f, "{}", value),
+            Value::F64(value) => write!(f, "{}", value),
+            Value::Decimal(value) => {
+                let scale = 10i128.pow(DECIMAL_SCALE);
+                let sign = if *value < 0 { "-" } else { "" };
+                let integer = (value / scale).unsigned_abs();
+                let fraction = format!("{:018}", (value % scale).unsigned_abs());
+                match fraction.trim_end_matches('0') {
+                    "" => write!(f, "{}{}", sign, integer),
+                    fraction => write!(f, "{}{}.{}", sign, integer, fraction),
+                }
+            }
+            Value::Address(bytes) | Value::Asset(bytes) => f.write_str(&hex::encode(bytes)),
+            Value::PublicKey(bytes) | Value::Hash(bytes) => f.write_str(&hex::encode(bytes)),
+            Value::Output(output) => write!(f, "Output({})", hex::encode(output.hash)),
+            Value::Input(input) => write!(f, "Input({})", hex::encode(input.output_hash)),
+            Value::Array(elements) if !elements.is_empty() => match bytes(self.clone()) {
+                Ok(bytes) => write!(f, "x\"{}\"", hex::encode(bytes)),
+                Err(_) => {
+                    let elements: Vec<_> = elements.iter().map(Value::to_string).collect();
+                    write!(f, "[{}]", elements.join(", "))
+                }
+            },
+            Value::Array(_) => f.write_str("[]"),
+        }
+    }
+}
+
+/// Output of a transaction.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Output {
+    pub amount: i128,
+    pub colour_hash: Vec<u8>,
+    pub coloured_address: Vec<u8>,
+    pub script_hash: Vec<u8>,
+
+    /// Hash identifying the output. Outputs with the same hash are the
+    /// same output.
+    pub hash: [u8; 32],
+
+    /// The input spent to create the output
+    pub input: Input,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Input {
+    pub public_key: [u8; 32],
+
+    /// Hash of the output spent by the input
+    pub output_hash: [u8; 32],
+}
+
+/// Output appended to the transaction by `XPU.pushOut`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PushedOutput {
+    pub amount: i128,
+    pub colour_hash: Vec<u8>,
+    pub coloured_address: Vec<u8>,
+    pub script_hash: Vec<u8>,
+}
+
+impl fmt::Display for PushedOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "amount {}, colour hash {}, address {}, script hash {}",
+            self.amount,
+            hex::encode(&self.colour_hash),
+            hex::encode(&self.coloured_address),
+            hex::encode(&self.script_hash)
+        )
+    }
+}
+
+/// Transaction spending the output whose script runs.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Context {
+    pub block_timestamp: i64,
+
+    /// Outputs of the transaction, along with the inputs spent to create them
+    pub outputs: Vec<Output>,
+
+    /// The output whose script is running
+    pub this: Output,
+
+    /// Bytes of the input spending `this`
+    pub input_bytes: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Outcome {
+    /// Main returned, with its return value if it had one
+    Success {
+        ret: Option<Value>,
+        outputs: Vec<PushedOutput>,
+    },
+
+    /// The script reverted
+    Revert(Revert),
+
+    /// The script failed a check
+    Abort(Abort),
+}
+
+/// Reason of a revert.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Revert {
+    /// Code of the reason, `0` if none was given
+    pub code: u16,
+
+    /// The reason, unless it was stripped from the script
+    pub reason: Option<String>,
+}
+
+impl Revert {
+    /// Returns a revert with the reason of `code` in `artifact`.
+    fn new(artifact: &Artifact, code: u16) -> Self {
+        Self {
+            code,
+            reason: artifact.reason(code).map(str::to_owned),
+        }
+    }
+}
+
+/// Formats the reason in quotes, or the code if the reason is not known.
+impl fmt::Display for Revert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "\"{}\"", reason),
+            None if self.code == 0 => f.write_str("no reason"),
+            None => write!(f, "reason {}", self.code),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Abort {
+    Overflow,
+    DivisionByZero,
+    IndexOutOfBounds,
+    InvalidCast,
+
+    /// The operands of an instruction have the wrong types, which only
+    /// happens with values of type `any`
+    TypeMismatch,
+    InvalidSignature,
+    CallDepthExceeded,
+    OutOfGas,
+
+    /// An array would have more than `MAX_ARRAY_LEN` elements
+    ArrayTooLong,
+}
+
+impl fmt::Display for Abort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Abort::Overflow => "arithmetic overflow",
+            Abort::DivisionByZero => "division by zero",
+            Abort::IndexOutOfBounds => "index out of bounds",
+            Abort::InvalidCast => "value does not fit the type it is cast to",
+            Abort::TypeMismatch => "operands have the wrong types",
+            Abort::InvalidSignature => "invalid signature",
+            Abort::CallDepthExceeded => "too many nested calls",
+            Abort::OutOfGas => "out of gas",
+            Abort::ArrayTooLong => "array too long",
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TestResult {
+    Passed,
+
+    /// The test failed for the given reason
+    Failed(String),
+}
+
+/// Errors of malformed bytecode. Offsets are relative to the start of the
+/// body of the function being executed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum VmErr {
+    Decode(DecodeErr),
+    InvalidOpCode(u8, usize),
+    InvalidConstant(usize),
+    StackUnderflow(usize),
+    UnknownFunction(u16, usize),
+    UninitializedSlot(u16, usize),
+}
+
+impl From<DecodeErr> for VmErr {
+    fn from(err: DecodeErr) -> Self {
+        VmErr::Decode(err)
+    }
+}
+
+/// Runs the main function of a compiled script with `args` as its arguments,
+/// aborting once it used more than `gas_limit` gas.
+pub fn run(
+    script: &[u8],
+    ctx: &Context,
+    args: Vec<Value>,
+    gas_limit: u64,
+) -> Result<Outcome, VmErr> {
+    let artifact = Artifact::decode(script)?;
+    let mut vm = Vm {
+        artifact: &artifact,
+        ctx: Cow::Borrowed(ctx),
+        stack: vec![],
+        frames: vec![Frame {
+            func: 0,
+            pc: 0,
+            slots: args.into_iter().map(Some).collect(),
+            base: 0,
+        }],
+        outputs: vec![],
+        test: None,
+        gas: gas_limit,
+    };
+
+    loop {
+        match vm.step() {
+            Ok(()) => {}
+            Err(Halt::Done(outcome)) => return Ok(outcome),
+            Err(Halt::Err(err)) => return Err(err),
+            Err(Halt::Test(_)) => unreachable!("only tests halt with a test result"),
+        }
+    }
+}
+
+/// Runs the test function at index `func` of a script compiled by
+/// `Compiler::compile_tests`.
+///
+/// A test passes if it returns, unless it expects main to revert. It fails
+/// if it reverts or aborts, or if main reverts other than expected. The test
+/// and the calls of main share `gas_limit`.
+pub fn run_test(script: &[u8], func: usize, gas_limit: u64) -> Result<TestResult, VmErr> {
+    let artifact = Artifact::decode(script)?;
+    if func == 0 || func > artifact.funcs.len() {
+        return Err(VmErr::UnknownFunction(func as u16, 0));
+    }
+
+    let mut vm = Vm {
+        artifact: &artifact,
+        ctx: Cow::Owned(Context::default()),
+        stack: vec![],
+        frames: vec![Frame {
+            func,
+            pc: 0,
+            slots: vec![],
+            base: 0,
+        }],
+        outputs: vec![],
+        test: Some(Test::default()),
+        gas: gas_limit,
+    };
+
+    loop {
+        let outcome = match vm.step() {
+            Ok(()) => continue,
+            Err(Halt::Test(result)) => return Ok(result),
+            Err(Halt::Err(err)) => return Err(err),
+            Err(Halt::Done(outcome)) => outcome,
+        };
+
+        let expected = vm.test.and_then(|test| test.expected_revert);
+        return Ok(match (outcome, expected) {
+            (Outcome::Success { .. }, None) => TestResult::Passed,
+            (Outcome::Success { .. }, Some(expected)) => TestResult::Failed(format!(
+                "main did not revert with {}",
+                Revert::new(&artifact, expected)
+            )),
+            (Outcome::Revert(revert), _) => TestResult::Failed(format!("reverted with {}", revert)),
+            (Outcome::Abort(abort), _) => TestResult::Failed(format!("aborted: {}", abort)),
+        });
+    }
+}
+
+struct Frame {
+    /// Index of the function, 0 for main
+    func: usize,
+
+    /// Offset of the next instruction
+    pc: usize,
+    slots: Vec<Option<Value>>,
+
+    /// Height of the stack when the function was called
+    base: usize,
+}
+
+/// Reasons to stop executing.
+enum Halt {
+    Done(Outcome),
+    Err(VmErr),
+
+    /// Main reverted while running a test
+    Test(TestResult),
+}
+
+impl From<Abort> for Halt {
+    fn from(abort: Abort) -> Self {
+        Halt::Done(Outcome::Abort(abort))
+    }
+}
+
+impl From<VmErr> for Halt {
+    fn from(err: VmErr) -> Self {
+        Halt::Err(err)
+    }
+}
+
+impl From<DecodeErr> for Halt {
+    fn from(err: DecodeErr) -> Self {
+        Halt::Err(VmErr::Decode(err))
+    }
+}
+
+struct Vm<'a> {
+    artifact: &'a Artifact,
+
+    /// Transaction, which only tests modify
+    ctx: Cow<'a, Context>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+
+    /// Outputs appended by `XPU.pushOut`
+    outputs: Vec<PushedOutput>,
+
+    /// State of the test being run, `None` if no test is running
+    test: Option<Test>,
+
+    /// Gas left
+    gas: u64,
+}
+
+#[derive(Default)]
+struct Test {
+    /// Code of the reason passed to `Test.expectRevert`
+    expected_revert: Option<u16>,
+
+    /// Number of outputs created by `Test.output`
+    mocked: usize,
+}
+
+impl Vm<'_> {
+    /// Executes the next instruction of the current function.
+    fn step(&mut self) -> Result<(), Halt> {
+        let artifact = self.artifact;
+        let frame = self.frames.last().unwrap();
+        let code = match frame.func {
+            0 => &artifact.main,
+            func => &artifact.funcs[func - 1],
+        };
+
+        let at = frame.pc;
+        let mut reader = Reader::new(code);
+        reader.offset = at;
+        let byte = reader.take_u8()?;
+        let op = OpCode::try_from(byte).map_err(|byte| VmErr::InvalidOpCode(byte, at))?;
+
+        // Instructions are paid for before they run
+        let cost = intrinsics::cost(op) as u64;
+        self.gas = self.gas.checked_sub(cost).ok_or(Abort::OutOfGas)?;
+
+        match op {
+            OpCode::PushConst => {
+                let ty = reader.take_type()?;
+                // Elements of zero bytes, such as `[u8; 0]`, are only
+                // bounded by the gas paying for them
+                let words = const_words(&ty).ok_or(Abort::ArrayTooLong)?;
+                if words > self.gas {
+                    return Err(Abort::OutOfGas.into());
+                }
+                let value = take_const(&mut reader, &ty)?.ok_or(VmErr::InvalidConstant(at))?;
+                self.push_new(value)?;
+            }
+
+            OpCode::Load => {
+                let slot = reader.take_u16()?;
+                let value = self
+                    .frame()
+                    .slots
+                    .get(slot as usize)
+                    .cloned()
+                    .flatten()
+                    .ok_or(VmErr::UninitializedSlot(slot, at))?;
+                self.push_new(value)?;
+            }
+
+            OpCode::Store => {
+                let slot = reader.take_u16()? as usize;
+                let value = self.pop(at)?;
+                let slots = &mut self.frame_mut().slots;
+                if slots.len() <= slot {
+                    slots.resize(slot + 1, None);
+                }
+                slots[slot] = Some(value);
+            }
+
+            OpCode::Pop => {
+                self.pop(at)?;
+            }
+
+            OpCode::Dup => {
+                let value = self.stack.last().ok_or(VmErr::StackUnderflow(at))?.clone();
+                self.push_new(value)?;
+            }
+
+            OpCode::Return => return self.ret(None),
+
+            OpCode::ReturnValue => {
+                let value = self.pop(at)?;
+                return self.ret(Some(value));
+            }
+
+            OpCode::Add
+            | OpCode::Sub
+            | OpCode::Mul
+            | OpCode::Div
+            | OpCode::Rem
+            | OpCode::Shl
+            | OpCode::Shr
+            | OpCode::BitAnd
+            | OpCode::BitXor
+            | OpCode::BitOr => {
+                let rhs = self.pop(at)?;
+                let lhs = self.pop(at)?;
+                self.push_new(arithmetic(op, lhs, rhs)?)?;
+            }
+
+            OpCode::Lt | OpCode::Le | OpCode::Gt | OpCode::Ge => {
+                let rhs = self.pop(at)?;
+                let lhs = self.pop(at)?;
+                let holds = match (compare(&lhs, &rhs)?, op) {
+                    (None, _) => false,
+                    (Some(ordering), OpCode::Lt) => ordering.is_lt(),
+                    (Some(ordering), OpCode::Le) => ordering.is_le(),
+                    (Some(ordering), OpCode::Gt) => ordering.is_gt(),
+                    (Some(ordering), _) => ordering.is_ge(),
+                };
+                self.stack.push(Value::Bool(holds));
+            }
+
+            OpCode::Eq | OpCode::Ne => {
+                let rhs = self.pop(at)?;
+                let lhs = self.pop(at)?;
+                let equal = equals(&lhs, &rhs)?;
+                self.stack.push(Value::Bool(equal == (op == OpCode::Eq)));
+            }
+
+            OpCode::Neg => {
+                let value = match self.pop(at)? {
+                    Value::Int(ty, value) => int(ty, -value)?,
+                    Value::F32(value) => Value::F32(-value),
+                    Value::F64(value) => Value::F64(-value),
+                    Value::Decimal(value) => {
+                        Value::Decimal(value.checked_neg().ok_or(Abort::Overflow)?)
+                    }
+                    _ => return Err(Abort::TypeMismatch.into()),
+                };
+                self.stack.push(value);
+            }
+
+            OpCode::Not => {
+                let value = self.pop_bool(at)?;
+                self.stack.push(Value::Bool(!value));
+            }
+
+            OpCode::Cast => {
+                let ty = reader.take_type()?;
+                let value = self.pop(at)?;
+                self.push_new(cast(value, &ty)?)?;
+            }
+
+            OpCode::Jump => {
+                let target = reader.take_u32()?;
+                self.frame_mut().pc = target as usize;
+                return Ok(());
+            }
+
+            OpCode::JumpIfFalse | OpCode::JumpIfTrue => {
+                let target = reader.take_u32()?;
+                if self.pop_bool(at)? == (op == OpCode::JumpIfTrue) {
+                    self.frame_mut().pc = target as usize;
+                    return Ok(());
+                }
+            }
+
+            OpCode::Revert => {
+                let revert = Revert::new(artifact, reader.take_u16()?);
+                if self.test.is_some() && self.frames.iter().any(|frame| frame.func == 0) {
+                    return Err(Halt::Test(self.main_reverted(revert)));
+                }
+                return Err(Halt::Done(Outcome::Revert(revert)));
+            }
+
+            OpCode::Call => {
+                let func = reader.take_u16()?;
+                let arity = reader.take_u8()? as usize;
+                // Only tests call main
+                if (func == 0 && self.test.is_none()) || func as usize > artifact.funcs.len() {
+                    return Err(VmErr::UnknownFunction(func, at).into());
+                }
+                if self.frames.len() == MAX_CALL_DEPTH {
+                    return Err(Abort::CallDepthExceeded.into());
+                }
+                if self.stack.len() < self.frame().base + arity {
+                    return Err(VmErr::StackUnderflow(at).into());
+                }
+
+                let args = self.stack.split_off(self.stack.len() - arity);
+                self.frame_mut().pc = reader.offset;
+                self.frames.push(Frame {
+                    func: func as usize,
+                    pc: 0,
+                    slots: args.into_iter().map(Some).collect(),
+                    base: self.stack.len(),
+                });
+                return Ok(());
+            }
+
+            OpCode::Index => {
+                let index = self.pop(at)?;
+                let elements = self.pop_array(at)?;
+                let index = array_index(&index)?;
+                let element = elements
+                    .into_iter()
+                    .nth(index)
+                    .ok_or(Abort::IndexOutOfBounds)?;
+                self.stack.push(element);
+            }
+
+            OpCode::Slice => {
+                let bounds = reader.take_u8()?;
+                let end = match bounds & 0b10 {
+                    0 => None,
+                    _ => Some(array_index(&self.pop(at)?)?),
+                };
+                let start = match bounds & 0b01 {
+                    0 => 0,
+                    _ => array_index(&self.pop(at)?)?,
+                };
+                let mut elements = self.pop_array(at)?;
+                let end = end.unwrap_or(elements.len());
+                if start > end || end > elements.len() {
+                    return Err(Abort::IndexOutOfBounds.into());
+                }
+                elements.truncate(end);
+                self.stack.push(Value::Array(elements.split_off(start)));
+            }
+
+            OpCode::Len => {
+                let elements = self.pop_array(at)?;
+                self.stack.push(int(ValueType::U32, elements.len().into())?);
+            }
+
+            OpCode::TakeFront | OpCode::TakeBack => {
+                let count = array_index(&self.pop(at)?)?;
+                let mut elements = self.pop_array(at)?;
+                if count > elements.len() {
+                    return Err(Abort::IndexOutOfBounds.into());
+                }
+                let (taken, rest) = if op == OpCode::TakeFront {
+                    let rest = elements.split_off(count);
+                    (elements, rest)
+                } else {
+                    let taken = elements.split_off(elements.len() - count);
+                    (taken, elements)
+                };
+                self.stack.push(Value::Array(taken));
+                self.stack.push(Value::Array(rest));
+            }
+
+            OpCode::BlockTimestamp => {
+                let timestamp = self.ctx.block_timestamp;
+                self.stack
+                    .push(Value::Int(ValueType::I64, timestamp.into()));
+            }
+
+            OpCode::OutputsLen => {
+                let len = self.ctx.outputs.len();
+                self.stack.push(int(ValueType::U32, len.into())?);
+            }
+
+            OpCode::InputBytes => self.push_new(Value::bytes(&self.ctx.input_bytes))?,
+
+            OpCode::VerifyEd25519 => {
+                let signature = bytes(self.pop(at)?)?;
+                let key = match self.pop(at)? {
+                    Value::PublicKey(key) => key,
+                    _ => return Err(Abort::TypeMismatch.into()),
+                };
+                let message = bytes(self.pop(at)?)?;
+                let mut signed = bytes(self.pop(at)?)?;
+                signed.extend_from_slice(&message);
+
+                let key = VerifyingKey::from_bytes(&key).map_err(|_| Abort::InvalidSignature)?;
+                let signature =
+                    Signature::from_slice(&signature).map_err(|_| Abort::InvalidSignature)?;
+                key.verify(&signed, &signature)
+                    .map_err(|_| Abort::InvalidSignature)?;
+            }
+
+            OpCode::PushOut => {
+                let script_hash = self.pop_address(at)?;
+                let coloured_address = self.pop_address(at)?;
+                let amount = match self.pop(at)? {
+                    Value::Int(ValueType::I128, amount) => amount.to_i128().unwrap(),
+                    _ => return Err(Abort::TypeMismatch.into()),
+                };
+                let output = PushedOutput {
+                    amount,
+                    colour_hash: self.ctx.this.colour_hash.clone(),
+                    coloured_address,
+                    script_hash,
+                };
+                self.push_output(output)?;
+            }
+
+            OpCode::PushOutput => {
+                let output = self.pop_output(at)?;
+                self.push_output(PushedOutput {
+                    amount: output.amount,
+                    colour_hash: output.colour_hash,
+                    coloured_address: output.coloured_address,
+                    script_hash: output.script_hash,
+                })?;
+            }
+
+            OpCode::GetOutputAt => {
+                let index = array_index(&self.pop(at)?)?;
+                let output = self.ctx.outputs.get(index).ok_or(Abort::IndexOutOfBounds)?;
+                self.stack.push(Value::Output(Box::new(output.clone())));
+            }
+
+            OpCode::This => {
+                let this = self.ctx.this.clone();
+                self.stack.push(Value::Output(Box::new(this)));
+            }
+
+            OpCode::OutputAmount
+            | OpCode::OutputColourHash
+            | OpCode::OutputColouredAddress
+            | OpCode::OutputScriptHash
+            | OpCode::OutputHash
+            | OpCode::OutputInput => {
+                let output = self.pop_output(at)?;
+                let value = match op {
+                    OpCode::OutputAmount => Value::Int(ValueType::I128, output.amount.into()),
+                    OpCode::OutputColourHash => Value::Asset(output.colour_hash),
+                    OpCode::OutputColouredAddress => Value::Address(output.coloured_address),
+                    OpCode::OutputScriptHash => Value::Address(output.script_hash),
+                    OpCode::OutputHash => Value::Hash(output.hash),
+                    _ => Value::Input(output.input),
+                };
+                self.stack.push(value);
+            }
+
+            OpCode::InputPublicKey | OpCode::InputOutputHash => {
+                let input = match self.pop(at)? {
+                    Value::Input(input) => input,
+                    _ => return Err(Abort::TypeMismatch.into()),
+                };
+                self.stack.push(match op {
+                    OpCode::InputPublicKey => Value::PublicKey(input.public_key),
+                    _ => Value::Hash(input.output_hash),
+                });
+            }
+
+            OpCode::SetBlockTimestamp
+            | OpCode::SetInputBytes
+            | OpCode::SetThis
+            | OpCode::AddOutput
+            | OpCode::MockOutput
+            | OpCode::MockOutputWithKey
+            | OpCode::DerivePublicKey
+            | OpCode::Sign
+            | OpCode::PushedLen
+            | OpCode::PushedAt => {
+                if self.test.is_none() {
+                    return Err(VmErr::InvalidOpCode(byte, at).into());
+                }
+                self.mock(op, at)?;
+            }
+
+            OpCode::ExpectRevert => {
+                let code = reader.take_u16()?;
+                match self.test.as_mut() {
+                    Some(test) => test.expected_revert = Some(code),
+                    None => return Err(VmErr::InvalidOpCode(byte, at).into()),
+                }
+            }
+        }
+
+        self.frame_mut().pc = reader.offset;
+        Ok(())
+    }
+
+    /// Executes an intrinsic of `Test`.
+    fn mock(&mut self, op: OpCode, at: usize) -> Result<(), Halt> {
+        match op {
+            OpCode::SetBlockTimestamp => {
+                let timestamp = match self.pop(at)? {
+                    Value::Int(ValueType::I64, timestamp) => timestamp.to_i64().unwrap(),
+                    _ => return Err(Abort::TypeMismatch.into()),
+                };
+                self.ctx.to_mut().block_timestamp = timestamp;
+            }
+
+            OpCode::SetInputBytes => {
+                let input_bytes = bytes(self.pop(at)?)?;
+                self.ctx.to_mut().input_bytes = input_bytes;
+            }
+
+            OpCode::SetThis => {
+                let output = self.pop_output(at)?;
+                self.ctx.to_mut().this = output;
+            }
+
+            OpCode::AddOutput => {
+                let output = self.pop_output(at)?;
+                self.ctx.to_mut().outputs.push(output);
+            }
+
+            OpCode::MockOutput | OpCode::MockOutputWithKey => {
+                let public_key = match op {
+                    OpCode::MockOutputWithKey => match self.pop(at)? {
+                        Value::PublicKey(key) => key,
+                        _ => return Err(Abort::TypeMismatch.into()),
+                    },
+                    _ => [0; 32],
+                };
+                let script_hash = self.pop_address(at)?;
+                let coloured_address = self.pop_address(at)?;
+                let colour_hash = match self.pop(at)? {
+                    Value::Asset(asset) => asset,
+                    _ => return Err(Abort::TypeMismatch.into()),
+                };
+                let amount = match self.pop(at)? {
+                    Value::Int(ValueType::I128, amount) => amount.to_i128().unwrap(),
+                    _ => return Err(Abort::TypeMismatch.into()),
+                };
+
+                // The n-th mocked output has the hash n
+                let test = self.test.as_mut().unwrap();
+                test.mocked += 1;
+                let mut hash = [0; 32];
+                hash[..8].copy_from_slice(&(test.mocked as u64).to_le_bytes());
+
+                let output = Output {
+                    amount,
+                    colour_hash,
+                    coloured_address,
+                    script_hash,
+                    hash,
+                    input: Input {
+                        public_key,
+                        output_hash: [0; 32],
+                    },
+                };
+                self.stack.push(Value::Output(Box::new(output)));
+            }
+
+            OpCode::DerivePublicKey => {
+                let key = self.pop_secret_key(at)?;
+                self.stack
+                    .push(Value::PublicKey(key.verifying_key().to_bytes()));
+            }
+
+            OpCode::Sign => {
+                let message = bytes(self.pop(at)?)?;
+                let key = self.pop_secret_key(at)?;
+                self.stack
+                    .push(Value::bytes(&key.sign(&message).to_bytes()));
+            }
+
+            OpCode::PushedLen => {
+                let len = self.outputs.len();
+                self.stack.push(int(ValueType::U32, len.into())?);
+            }
+
+            OpCode::PushedAt => {
+                let index = array_index(&self.pop(at)?)?;
+                let pushed = self.outputs.get(index).ok_or(Abort::IndexOutOfBounds)?;
+                let output = Output {
+                    amount: pushed.amount,
+                    colour_hash: pushed.colour_hash.clone(),
+                    coloured_address: pushed.coloured_address.clone(),
+                    script_hash: pushed.script_hash.clone(),
+                    ..Output::default()
+                };
+                self.stack.push(Value::Output(Box::new(output)));
+            }
+
+            _ => unreachable!("{:?} is not an intrinsic of `Test`", op),
+        }
+
+        Ok(())
+    }
+
+    /// Returns the result of a test whose call of main reverted.
+    fn main_reverted(&self, revert: Revert) -> TestResult {
+        let expected = self
+            .test
+            .as_ref()
+            .and_then(|test| test.expected_revert.as_ref());
+        match expected {
+            Some(expected) if revert.code == *expected => TestResult::Passed,
+            Some(expected) => TestResult::Failed(format!(
+                "main reverted with {} instead of {}",
+                revert,
+                Revert::new(self.artifact, *expected)
+            )),
+            None => TestResult::Failed(format!("main reverted with {}", revert)),
+        }
+    }
+
+    /// Returns from the current function. Returning from main ends the
+    /// script, unless a test called it.
+    fn ret(&mut self, value: Option<Value>) -> Result<(), Halt> {
+        let frame = self.frames.pop().unwrap();
+        if self.frames.is_empty() {
+            return Err(Halt::Done(Outcome::Success {
+                ret: value,
+                outputs: std::mem::take(&mut self.outputs),
+            }));
+        }
+
+        // Tests discard the value returned by main
+        self.stack.truncate(frame.base);
+        if frame.func != 0 {
+            self.stack.extend(value);
+        }
+        Ok(())
+    }
+
+    /// Appends an output and pushes its index.
+    /// Pushes a value created or copied by the current instruction, which
+    /// costs a gas per word of the value beyond the first. Values moved
+    /// between the stack and slots were paid for when they were pushed.
+    fn push_new(&mut self, value: Value) -> Result<(), Abort> {
+        let cost = words(&value) - 1;
+        self.gas = self.gas.checked_sub(cost).ok_or(Abort::OutOfGas)?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn push_output(&mut self, output: PushedOutput) -> Result<(), Halt> {
+        self.outputs.push(output);
+        let index = int(ValueType::U32, (self.outputs.len() - 1).into())?;
+        self.stack.push(index);
+        Ok(())
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().unwrap()
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    /// Pops a value pushed by the current function.
+    fn pop(&mut self, at: usize) -> Result<Value, VmErr> {
+        if self.stack.len() == self.frame().base {
+            return Err(VmErr::StackUnderflow(at));
+        }
+        Ok(self.stack.pop().unwrap())
+    }
+
+    fn pop_bool(&mut self, at: usize) -> Result<bool, Halt> {
+        match self.pop(at)? {
+            Value::Bool(value) => Ok(value),
+            _ => Err(Abort::TypeMismatch.into()),
+        }
+    }
+
+    fn pop_array(&mut self, at: usize) -> Result<Vec<Value>, Halt> {
+        match self.pop(at)? {
+            Value::Array(elements) => Ok(elements),
+            _ => Err(Abort::TypeMismatch.into()),
+        }
+    }
+
+    fn pop_address(&mut self, at: usize) -> Result<Vec<u8>, Halt> {
+        match self.pop(at)? {
+            Value::Address(address) => Ok(address),
+            _ => Err(Abort::TypeMismatch.into()),
+        }
+    }
+
+    fn pop_output(&mut self, at: usize) -> Result<Output, Halt> {
+        match self.pop(at)? {
+            Value::Output(output) => Ok(*output),
+            _ => Err(Abort::TypeMismatch.into()),
+        }
+    }
+
+    /// Pops an Ed25519 secret key of 32 bytes.
+    fn pop_secret_key(&mut self, at: usize) -> Result<SigningKey, Halt> {
+        let secret = bytes(self.pop(at)?)?;
+        let secret = secret.try_into().map_err(|_| Abort::InvalidCast)?;
+        Ok(SigningKey::from_bytes(&secret))
+    }
+}
+
+/// Takes a constant of type `ty`. Returns `None` if constants cannot have
+/// the type or the value is invalid.
+fn take_const(reader: &mut Reader, ty: &ValueType) -> Result<Option<Value>, DecodeErr> {
+    let value = match ty {
+        ValueType::Bool => match reader.take_u8()? {
+            0x00 => Value::Bool(false),
+            0x01 => Value::Bool(true),
+            _ => return Ok(None),
+        },
+        // Big integers past `MAX_BIG_BITS` are invalid
+        ValueType::UBIG => {
+            let len = reader.take_u16()? as usize;
+            let value = BigInt::from_bytes_le(Sign::Plus, reader.take(len)?);
+            return Ok(int(ty.clone(), value).ok());
+        }
+        ValueType::IBIG => {
+            let len = reader.take_u16()? as usize;
+            let value = BigInt::from_signed_bytes_le(reader.take(len)?);
+            return Ok(int(ty.clone(), value).ok());
+        }
+        ValueType::F32 => Value::F32(f32::from_le_bytes(reader.take(4)?.try_into().unwrap())),
+        ValueType::F64 => Value::F64(f64::from_le_bytes(reader.take(8)?.try_into().unwrap())),
+        ValueType::Decimal => {
+            Value::Decimal(i128::from_le_bytes(reader.take(16)?.try_into().unwrap()))
+        }
+        ValueType::FixedArray(element, len) => {
+            let len = *len as usize;
+            if len.saturating_mul(const_len(element)) > reader.remaining() {
+                return Ok(None);
+            }
+            let mut elements = Vec::with_capacity(len);
+            for _ in 0..len {
+                match take_const(reader, element)? {
+                    Some(value) => elements.push(value),
+                    None => return Ok(None),
+                }
+            }
+            Value::Array(elements)
+        }
+        _ => match int_bits(ty) {
+            Some((bits, signed)) => {
+                let bytes = reader.take(bits as usize / 8)?;
+                let value = if signed {
+                    BigInt::from_signed_bytes_le(bytes)
+                } else {
+                    BigInt::from_bytes_le(Sign::Plus, bytes)
+                };
+                Value::Int(ty.clone(), value)
+            }
+            None => return Ok(None),
+        },
+    };
+
+    Ok(Some(value))
+}
+
+/// Returns the least number of bytes encoding a constant of type `ty`.
+fn const_len(ty: &ValueType) -> usize {
+    match ty {
+        ValueType::Bool => 1,
+        ValueType::UBIG | ValueType::IBIG => 2,
+        ValueType::F32 => 4,
+        ValueType::F64 => 8,
+        ValueType::Decimal => 16,
+        ValueType::FixedArray(element, len) => (*len as usize).saturating_mul(const_len(element)),
+        _ => int_bits(ty).map_or(0, |(bits, _)| bits as usize / 8),
+    }
+}
+
+/// Returns the number of words of a constant of type `ty`, counting big
+/// integers as one word, or `None` if it has an array longer than
+/// `MAX_ARRAY_LEN`.
+fn const_words(ty: &ValueType) -> Option<u64> {
+    match ty {
+        ValueType::FixedArray(element, len) => {
+            if *len as usize > MAX_ARRAY_LEN {
+                return None;
+            }
+            Some((*len as u64).saturating_mul(const_words(element)?) + 1)
+        }
+        _ => Some(1),
+    }
+}
+
+/// Returns the size of a value in words, which its gas cost is based on.
+/// Arrays take a word and a word per element, and big integers a word per
+/// 64 bits.
+fn words(value: &Value) -> u64 {
+    match value {
+        Value::Array(elements) => 1 + elements.iter().map(words).sum::<u64>(),
+        Value::Int(ValueType::UBIG | ValueType::IBIG, value) => 1 + value.bits() / 64,
+        _ => 1,
+    }
+}
+
+/// Returns the number of bits of fixed size integer types and whether they
+/// are signed.
+fn int_bits(ty: &ValueType) -> Option<(u32, bool)> {
+    let bits = match ty {
+        ValueType::U8 => (8, false),
+        ValueType::U16 => (16, false),
+        ValueType::U32 => (32, false),
+        ValueType::U64 => (64, false),
+        ValueType::U128 => (128, false),
+        ValueType::I8 => (8, true),
+        ValueType::I16 => (16, true),
+        ValueType::I32 => (32, true),
+        ValueType::I64 => (64, true),
+        ValueType::I128 => (128, true),
+        _ => return None,
+    };
+    Some(bits)
+}
+
+fn is_int(ty: &ValueType) -> bool {
+    int_bits(ty).is_some() || matches!(ty, ValueType::UBIG | ValueType::IBIG)
+}
+
+/// Returns true if `value` can be represented by the integer type `ty`.
+fn fits(ty: &ValueType, value: &BigInt) -> bool {
+    match (ty, int_bits(ty)) {
+        (ValueType::UBIG, _) => value.sign() != Sign::Minus && value.bits() <= MAX_BIG_BITS,
+        (ValueType::IBIG, _) => value.bits() <= MAX_BIG_BITS,
+        (_, Some((bits, false))) => value.sign() != Sign::Minus && value.bits() <= bits as u64,
+        (_, Some((bits, true))) => {
+            let half = BigInt::one() << (bits - 1);
+            -&half <= *value && *value < half
+        }
+        _ => false,
+    }
+}
+
+/// Returns an integer of type `ty`, or aborts if the value does not fit.
+fn int(ty: ValueType, value: BigInt) -> Result<Value, Abort> {
+    if fits(&ty, &value) {
+        Ok(Value::Int(ty, value))
+    } else {
+        Err(Abort::Overflow)
+    }
+}
+
+fn decimal_scale() -> BigInt {
+    BigInt::from(10).pow(DECIMAL_SCALE)
+}
+
+/// Converts an index, length or count to `usize`.
+fn array_index(value: &Value) -> Result<usize, Abort> {
+    match value {
+        Value::Int(_, value) => value.to_usize().ok_or(Abort::IndexOutOfBounds),
+        _ => Err(Abort::TypeMismatch),
+    }
+}
+
+/// Returns the bytes of an array of `u8`.
+fn bytes(value: Value) -> Result<Vec<u8>, Abort> {
+    match value {
+        Value::Array(elements) => elements
+            .iter()
+            .map(|element| match element {
+                Value::Int(ValueType::U8, byte) => Ok(byte.to_u8().unwrap()),
+                _ => Err(Abort::TypeMismatch),
+            })
+            .collect(),
+        _ => Err(Abort::TypeMismatch),
+    }
+}
+
+/// Evaluates an arithmetic or bitwise operator.
+fn arithmetic(op: OpCode, lhs: Value, rhs: Value) -> Result<Value, Abort> {
+    match (lhs, rhs) {
+        (Value::Int(ty, lhs), Value::Int(_, rhs)) if matches!(op, OpCode::Shl | OpCode::Shr) => {
+            shift(op, ty, lhs, rhs)
+        }
+        (Value::Int(ty, lhs), Value::Int(rhs_ty, rhs)) if ty == rhs_ty => {
+            let value = match op {
+                OpCode::Add => lhs + rhs,
+                OpCode::Sub => lhs - rhs,
+                OpCode::Mul => lhs * rhs,
+                OpCode::Div | OpCode::Rem if rhs.is_zero() => return Err(Abort::DivisionByZero),
+                OpCode::Div => lhs / rhs,
+                OpCode::Rem => lhs % rhs,
+                OpCode::BitAnd => lhs & rhs,
+                OpCode::BitXor => lhs ^ rhs,
+                _ => lhs | rhs,
+            };
+            int(ty, value)
+        }
+        (Value::F32(lhs), Value::F32(rhs)) => float(op, lhs, rhs).map(Value::F32),
+        (Value::F64(lhs), Value::F64(rhs)) => float(op, lhs, rhs).map(Value::F64),
+        (Value::Decimal(lhs), Value::Decimal(rhs)) => decimal(op, lhs, rhs).map(Value::Decimal),
+        (Value::Array(mut lhs), Value::Array(rhs)) if op == OpCode::Add => {
+            if lhs.len() + rhs.len() > MAX_ARRAY_LEN {
+                return Err(Abort::ArrayTooLong);
+            }
+            lhs.extend(rhs);
+            Ok(Value::Array(lhs))
+        }
+        _ => Err(Abort::TypeMismatch),
+    }
+}
+
+/// Shifts an integer of type `ty`. Fixed size integers keep their lowest
+/// bits, and cannot be shifted by their size or more. Big integers cannot
+/// be shifted left past `MAX_BIG_BITS`, which is checked before shifting.
+fn shift(op: OpCode, ty: ValueType, value: BigInt, amount: BigInt) -> Result<Value, Abort> {
+    let amount = amount.to_u32().ok_or(Abort::Overflow)?;
+    let bits = int_bits(&ty);
+    let overflows = match bits {
+        Some((bits, _)) => amount >= bits,
+        None => op == OpCode::Shl && value.bits() + amount as u64 > MAX_BIG_BITS,
+    };
+    if overflows {
+        return Err(Abort::Overflow);
+    }
+
+    if op == OpCode::Shr {
+        return int(ty, value >> amount);
+    }
+
+    let mut value = value << amount;
+    if let Some((bits, signed)) = bits {
+        let modulus = BigInt::one() << bits;
+        value &= &modulus - 1;
+        if signed && value.bits() == bits as u64 {
+            value -= modulus;
+        }
+    }
+    int(ty, value)
+}
+
+fn float<T>(op: OpCode, lhs: T, rhs: T) -> Result<T, Abort>
+where
+    T: std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<Output = T>
+        + std::ops::Div<Output = T>
+        + std::ops::Rem<Output = T>,
+{
+    match op {
+        OpCode::Add => Ok(lhs + rhs),
+        OpCode::Sub => Ok(lhs - rhs),
+        OpCode::Mul => Ok(lhs * rhs),
+        OpCode::Div => Ok(lhs / rhs),
+        OpCode::Rem => Ok(lhs % rhs),
+        _ => Err(Abort::TypeMismatch),
+    }
+}
+
+/// Evaluates an operator on decimals, which are scaled by `10^18`. Products
+/// and quotients are rounded towards zero.
+fn decimal(op: OpCode, lhs: i128, rhs: i128) -> Result<i128, Abort> {
+    let value = match op {
+        OpCode::Add => lhs.checked_add(rhs),
+        OpCode::Sub => lhs.checked_sub(rhs),
+        OpCode::Div | OpCode::Rem if rhs == 0 => return Err(Abort::DivisionByZero),
+        OpCode::Mul => (BigInt::from(lhs) * rhs / decimal_scale()).to_i128(),
+        OpCode::Div => (BigInt::from(lhs) * decimal_scale() / rhs).to_i128(),
+        OpCode::Rem => lhs.checked_rem(rhs),
+        _ => return Err(Abort::TypeMismatch),
+    };
+    value.ok_or(Abort::Overflow)
+}
+
+/// Compares numbers of the same type. Returns `None` if floats are not
+/// ordered.
+fn compare(lhs: &Value, rhs: &Value) -> Result<Option<Ordering>, Abort> {
+    match (lhs, rhs) {
+        (Value::Int(ty, lhs), Value::Int(rhs_ty, rhs)) if ty == rhs_ty => Ok(Some(lhs.cmp(rhs))),
+        (Value::F32(lhs), Value::F32(rhs)) => Ok(lhs.partial_cmp(rhs)),
+        (Value::F64(lhs), Value::F64(rhs)) => Ok(lhs.partial_cmp(rhs)),
+        (Value::Decimal(lhs), Value::Decimal(rhs)) => Ok(Some(lhs.cmp(rhs))),
+        _ => Err(Abort::TypeMismatch),
+    }
+}
+
+/// Compares values of the same type for equality. Outputs are equal if they
+/// have the same hash.
+fn equals(lhs: &Value, rhs: &Value) -> Result<bool, Abort> {
+    let equal = match (lhs, rhs) {
+        (Value::Int(_, _) | Value::F32(_) | Value::F64(_) | Value::Decimal(_), _) => {
+            compare(lhs, rhs)? == Some(Ordering::Equal)
+        }
+        (Value::Bool(lhs), Value::Bool(rhs)) => lhs == rhs,
+        (Value::Address(lhs), Value::Address(rhs)) => lhs == rhs,
+        (Value::Asset(lhs), Value::Asset(rhs)) => lhs == rhs,
+        (Value::PublicKey(lhs), Value::PublicKey(rhs)) => lhs == rhs,
+        (Value::Hash(lhs), Value::Hash(rhs)) => lhs == rhs,
+        (Value::Output(lhs), Value::Output(rhs)) => lhs.hash == rhs.hash,
+        (Value::Input(lhs), Value::Input(rhs)) => lhs == rhs,
+        (Value::Array(lhs), Value::Array(rhs)) => {
+            if lhs.len() != rhs.len() {
+                return Ok(false);
+            }
+            for (lhs, rhs) in lhs.iter().zip(rhs.iter()) {
+                if !equals(lhs, rhs)? {
+                    return Ok(false);
+                }
+            }
+            true
+        }
+        _ => return Err(Abort::TypeMismatch),
+    };
+    Ok(equal)
+}
+
+/// Converts a value to `ty`, or aborts if the value does not fit.
+fn cast(value: Value, ty: &ValueType) -> Result<Value, Abort> {
+    let value = match (value, ty) {
+        (value, ValueType::Any) => value,
+        (Value::Int(_, value), _) if is_int(ty) => {
+            int(ty.clone(), value).map_err(|_| Abort::InvalidCast)?
+        }
+        (Value::Int(_, value), ValueType::F32) => {
+            Value::F32(value.to_f32().ok_or(Abort::InvalidCast)?)
+        }
+        (Value::Int(_, value), ValueType::F64) => {
+            Value::F64(value.to_f64().ok_or(Abort::InvalidCast)?)
+        }
+        (Value::Int(_, value), ValueType::Decimal) => Value::Decimal(
+            (value * decimal_scale())
+                .to_i128()
+                .ok_or(Abort::InvalidCast)?,
+        ),
+        (Value::Bool(value), _) if is_int(ty) => Value::Int(ty.clone(), BigInt::from(value as u8)),
+        (Value::F32(value), _) => cast_float(value as f64, ty)?,
+        (Value::F64(value), _) => cast_float(value, ty)?,
+        (Value::Decimal(value), ValueType::Decimal) => Value::Decimal(value),
+        (Value::Decimal(value), ValueType::F32) => Value::F32((value as f64 / 1e18) as f32),
+        (Value::Decimal(value), ValueType::F64) => Value::F64(value as f64 / 1e18),
+        (Value::Decimal(value), _) if is_int(ty) => {
+            int(ty.clone(), BigInt::from(value) / decimal_scale())
+                .map_err(|_| Abort::InvalidCast)?
+        }
+
+        // Arrays of `u8` to the types represented by bytes
+        (value @ Value::Array(_), ValueType::Address) => Value::Address(bytes(value)?),
+        (value @ Value::Array(_), ValueType::Asset) => Value::Asset(bytes(value)?),
+        (value @ Value::Array(_), ValueType::PublicKey) => {
+            Value::PublicKey(bytes(value)?.try_into().map_err(|_| Abort::InvalidCast)?)
+        }
+        (value @ Value::Array(_), ValueType::Hash) => {
+            Value::Hash(bytes(value)?.try_into().map_err(|_| Abort::InvalidCast)?)
+        }
+        (Value::Address(bytes) | Value::Asset(bytes), _) if is_array(ty) => {
+            cast(Value::bytes(&bytes), ty)?
+        }
+        (Value::PublicKey(bytes) | Value::Hash(bytes), _) if is_array(ty) => {
+            cast(Value::bytes(&bytes), ty)?
+        }
+
+        (Value::Array(elements), ValueType::Array(element)) => Value::Array(
+            elements
+                .into_iter()
+                .map(|value| cast(value, element))
+                .collect::<Result<_, _>>()?,
+        ),
+        (Value::Array(elements), ValueType::FixedArray(element, len)) => {
+            if elements.len() != *len as usize {
+                return Err(Abort::InvalidCast);
+            }
+            cast(Value::Array(elements), &ValueType::Array(element.clone()))?
+        }
+        (value, _) if value.ty() == *ty => value,
+        _ => return Err(Abort::InvalidCast),
+    };
+    Ok(value)
+}
+
+/// Converts a float to `ty`. Conversions to integers and decimals round
+/// towards zero.
+fn cast_float(value: f64, ty: &ValueType) -> Result<Value, Abort> {
+    let value = match ty {
+        ValueType::F32 => Value::F32(value as f32),
+        ValueType::F64 => Value::F64(value),
+        ValueType::Decimal => Value::Decimal(
+            BigInt::from_f64((value * 1e18).trunc())
+                .and_then(|value| value.to_i128())
+                .ok_or(Abort::InvalidCast)?,
+        ),
+        _ if is_int(ty) => {
+            let value = BigInt::from_f64(value.trunc()).ok_or(Abort::InvalidCast)?;
+            int(ty.clone(), value).map_err(|_| Abort::InvalidCast)?
+        }
+        _ => return Err(Abort::InvalidCast),
+    };
+    Ok(value)
+}
+
+fn is_array(ty: &ValueType) -> bool {
+    matches!(ty, ValueType::Array(_) | ValueType::FixedArray(_, _))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        run, run_test, Abort, Context, Input, Outcome, Output, PushedOutput, Revert, TestResult,
+        Value, VmErr, DEFAULT_GAS_LIMIT, MAX_BIG_BITS,
+    };
+    use crate::ast::ValueType;
+    use crate::bytecode::{encode_type, Artifact, DecodeErr, OpCode};
+    use crate::compiler::Compiler;
+    use crate::lexer::tokenise;
+    use crate::parser::parse;
+    use ed25519_dalek::{Signer, SigningKey};
+    use num_bigint::BigInt;
+    use num_traits::One;
+
+    fn compile_str(input: &str) -> Compiler {
+        let program = parse(tokenise(input).collect::<Result<_, _>>().unwrap()).unwrap();
+        let mut compiler = Compiler::new();
+        for function in program.functions.iter() {
+            compiler.declare_function(function).unwrap();
+        }
+        for function in program.functions.iter() {
+            compiler.push_function(function).unwrap();
+        }
+        compiler
+    }
+
+    fn run_str(input: &str, ctx: &Context, args: Vec<Value>) -> Outcome {
+        run(&compile_str(input).compile(), ctx, args, DEFAULT_GAS_LIMIT).unwrap()
+    }
+
+    fn int(ty: ValueType, value: i128) -> Value {
+        Value::Int(ty, BigInt::from(value))
+    }
+
+    fn returned(value: Value) -> Outcome {
+        Outcome::Success {
+            ret: Some(value),
+            outputs: vec![],
+        }
+    }
+
+    #[test]
+    fn test_run_loops_and_calls() {
+        let input = "
+            function square(a: u64) -> u64 { return a * a; }
+            function main(n: u64) {
+                let sum: u64 = 0;
+                for (let i = 0; i < n; i++) {
+                    if (i == 2) { continue; }
+                    sum += square(i);
+                }
+                return sum;
+            }";
+        assert_eq!(
+            run_str(input, &Context::default(), vec![int(ValueType::U64, 4)]),
+            returned(int(ValueType::U64, 10))
+        );
+    }
+
+    #[test]
+    fn test_run_checks_arithmetic() {
+        let input = "function main(a: u8, b: u8) { return a / b + 1; }";
+        let ctx = Context::default();
+        let args = |a, b| vec![int(ValueType::U8, a), int(ValueType::U8, b)];
+        assert_eq!(
+            run_str(input, &ctx, args(254, 1)),
+            returned(int(ValueType::U8, 255))
+        );
+        assert_eq!(
+            run_str(input, &ctx, args(255, 1)),
+            Outcome::Abort(Abort::Overflow)
+        );
+        assert_eq!(
+            run_str(input, &ctx, args(1, 0)),
+            Outcome::Abort(Abort::DivisionByZero)
+        );
+
+        let input = "function main(a: i8) { return -a << 1; }";
+        assert_eq!(
+            run_str(input, &ctx, vec![int(ValueType::I8, -96)]),
+            returned(int(ValueType::I8, -64))
+        );
+        assert_eq!(
+            run_str(input, &ctx, vec![int(ValueType::I8, -128)]),
+            Outcome::Abort(Abort::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_run_decimals_and_casts() {
+        let input =
+            "function main(a: i128, rate: decimal) { return (a as decimal * rate) as i128; }";
+        let args = vec![
+            int(ValueType::I128, 7),
+            Value::Decimal(1_500_000_000_000_000_000),
+        ];
+        assert_eq!(
+            run_str(input, &Context::default(), args),
+            returned(int(ValueType::I128, 10))
+        );
+
+        let input = "function main(a) { return a as u8; }";
+        assert_eq!(
+            run_str(input, &Context::default(), vec![int(ValueType::I64, 255)]),
+            returned(int(ValueType::U8, 255))
+        );
+        assert_eq!(
+            run_str(input, &Context::default(), vec![int(ValueType::I64, -1)]),
+            Outcome::Abort(Abort::InvalidCast)
+        );
+        assert_eq!(
+            run_str(input, &Context::default(), vec![Value::Bool(true)]),
+            returned(int(ValueType::U8, 1))
+        );
+    }
+
+    #[test]
+    fn test_run_revert() {
+        let input = "function main(a: i64) { if (a > 1) { revert(\"too big\"); } }";
+        assert_eq!(
+            run_str(input, &Context::default(), vec![int(ValueType::I64, 2)]),
+            Outcome::Revert(Revert {
+                code: 1,
+                reason: Some("too big".to_owned())
+            })
+        );
+        assert_eq!(
+            run_str(input, &Context::default(), vec![int(ValueType::I64, 1)]),
+            Outcome::Success {
+                ret: None,
+                outputs: vec![]
+            }
+        );
+
+        // Scripts without their reasons only tell the code
+        let program = parse(tokenise(input).collect::<Result<_, _>>().unwrap()).unwrap();
+        let mut compiler = Compiler::new();
+        compiler.set_strip_reasons(true);
+        compiler.push_function(&program.functions[0]).unwrap();
+        let outcome = run(
+            &compiler.compile(),
+            &Context::default(),
+            vec![int(ValueType::I64, 2)],
+            DEFAULT_GAS_LIMIT,
+        );
+        let revert = Revert {
+            code: 1,
+            reason: None,
+        };
+        assert_eq!(revert.to_string(), "reason 1");
+        assert_eq!(outcome, Ok(Outcome::Revert(revert)));
+    }
+
+    #[test]
+    fn test_run_arrays() {
+        let input = "
+            function main(a: [u8]) {
+                let front = a.takeFront(2);
+                return front + a[1..] + x\"ff\";
+            }";
+        assert_eq!(
+            run_str(
+                input,
+                &Context::default(),
+                vec![Value::bytes(&[1, 2, 3, 4])]
+            ),
+            returned(Value::bytes(&[1, 2, 4, 0xff]))
+        );
+        assert_eq!(
+            run_str(input, &Context::default(), vec![Value::bytes(&[1])]),
+            Outcome::Abort(Abort::IndexOutOfBounds)
+        );
+
+        let input = "function main(a: address) { return (a as [u8])[0] == 7 && a as [u8; 2] == x\"0708\"; }";
+        assert_eq!(
+            run_str(input, &Context::default(), vec![Value::Address(vec![7, 8])]),
+            returned(Value::Bool(true))
+        );
+        assert_eq!(
+            run_str(input, &Context::default(), vec![Value::Address(vec![7])]),
+            Outcome::Abort(Abort::InvalidCast)
+        );
+    }
+
+    #[test]
+    fn test_run_push_out() {
+        let ctx = Context {
+            this: Output {
+                colour_hash: vec![9; 32],
+                ..Output::default()
+            },
+            ..Context::default()
+        };
+        let args = vec![
+            int(ValueType::I64, 50),
+            Value::Address(vec![1; 20]),
+            Value::Address(vec![2; 20]),
+        ];
+        assert_eq!(
+            run_str(include_str!("../examples/simple_spend/main.ps"), &ctx, args),
+            Outcome::Success {
+                ret: Some(int(ValueType::U32, 0)),
+                outputs: vec![PushedOutput {
+                    amount: 50,
+                    colour_hash: vec![9; 32],
+                    coloured_address: vec![1; 20],
+                    script_hash: vec![2; 20],
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn test_run_verify_ed25519() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let signature = key.sign(b"prefixmessage").to_bytes();
+        let input = "function main(key: PublicKey, sig: [u8]) { XPU.verifyEd25519(this.inputBytes, b\"message\", key, sig); }";
+        let ctx = Context {
+            input_bytes: b"prefix".to_vec(),
+            ..Context::default()
+        };
+        let args = |signature: &[u8]| {
+            vec![
+                Value::PublicKey(key.verifying_key().to_bytes()),
+                Value::bytes(signature),
+            ]
+        };
+        assert_eq!(
+            run_str(input, &ctx, args(&signature)),
+            Outcome::Success {
+                ret: None,
+                outputs: vec![]
+            }
+        );
+        assert_eq!(
+            run_str(input, &ctx, args(&signature[1..])),
+            Outcome::Abort(Abort::InvalidSignature)
+        );
+
+        let mut tampered = signature;
+        tampered[0] ^= 1;
+        assert_eq!(
+            run_str(input, &ctx, args(&tampered)),
+            Outcome::Abort(Abort::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_run_limit_order() {
+        let taker = SigningKey::from_bytes(&[7; 32]);
+        let out_address = vec![3; 20];
+        let out_script_hash = vec![4; 20];
+        let signed = [
+            b"input".to_vec(),
+            out_address.clone(),
+            out_script_hash.clone(),
+        ]
+        .concat();
+
+        let this = Output {
+            amount: 500,
+            colour_hash: vec![9; 32],
+            coloured_address: vec![5; 20],
+            script_hash: vec![6; 20],
+            hash: [0xaa; 32],
+            input: Input::default(),
+        };
+        let payment = Output {
+            amount: 100,
+            colour_hash: vec![2; 32],
+            coloured_address: vec![1; 20],
+            hash: [0xbb; 32],
+            input: Input {
+                public_key: taker.verifying_key().to_bytes(),
+                output_hash: [0xcc; 32],
+            },
+            ..Output::default()
+        };
+        let mut ctx = Context {
+            block_timestamp: 50,
+            outputs: vec![this.clone(), payment],
+            this: this.clone(),
+            input_bytes: b"input".to_vec(),
+        };
+        let args = vec![
+            Value::Decimal(2_000_000_000_000_000_000),
+            int(ValueType::I128, 1000),
+            int(ValueType::I128, 10),
+            int(ValueType::I32, 100),
+            Value::Address(vec![1; 20]),
+            Value::Asset(vec![2; 32]),
+            Value::Address(out_address.clone()),
+            Value::Address(out_script_hash.clone()),
+            Value::bytes(&taker.sign(&signed).to_bytes()),
+        ];
+        let input = include_str!("../examples/limit_order/main.ps");
+
+        assert_eq!(
+            run_str(input, &ctx, args.clone()),
+            Outcome::Success {
+                ret: Some(int(ValueType::U32, 1)),
+                outputs: vec![
+                    PushedOutput {
+                        amount: 200,
+                        colour_hash: vec![9; 32],
+                        coloured_address: out_address,
+                        script_hash: out_script_hash,
+                    },
+                    PushedOutput {
+                        amount: 500,
+                        colour_hash: vec![9; 32],
+                        coloured_address: vec![5; 20],
+                        script_hash: vec![6; 20],
+                    },
+                ],
+            }
+        );
+
+        ctx.block_timestamp = 101;
+        assert_eq!(
+            run_str(input, &ctx, args),
+            Outcome::Revert(Revert {
+                code: 1,
+                reason: Some("deadline passed".to_owned())
+            })
+        );
+    }
+
+    #[test]
+    fn test_run_out_of_gas() {
+        let ctx = Context::default();
+        let script = compile_str("function main() { while (true) {} }").compile();
+        assert_eq!(
+            run(&script, &ctx, vec![], 1000),
+            Ok(Outcome::Abort(Abort::OutOfGas))
+        );
+
+        // `XPU.blockTimestamp` costs 2 gas and returning its value 1 gas
+        let script = compile_str("function main() { return XPU.blockTimestamp; }").compile();
+        assert_eq!(
+            run(&script, &ctx, vec![], 3),
+            Ok(returned(int(ValueType::I64, 0)))
+        );
+        assert_eq!(
+            run(&script, &ctx, vec![], 2),
+            Ok(Outcome::Abort(Abort::OutOfGas))
+        );
+
+        let (script, tests) =
+            compile_str("function main() { while (true) {} } test function loops() { main(); }")
+                .compile_tests();
+        assert_eq!(
+            run_test(&script, tests[0].func, 1000),
+            Ok(TestResult::Failed("aborted: out of gas".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_run_bounds_allocations() {
+        let ctx = Context::default();
+        let input = "function main(a: ubig, n: u32) { return a << n; }";
+        let args = |n| vec![int(ValueType::UBIG, 1), int(ValueType::U32, n)];
+        assert_eq!(
+            run_str(input, &ctx, args(MAX_BIG_BITS as i128 - 1)),
+            returned(Value::Int(
+                ValueType::UBIG,
+                BigInt::one() << (MAX_BIG_BITS - 1)
+            ))
+        );
+        assert_eq!(
+            run_str(input, &ctx, args(4_000_000_000)),
+            Outcome::Abort(Abort::Overflow)
+        );
+
+        // Concatenations pay for the elements they create
+        let script = compile_str(
+            "function main() {
+                let a: [u8] = x\"00\";
+                for (let i = 0; i < 28; i++) { a = a + a; }
+                return a.len();
+            }",
+        )
+        .compile();
+        assert_eq!(
+            run(&script, &ctx, vec![], 1000),
+            Ok(Outcome::Abort(Abort::OutOfGas))
+        );
+        assert_eq!(
+            run(&script, &ctx, vec![], DEFAULT_GAS_LIMIT),
+            Ok(Outcome::Abort(Abort::ArrayTooLong))
+        );
+
+        // Constants are checked against the bytes and the gas left before
+        // they are decoded
+        let script = |ty: ValueType| {
+            let mut main = vec![OpCode::PushConst as u8];
+            encode_type(&ty, &mut main);
+            Artifact {
+                main,
+                ..Artifact::default()
+            }
+            .encode()
+            .unwrap()
+        };
+        let array = |element, len| ValueType::FixedArray(Box::new(element), len);
+        let empty = || array(ValueType::U8, 0);
+        assert_eq!(
+            run(&script(array(empty(), u32::MAX)), &ctx, vec![], 1000),
+            Ok(Outcome::Abort(Abort::ArrayTooLong))
+        );
+        assert_eq!(
+            run(&script(array(empty(), 60_000)), &ctx, vec![], 1000),
+            Ok(Outcome::Abort(Abort::OutOfGas))
+        );
+        assert_eq!(
+            run(
+                &script(array(ValueType::U8, 60_000)),
+                &ctx,
+                vec![],
+                DEFAULT_GAS_LIMIT
+            ),
+            Err(VmErr::InvalidConstant(0))
+        );
+    }
+
+    #[test]
+    fn test_run_malformed_bytecode() {
+        let ctx = Context::default();
+        let script = |main| {
+            Artifact {
+                main,
+                ..Artifact::default()
+            }
+            .encode()
+            .unwrap()
+        };
+        assert_eq!(
+            run(
+                &script(vec![OpCode::Pop as u8]),
+                &ctx,
+                vec![],
+                DEFAULT_GAS_LIMIT
+            ),
+            Err(VmErr::StackUnderflow(0))
+        );
+        assert_eq!(
+            run(
+                &script(vec![OpCode::Load as u8, 0x00, 0x00, 0x25]),
+                &ctx,
+                vec![Value::Bool(true)],
+                DEFAULT_GAS_LIMIT
+            ),
+            Err(VmErr::InvalidOpCode(0x25, 3))
+        );
+        assert_eq!(
+            run(
+                &script(vec![OpCode::Jump as u8, 0xff, 0x00, 0x00, 0x00]),
+                &ctx,
+                vec![],
+                DEFAULT_GAS_LIMIT
+            ),
+            Err(VmErr::Decode(DecodeErr::UnexpectedEnd(0xff)))
+        );
+        let mut cast = vec![OpCode::PushConst as u8, 0x01, 0x00, OpCode::Cast as u8];
+        cast.extend_from_slice(&[0x12; 100_000]);
+        assert_eq!(
+            run(&script(cast), &ctx, vec![], DEFAULT_GAS_LIMIT),
+            Err(VmErr::Decode(DecodeErr::TypeTooDeep(36)))
+        );
+        assert_eq!(
+            run(
+                &script(vec![OpCode::Call as u8, 0x01, 0x00, 0x00]),
+                &ctx,
+                vec![],
+                DEFAULT_GAS_LIMIT
+            ),
+            Err(VmErr::UnknownFunction(1, 0))
+        );
+
+        // Intrinsics of `Test` and calls of main only run in tests
+        assert_eq!(
+            run(
+                &script(vec![OpCode::PushedLen as u8]),
+                &ctx,
+                vec![],
+                DEFAULT_GAS_LIMIT
+            ),
+            Err(VmErr::InvalidOpCode(0x69, 0))
+        );
+        assert_eq!(
+            run(
+                &script(vec![OpCode::Call as u8, 0x00, 0x00, 0x00]),
+                &ctx,
+                vec![],
+                DEFAULT_GAS_LIMIT
+            ),
+            Err(VmErr::UnknownFunction(0, 0))
+        );
+    }
+
+    #[test]
+    fn test_run_tests() {
+        let input = r#"
+            function main(deadline: i64, amount: i128) {
+                if (XPU.blockTimestamp > deadline) {
+                    revert("deadline passed");
+                }
+                XPU.pushOut(amount, this.colouredAddress, this.scriptHash);
+                return 1;
+            }
+
+            test function pushes_output() {
+                Test.setThis(Test.output(500, x"09" as asset, x"05" as address, x"06" as address));
+                Test.setBlockTimestamp(10);
+                main(20, 5);
+                let pushed = Test.pushedAt(0);
+                if (Test.pushedLen() != 1 || pushed.amount != 5 || pushed.colourHash != x"09" as asset) {
+                    revert("wrong output");
+                }
+            }
+
+            test function expects_revert() {
+                Test.setBlockTimestamp(30);
+                Test.expectRevert("deadline passed");
+                main(20, 5);
+            }
+
+            test function expects_other_revert() {
+                Test.setBlockTimestamp(30);
+                Test.expectRevert("not enough amount");
+                main(20, 5);
+            }
+
+            test function expects_missing_revert() {
+                Test.expectRevert("deadline passed");
+                main(20, 5);
+            }
+
+            test function unexpected_revert() {
+                Test.setBlockTimestamp(30);
+                main(20, 5);
+            }
+
+            test function fails() {
+                main(20, 5);
+                if (Test.pushedLen() != 2) {
+                    revert("expected two outputs");
+                }
+            }
+
+            test function aborts() {
+                Test.pushedAt(0);
+            }
+
+            test function signs() {
+                let secret = x"0707070707070707070707070707070707070707070707070707070707070707";
+                let key = Test.publicKey(secret);
+                let output = Test.output(1, x"" as asset, x"" as address, x"" as address, key);
+                Test.addOutput(output);
+                if (XPU.getOutputAt(0).input.publicKey != key || XPU.getOutputAt(0) != output) {
+                    revert("wrong output");
+                }
+                XPU.verifyEd25519(b"", b"message", key, Test.sign(secret, b"message"));
+            }
+        "#;
+        let program = parse(tokenise(input).collect::<Result<_, _>>().unwrap()).unwrap();
+        let mut compiler = Compiler::new();
+        for function in program.functions.iter() {
+            compiler.declare_function(function).unwrap();
+        }
+        for function in program.functions.iter() {
+            compiler.push_function(function).unwrap();
+        }
+
+        let (script, tests) = compiler.compile_tests();
+        let results: Vec<_> = tests
+            .iter()
+            .map(|test| {
+                (
+                    test.name.as_str(),
+                    run_test(&script, test.func, DEFAULT_GAS_LIMIT).unwrap(),
+                )
+            })
+            .collect();
+        let failed = |reason: &str| TestResult::Failed(reason.to_owned());
+        assert_eq!(
+            results,
+            vec![
+                ("pushes_output", TestResult::Passed),
+                ("expects_revert", TestResult::Passed),
+                (
+                    "expects_other_revert",
+                    failed(
+                        "main reverted with \"deadline passed\" instead of \"not enough amount\""
+                    )
+                ),
+                (
+                    "expects_missing_revert",
+                    failed("main did not revert with \"deadline passed\"")
+                ),
+                (
+                    "unexpected_revert",
+                    failed("main reverted with \"deadline passed\"")
+                ),
+                ("fails", failed("reverted with \"expected two outputs\"")),
+                ("aborts", failed("aborted: index out of bounds")),
+                ("signs", TestResult::Passed),
+            ]
+        );
+
+        // The compiled script does not contain the tests
+        let artifact = Artifact::decode(&compiler.compile()).unwrap();
+        assert!(artifact.funcs.is_empty());
+        assert_eq!(
+            run_test(&compiler.compile(), 1, DEFAULT_GAS_LIMIT),
+            Err(VmErr::UnknownFunction(1, 0))
+        );
+    }
+}